@@ -1,6 +1,638 @@
 #![no_std]
 
-pub enum RateLimitSetting {
+pub const IPPROTO_ICMP: u32 = 1;
+pub const IPPROTO_TCP: u32 = 6;
+pub const IPPROTO_UDP: u32 = 17;
+
+const SECOND: u64 = 1_000_000_000;
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Counter {
+    Pass,
+    Blacklist,
+    RateLimit,
+    Default,
+    SynCookie,
+    SynCookieValid,
+    SynCookieInvalid,
+    Geo,
+    Asn,
+    Bogon,
+    BadVersion,
+    BadIhl,
+    Truncated,
+    IpOptions,
+    SourceRoute,
+    ZeroTtl,
+    Land,
+    BadFragment,
+    Fragment,
+    TinyFragment,
+    FragmentUntracked,
+    NullScan,
+    XmasScan,
+    FinScan,
+    SynFin,
+    SynRst,
+    SynPayload,
+    BadDataOffset,
+    PortZero,
+    Scan,
+    Trap,
+    ScanDetected,
+    SynOptions,
+}
+
+impl Counter {
+    pub const ALL: [Self; 33] = [
+        Self::Pass,
+        Self::Blacklist,
+        Self::RateLimit,
+        Self::Default,
+        Self::SynCookie,
+        Self::SynCookieValid,
+        Self::SynCookieInvalid,
+        Self::Geo,
+        Self::Asn,
+        Self::Bogon,
+        Self::BadVersion,
+        Self::BadIhl,
+        Self::Truncated,
+        Self::IpOptions,
+        Self::SourceRoute,
+        Self::ZeroTtl,
+        Self::Land,
+        Self::BadFragment,
+        Self::Fragment,
+        Self::TinyFragment,
+        Self::FragmentUntracked,
+        Self::NullScan,
+        Self::XmasScan,
+        Self::FinScan,
+        Self::SynFin,
+        Self::SynRst,
+        Self::SynPayload,
+        Self::BadDataOffset,
+        Self::PortZero,
+        Self::Scan,
+        Self::Trap,
+        Self::ScanDetected,
+        Self::SynOptions,
+    ];
+    pub const LEN: u32 = Self::ALL.len() as u32;
+
+    pub fn is_drop(self) -> bool {
+        matches!(
+            self,
+            Self::Blacklist
+                | Self::RateLimit
+                | Self::Default
+                | Self::SynCookieInvalid
+                | Self::Geo
+                | Self::Asn
+                | Self::Bogon
+                | Self::BadVersion
+                | Self::BadIhl
+                | Self::Truncated
+                | Self::IpOptions
+                | Self::SourceRoute
+                | Self::ZeroTtl
+                | Self::Land
+                | Self::BadFragment
+                | Self::Fragment
+                | Self::TinyFragment
+                | Self::FragmentUntracked
+                | Self::NullScan
+                | Self::XmasScan
+                | Self::FinScan
+                | Self::SynFin
+                | Self::SynRst
+                | Self::SynPayload
+                | Self::BadDataOffset
+                | Self::PortZero
+                | Self::Scan
+                | Self::Trap
+                | Self::SynOptions
+        )
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Blacklist => "blacklist",
+            Self::RateLimit => "rate_limit",
+            Self::Default => "default",
+            Self::SynCookie => "syn_cookie",
+            Self::SynCookieValid => "syn_cookie_valid",
+            Self::SynCookieInvalid => "syn_cookie_invalid",
+            Self::Geo => "geo",
+            Self::Asn => "asn",
+            Self::Bogon => "bogon",
+            Self::BadVersion => "bad_version",
+            Self::BadIhl => "bad_ihl",
+            Self::Truncated => "truncated",
+            Self::IpOptions => "ip_options",
+            Self::SourceRoute => "source_route",
+            Self::ZeroTtl => "zero_ttl",
+            Self::Land => "land",
+            Self::BadFragment => "bad_fragment",
+            Self::Fragment => "fragment",
+            Self::TinyFragment => "tiny_fragment",
+            Self::FragmentUntracked => "fragment_untracked",
+            Self::NullScan => "null_scan",
+            Self::XmasScan => "xmas_scan",
+            Self::FinScan => "fin_scan",
+            Self::SynFin => "syn_fin",
+            Self::SynRst => "syn_rst",
+            Self::SynPayload => "syn_payload",
+            Self::BadDataOffset => "bad_data_offset",
+            Self::PortZero => "port_zero",
+            Self::Scan => "scan",
+            Self::Trap => "trap",
+            Self::ScanDetected => "scan_detected",
+            Self::SynOptions => "syn_options",
+        }
+    }
+}
+
+impl TryFrom<u32> for Counter {
+    type Error = u32;
+
+    fn try_from(counter: u32) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|&candidate| candidate as u32 == counter)
+            .ok_or(counter)
+    }
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Check {
+    Management,
+    Sanity,
+    Fragment,
+    Whitelist,
+    Blacklist,
+    Bogon,
+    Trap,
+    TcpAnomaly,
+    Geo,
+    Asn,
+    Scan,
+    SynCookie,
+    Established,
+    RateLimit,
+    Default,
+}
+
+impl Check {
+    pub const ALL: [Self; 15] = [
+        Self::Management,
+        Self::Sanity,
+        Self::Fragment,
+        Self::Whitelist,
+        Self::Blacklist,
+        Self::Bogon,
+        Self::Trap,
+        Self::TcpAnomaly,
+        Self::Geo,
+        Self::Asn,
+        Self::Scan,
+        Self::SynCookie,
+        Self::Established,
+        Self::RateLimit,
+        Self::Default,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Management => "management",
+            Self::Sanity => "sanity",
+            Self::Fragment => "fragment",
+            Self::Whitelist => "whitelist",
+            Self::Blacklist => "blacklist",
+            Self::Bogon => "bogon",
+            Self::Trap => "trap",
+            Self::TcpAnomaly => "tcp_anomaly",
+            Self::Geo => "geo",
+            Self::Asn => "asn",
+            Self::Scan => "scan",
+            Self::SynCookie => "syn_cookie",
+            Self::Established => "established",
+            Self::RateLimit => "rate_limit",
+            Self::Default => "default",
+        }
+    }
+}
+
+impl TryFrom<u32> for Check {
+    type Error = u32;
+
+    fn try_from(check: u32) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|&candidate| candidate as u32 == check)
+            .ok_or(check)
+    }
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DefaultAction {
+    Pass,
+    Drop,
+}
+
+impl DefaultAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Drop => "drop",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    pub addr: u32,
+    pub country: u32,
+    pub banned: u32,
+    pub value: u64,
+    pub ttl: u64,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    RateLimit,
+    Asn,
+    Scan,
+    Trap,
+}
+
+impl EventKind {
+    pub const ALL: [Self; 4] = [Self::RateLimit, Self::Asn, Self::Scan, Self::Trap];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate_limit",
+            Self::Asn => "asn",
+            Self::Scan => "scan",
+            Self::Trap => "trap",
+        }
+    }
+}
+
+impl TryFrom<u32> for EventKind {
+    type Error = u32;
+
+    fn try_from(kind: u32) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|&event_kind| event_kind as u32 == kind)
+            .ok_or(kind)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Flow {
+    pub state: FlowState,
+    pub packets: u32,
+    pub last_seen: u64,
+}
+
+impl Flow {
+    pub fn expired(&self, protocol: u32, now: u64) -> bool {
+        now.saturating_sub(self.last_seen) > self.state.timeout(protocol)
+    }
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for Flow {}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlowKey {
+    pub remote: u32,
+    pub local: u32,
+    pub remote_port: u16,
+    pub local_port: u16,
+    pub protocol: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FlowKey {}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowState {
+    SynSent,
+    Established,
+    Closing,
+}
+
+impl FlowState {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SynSent => "syn_sent",
+            Self::Established => "established",
+            Self::Closing => "closing",
+        }
+    }
+
+    pub fn timeout(self, protocol: u32) -> u64 {
+        match (protocol, self) {
+            (IPPROTO_TCP, Self::SynSent) => 60 * SECOND,
+            (IPPROTO_TCP, Self::Established) => 3600 * SECOND,
+            (IPPROTO_TCP, Self::Closing) => 10 * SECOND,
+            (IPPROTO_UDP, _) => 120 * SECOND,
+            _ => 30 * SECOND,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FragmentKey {
+    pub src: u32,
+    pub dst: u32,
+    pub id: u32,
+    pub protocol: u32,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FragmentMode {
+    Pass,
+    Drop,
+    Track,
+}
+
+impl FragmentMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Drop => "drop",
+            Self::Track => "track",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FragmentVerdict {
+    pub counter: Counter,
+    pub seen: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeoKey {
+    pub generation: u32,
+    pub country: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for GeoKey {}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeoRule {
+    Block,
+    Allow,
+}
+
+impl GeoRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Allow => "allow",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ListKey {
+    pub generation: u32,
+    pub addr: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ListKey {}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PortKey {
+    pub generation: u32,
+    pub port: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PortKey {}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitWindow {
+    pub window_start: u64,
+    pub packet_count: u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for RateLimitWindow {}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sanity {
+    BadVersion,
+    BadIhl,
+    Truncated,
+    IpOptions,
+    SourceRoute,
+    ZeroTtl,
+    Land,
+    BadFragment,
+}
+
+impl Sanity {
+    pub const ALL: [Self; 8] = [
+        Self::BadVersion,
+        Self::BadIhl,
+        Self::Truncated,
+        Self::IpOptions,
+        Self::SourceRoute,
+        Self::ZeroTtl,
+        Self::Land,
+        Self::BadFragment,
+    ];
+
+    pub fn bit(self) -> u64 {
+        1 << self as u32
+    }
+
+    pub fn counter(self) -> Counter {
+        match self {
+            Self::BadVersion => Counter::BadVersion,
+            Self::BadIhl => Counter::BadIhl,
+            Self::Truncated => Counter::Truncated,
+            Self::IpOptions => Counter::IpOptions,
+            Self::SourceRoute => Counter::SourceRoute,
+            Self::ZeroTtl => Counter::ZeroTtl,
+            Self::Land => Counter::Land,
+            Self::BadFragment => Counter::BadFragment,
+        }
+    }
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScanTrack {
+    Ports,
+    Hosts,
+}
+
+impl ScanTrack {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ports => "ports",
+            Self::Hosts => "hosts",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanWindow {
+    pub window_start: u64,
+    pub bitmap: [u64; 4],
+    pub count: u64,
+}
+
+#[derive(Clone, Copy)]
+pub enum Setting {
+    AllowEstablished,
+    SynThreshold,
+    GeoAllowOnly,
+    Bogon,
+    Sanity,
+    FragmentMode,
+    FragmentDropTiny,
+    TcpAnomalyDrop,
+    TcpAnomalyLog,
+    ScanThreshold,
+    ScanWindow,
+    ScanTrack,
+    ScanBanTtl,
+    TrapBanTtl,
+    DefaultAction,
     PacketLimit,
     WindowSize,
 }
+
+impl Setting {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AllowEstablished => "allow_established",
+            Self::SynThreshold => "syn_threshold",
+            Self::GeoAllowOnly => "geo_allow_only",
+            Self::Bogon => "bogon",
+            Self::Sanity => "sanity",
+            Self::FragmentMode => "fragment_mode",
+            Self::FragmentDropTiny => "fragment_drop_tiny",
+            Self::TcpAnomalyDrop => "tcp_anomaly_drop",
+            Self::TcpAnomalyLog => "tcp_anomaly_log",
+            Self::ScanThreshold => "scan_threshold",
+            Self::ScanWindow => "scan_window",
+            Self::ScanTrack => "scan_track",
+            Self::ScanBanTtl => "scan_ban_ttl",
+            Self::TrapBanTtl => "trap_ban_ttl",
+            Self::DefaultAction => "default_action",
+            Self::PacketLimit => "packet_limit",
+            Self::WindowSize => "window_size",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SettingKey {
+    pub generation: u32,
+    pub setting: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for SettingKey {}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceStat {
+    pub packets: u64,
+    pub bytes: u64,
+    pub drops: u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for SourceStat {}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TcpAnomaly {
+    NullScan,
+    XmasScan,
+    FinScan,
+    SynFin,
+    SynRst,
+    SynPayload,
+    BadDataOffset,
+    PortZero,
+}
+
+impl TcpAnomaly {
+    pub const ALL: [Self; 8] = [
+        Self::NullScan,
+        Self::XmasScan,
+        Self::FinScan,
+        Self::SynFin,
+        Self::SynRst,
+        Self::SynPayload,
+        Self::BadDataOffset,
+        Self::PortZero,
+    ];
+
+    pub fn bit(self) -> u64 {
+        1 << self as u32
+    }
+
+    pub fn counter(self) -> Counter {
+        match self {
+            Self::NullScan => Counter::NullScan,
+            Self::XmasScan => Counter::XmasScan,
+            Self::FinScan => Counter::FinScan,
+            Self::SynFin => Counter::SynFin,
+            Self::SynRst => Counter::SynRst,
+            Self::SynPayload => Counter::SynPayload,
+            Self::BadDataOffset => Counter::BadDataOffset,
+            Self::PortZero => Counter::PortZero,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Trace {
+    pub src: u32,
+    pub dst: u32,
+    pub len: u32,
+    pub protocol: u32,
+    pub sport: u16,
+    pub dport: u16,
+    pub checks: u32,
+    pub check: u32,
+    pub counter: u32,
+    pub action: u32,
+}
@@ -0,0 +1,50 @@
+use aya_ebpf::{
+    bindings::BPF_F_NO_PREALLOC,
+    helpers::r#gen::bpf_ktime_get_ns,
+    macros::map,
+    maps::{LpmTrie, LruHashMap, lpm_trie::Key},
+};
+use common::EventKind;
+
+use crate::xdp::event;
+
+const EVENT_INTERVAL: u64 = 60 * 1_000_000_000;
+
+#[map]
+static ASN: LpmTrie<[u8; 8], u32> = LpmTrie::pinned(1 << 18, BPF_F_NO_PREALLOC);
+
+#[map]
+static ASN_EVENTS: LruHashMap<u32, u64> = LruHashMap::pinned(1024, 0);
+
+pub fn asn(generation: u32, addr: u32) -> bool {
+    let mut data = [0; 8];
+
+    data[..4].copy_from_slice(&generation.to_be_bytes());
+    data[4..].copy_from_slice(&addr.to_be_bytes());
+
+    let Some(&asn) = ASN.get(&Key::new(64, data)) else {
+        return false;
+    };
+    let now = unsafe { bpf_ktime_get_ns() };
+    let due = match ASN_EVENTS.get_ptr_mut(&addr) {
+        Some(last) => unsafe {
+            let due = now - *last > EVENT_INTERVAL;
+
+            if due {
+                *last = now;
+            }
+
+            due
+        },
+        None => {
+            ASN_EVENTS.insert(&addr, &now, 0).ok();
+            true
+        }
+    };
+
+    if due {
+        event(EventKind::Asn, addr, asn.into());
+    }
+
+    true
+}
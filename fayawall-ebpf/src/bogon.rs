@@ -0,0 +1,24 @@
+use aya_ebpf::{
+    bindings::BPF_F_NO_PREALLOC,
+    macros::map,
+    maps::{LpmTrie, lpm_trie::Key},
+};
+use common::Setting;
+
+use crate::xdp::setting;
+
+#[map]
+static BOGONS: LpmTrie<[u8; 8], u32> = LpmTrie::pinned(1 << 15, BPF_F_NO_PREALLOC);
+
+pub fn bogon(generation: u32, addr: u32) -> bool {
+    if setting(generation, Setting::Bogon) == 0 {
+        return false;
+    }
+
+    let mut data = [0; 8];
+
+    data[..4].copy_from_slice(&generation.to_be_bytes());
+    data[4..].copy_from_slice(&addr.to_be_bytes());
+
+    BOGONS.get(&Key::new(64, data)).is_some()
+}
@@ -0,0 +1,257 @@
+use aya_ebpf::{
+    helpers::r#gen::bpf_ktime_get_ns,
+    macros::map,
+    maps::LruHashMap,
+    programs::{TcContext, XdpContext},
+};
+use common::{Flow, FlowKey, FlowState, IPPROTO_ICMP, IPPROTO_TCP, IPPROTO_UDP};
+use network_types::{
+    eth::{EthHdr, EtherType},
+    ip::{IpProto, Ipv4Hdr},
+    tcp::TcpHdr,
+    udp::UdpHdr,
+};
+
+use crate::xdp::{Error, data_ptr};
+
+const ICMP_DEST_UNREACH: u8 = 3;
+const ICMP_ECHO: u8 = 8;
+const ICMP_ECHOREPLY: u8 = 0;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+const ICMP_HEADER_LEN: usize = 8;
+
+#[map]
+static CONNTRACK: LruHashMap<FlowKey, Flow> = LruHashMap::pinned(65536, 0);
+
+pub fn established(ctx: &XdpContext, ipv4_hdr: *const Ipv4Hdr) -> bool {
+    ingress(ctx, ipv4_hdr).unwrap_or(false)
+}
+
+fn ingress(ctx: &XdpContext, ipv4_hdr: *const Ipv4Hdr) -> Result<bool, Error> {
+    let now = unsafe { bpf_ktime_get_ns() };
+    let (remote, local, protocol, offset) = unsafe {
+        (
+            u32::from_be_bytes((*ipv4_hdr).src_addr),
+            u32::from_be_bytes((*ipv4_hdr).dst_addr),
+            (*ipv4_hdr).proto,
+            EthHdr::LEN + (*ipv4_hdr).ihl() as usize,
+        )
+    };
+
+    match protocol {
+        IpProto::Tcp => {
+            let tcp_hdr: *const TcpHdr = unsafe { data_ptr(ctx, offset)? };
+            let key = unsafe {
+                FlowKey {
+                    remote,
+                    local,
+                    remote_port: u16::from_be_bytes((*tcp_hdr).source),
+                    local_port: u16::from_be_bytes((*tcp_hdr).dest),
+                    protocol: IPPROTO_TCP,
+                }
+            };
+            let Some(flow) = lookup(&key, now) else {
+                return Ok(false);
+            };
+
+            unsafe {
+                if (*tcp_hdr).rst() != 0 || (*tcp_hdr).fin() != 0 {
+                    (*flow).state = FlowState::Closing;
+                } else if (*flow).state == FlowState::SynSent {
+                    if (*tcp_hdr).syn() == 0 || (*tcp_hdr).ack() == 0 {
+                        return Ok(false);
+                    }
+                    (*flow).state = FlowState::Established;
+                }
+                (*flow).packets = (*flow).packets.wrapping_add(1);
+                (*flow).last_seen = now;
+            }
+
+            Ok(true)
+        }
+        IpProto::Udp => {
+            let udp_hdr: *const UdpHdr = unsafe { data_ptr(ctx, offset)? };
+            let key = unsafe {
+                FlowKey {
+                    remote,
+                    local,
+                    remote_port: u16::from_be_bytes((*udp_hdr).src),
+                    local_port: u16::from_be_bytes((*udp_hdr).dst),
+                    protocol: IPPROTO_UDP,
+                }
+            };
+
+            Ok(refresh(&key, now))
+        }
+        IpProto::Icmp => {
+            let icmp_hdr: *const [u8; ICMP_HEADER_LEN] = unsafe { data_ptr(ctx, offset)? };
+            let icmp_hdr = unsafe { *icmp_hdr };
+
+            match icmp_hdr[0] {
+                ICMP_ECHOREPLY => Ok(refresh(
+                    &FlowKey {
+                        remote,
+                        local,
+                        remote_port: 0,
+                        local_port: u16::from_be_bytes([icmp_hdr[4], icmp_hdr[5]]),
+                        protocol: IPPROTO_ICMP,
+                    },
+                    now,
+                )),
+                ICMP_DEST_UNREACH | ICMP_TIME_EXCEEDED => {
+                    related(ctx, offset + ICMP_HEADER_LEN, now)
+                }
+                _ => Ok(false),
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+fn lookup(key: &FlowKey, now: u64) -> Option<*mut Flow> {
+    let flow = CONNTRACK.get_ptr_mut(key)?;
+
+    if unsafe { (*flow).expired(key.protocol, now) } {
+        None
+    } else {
+        Some(flow)
+    }
+}
+
+fn refresh(key: &FlowKey, now: u64) -> bool {
+    match lookup(key, now) {
+        Some(flow) => {
+            unsafe {
+                (*flow).packets = (*flow).packets.wrapping_add(1);
+                (*flow).last_seen = now;
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+fn related(ctx: &XdpContext, offset: usize, now: u64) -> Result<bool, Error> {
+    let inner: *const Ipv4Hdr = unsafe { data_ptr(ctx, offset)? };
+    let (remote, local, protocol, offset) = unsafe {
+        (
+            u32::from_be_bytes((*inner).dst_addr),
+            u32::from_be_bytes((*inner).src_addr),
+            (*inner).proto,
+            offset + (*inner).ihl() as usize,
+        )
+    };
+    let protocol = match protocol {
+        IpProto::Tcp => IPPROTO_TCP,
+        IpProto::Udp => IPPROTO_UDP,
+        _ => return Ok(false),
+    };
+    let ports: *const [u8; 4] = unsafe { data_ptr(ctx, offset)? };
+    let ports = unsafe { *ports };
+    let key = FlowKey {
+        remote,
+        local,
+        remote_port: u16::from_be_bytes([ports[2], ports[3]]),
+        local_port: u16::from_be_bytes([ports[0], ports[1]]),
+        protocol,
+    };
+
+    Ok(lookup(&key, now).is_some())
+}
+
+fn track(key: &FlowKey, state: FlowState) {
+    let now = unsafe { bpf_ktime_get_ns() };
+
+    match lookup(key, now) {
+        Some(flow) => unsafe {
+            if !((*flow).state == FlowState::Closing && state == FlowState::Established) {
+                (*flow).state = state;
+            }
+            (*flow).packets = (*flow).packets.wrapping_add(1);
+            (*flow).last_seen = now;
+        },
+        None => {
+            CONNTRACK
+                .insert(
+                    key,
+                    &Flow {
+                        state,
+                        packets: 1,
+                        last_seen: now,
+                    },
+                    0,
+                )
+                .ok();
+        }
+    }
+}
+
+pub fn try_conntrack(ctx: &TcContext) -> Result<(), Error> {
+    let eth_hdr: EthHdr = ctx.load(0).map_err(|_| Error)?;
+
+    if eth_hdr.ether_type != EtherType::Ipv4.into() {
+        return Ok(());
+    }
+
+    let ipv4_hdr: Ipv4Hdr = ctx.load(EthHdr::LEN).map_err(|_| Error)?;
+    let offset = EthHdr::LEN + ipv4_hdr.ihl() as usize;
+    let remote = u32::from_be_bytes(ipv4_hdr.dst_addr);
+    let local = u32::from_be_bytes(ipv4_hdr.src_addr);
+
+    match ipv4_hdr.proto {
+        IpProto::Tcp => {
+            let tcp_hdr: TcpHdr = ctx.load(offset).map_err(|_| Error)?;
+            let state = if tcp_hdr.rst() != 0 || tcp_hdr.fin() != 0 {
+                FlowState::Closing
+            } else if tcp_hdr.syn() != 0 && tcp_hdr.ack() == 0 {
+                FlowState::SynSent
+            } else {
+                FlowState::Established
+            };
+
+            track(
+                &FlowKey {
+                    remote,
+                    local,
+                    remote_port: u16::from_be_bytes(tcp_hdr.dest),
+                    local_port: u16::from_be_bytes(tcp_hdr.source),
+                    protocol: IPPROTO_TCP,
+                },
+                state,
+            );
+        }
+        IpProto::Udp => {
+            let udp_hdr: UdpHdr = ctx.load(offset).map_err(|_| Error)?;
+
+            track(
+                &FlowKey {
+                    remote,
+                    local,
+                    remote_port: u16::from_be_bytes(udp_hdr.dst),
+                    local_port: u16::from_be_bytes(udp_hdr.src),
+                    protocol: IPPROTO_UDP,
+                },
+                FlowState::Established,
+            );
+        }
+        IpProto::Icmp => {
+            let icmp_hdr: [u8; ICMP_HEADER_LEN] = ctx.load(offset).map_err(|_| Error)?;
+
+            if icmp_hdr[0] == ICMP_ECHO {
+                track(
+                    &FlowKey {
+                        remote,
+                        local,
+                        remote_port: 0,
+                        local_port: u16::from_be_bytes([icmp_hdr[4], icmp_hdr[5]]),
+                        protocol: IPPROTO_ICMP,
+                    },
+                    FlowState::Established,
+                );
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use aya_ebpf::{helpers::r#gen::bpf_ktime_get_ns, macros::map, maps::LruHashMap};
+use common::{Counter, FragmentKey, FragmentMode, FragmentVerdict, Setting};
+use network_types::ip::{IpProto, Ipv4Hdr};
+
+use crate::xdp::setting;
+
+const MORE_FRAGMENTS: u8 = 0b001;
+const TIMEOUT: u64 = 30 * 1_000_000_000;
+
+#[map]
+static FRAGMENTS: LruHashMap<FragmentKey, FragmentVerdict> = LruHashMap::pinned(4096, 0);
+
+pub(crate) fn fragment(ipv4_hdr: *const Ipv4Hdr, generation: u32) -> Option<Counter> {
+    let (more, offset, protocol, payload_len) = unsafe {
+        (
+            (*ipv4_hdr).frag_flags() & MORE_FRAGMENTS != 0,
+            (*ipv4_hdr).frag_offset(),
+            (*ipv4_hdr).proto,
+            ((*ipv4_hdr).tot_len() as usize).saturating_sub((*ipv4_hdr).ihl() as usize),
+        )
+    };
+
+    if !more && offset == 0 {
+        return None;
+    }
+
+    let mode = setting(generation, Setting::FragmentMode);
+
+    if mode == FragmentMode::Drop as u64 {
+        return Some(Counter::Fragment);
+    }
+
+    if setting(generation, Setting::FragmentDropTiny) != 0 && tiny(protocol, offset, payload_len) {
+        return Some(Counter::TinyFragment);
+    }
+
+    if mode != FragmentMode::Track as u64 || offset == 0 {
+        return None;
+    }
+
+    match unsafe { FRAGMENTS.get(&key(ipv4_hdr)) } {
+        Some(verdict) if unsafe { bpf_ktime_get_ns() } - verdict.seen <= TIMEOUT => {
+            Some(verdict.counter)
+        }
+        _ => Some(Counter::FragmentUntracked),
+    }
+}
+
+fn key(ipv4_hdr: *const Ipv4Hdr) -> FragmentKey {
+    unsafe {
+        FragmentKey {
+            src: u32::from_be_bytes((*ipv4_hdr).src_addr),
+            dst: u32::from_be_bytes((*ipv4_hdr).dst_addr),
+            id: (*ipv4_hdr).id().into(),
+            protocol: (*ipv4_hdr).proto as u32,
+        }
+    }
+}
+
+fn tiny(protocol: IpProto, offset: u16, payload_len: usize) -> bool {
+    let header_len = match protocol {
+        IpProto::Tcp => 20,
+        IpProto::Udp | IpProto::Icmp => 8,
+        _ => 0,
+    };
+
+    (offset == 0 && payload_len < header_len) || (offset == 1 && protocol == IpProto::Tcp)
+}
+
+pub(crate) fn track(ipv4_hdr: *const Ipv4Hdr, generation: u32, counter: Counter) {
+    let (more, offset) = unsafe {
+        (
+            (*ipv4_hdr).frag_flags() & MORE_FRAGMENTS != 0,
+            (*ipv4_hdr).frag_offset(),
+        )
+    };
+
+    if !more
+        || offset != 0
+        || counter == Counter::SynCookie
+        || setting(generation, Setting::FragmentMode) != FragmentMode::Track as u64
+    {
+        return;
+    }
+
+    let verdict = FragmentVerdict {
+        counter,
+        seen: unsafe { bpf_ktime_get_ns() },
+    };
+
+    FRAGMENTS.insert(&key(ipv4_hdr), &verdict, 0).ok();
+}
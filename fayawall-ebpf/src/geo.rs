@@ -0,0 +1,62 @@
+use aya_ebpf::{
+    bindings::BPF_F_NO_PREALLOC,
+    macros::map,
+    maps::{HashMap, LpmTrie, PerCpuHashMap, lpm_trie::Key},
+};
+use common::{GeoKey, GeoRule, Setting};
+
+use crate::xdp::setting;
+
+#[map]
+static GEO: LpmTrie<[u8; 8], u32> = LpmTrie::pinned(1 << 21, BPF_F_NO_PREALLOC);
+
+#[map]
+static GEO_DROPS: PerCpuHashMap<u32, u64> = PerCpuHashMap::pinned(256, 0);
+
+#[map]
+static GEO_RULES: HashMap<GeoKey, u32> = HashMap::pinned(512, 0);
+
+fn count(country: u32) {
+    match GEO_DROPS.get_ptr_mut(&country) {
+        Some(drops) => unsafe { *drops += 1 },
+        None => {
+            GEO_DROPS.insert(&country, &1, 0).ok();
+        }
+    }
+}
+
+pub fn country(generation: u32, addr: u32) -> u32 {
+    let mut data = [0; 8];
+
+    data[..4].copy_from_slice(&generation.to_be_bytes());
+    data[4..].copy_from_slice(&addr.to_be_bytes());
+
+    GEO.get(&Key::new(64, data)).copied().unwrap_or(0)
+}
+
+pub fn geo(generation: u32, addr: u32) -> bool {
+    let country = country(generation, addr);
+
+    if country == 0 {
+        return false;
+    }
+
+    let rule = unsafe {
+        GEO_RULES.get(&GeoKey {
+            generation,
+            country,
+        })
+    }
+    .copied();
+    let blocked = match rule {
+        Some(rule) if rule == GeoRule::Block as u32 => true,
+        Some(_) => false,
+        None => setting(generation, Setting::GeoAllowOnly) != 0,
+    };
+
+    if blocked {
+        count(country);
+    }
+
+    blocked
+}
@@ -1,3 +1,13 @@
 #![no_std]
 
+pub mod asn;
+pub mod bogon;
+pub mod conntrack;
+pub mod fragment;
+pub mod geo;
+pub mod sanity;
+pub mod scan;
+pub mod syn_cookie;
+pub mod tcp_anomaly;
+pub mod trap;
 pub mod xdp;
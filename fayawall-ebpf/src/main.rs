@@ -1,10 +1,21 @@
 #![no_main]
 #![no_std]
 
-use aya_ebpf::{bindings::xdp_action::XDP_ABORTED, macros::xdp, programs::XdpContext};
-use fayawall_ebpf::xdp::try_xdp_firewall;
+use aya_ebpf::{
+    bindings::{TC_ACT_OK, xdp_action::XDP_ABORTED},
+    macros::{classifier, xdp},
+    programs::{TcContext, XdpContext},
+};
+use fayawall_ebpf::{conntrack::try_conntrack, xdp::try_xdp_firewall};
 use panic_halt as _;
 
+#[classifier]
+pub fn conntrack(ctx: TcContext) -> i32 {
+    try_conntrack(&ctx).ok();
+
+    TC_ACT_OK as i32
+}
+
 #[xdp]
 pub fn xdp_firewall(ctx: XdpContext) -> u32 {
     try_xdp_firewall(ctx).unwrap_or(XDP_ABORTED)
@@ -0,0 +1,106 @@
+use aya_ebpf::programs::XdpContext;
+use common::{Counter, Sanity, Setting};
+use network_types::{eth::EthHdr, ip::Ipv4Hdr};
+
+use crate::xdp::{data_ptr, setting};
+
+const EOL: u8 = 0;
+const FRAGMENT_UNIT: usize = 8;
+const LSRR: u8 = 0x83;
+const MAX_OPTIONS_LEN: usize = 40;
+const MORE_FRAGMENTS: u8 = 0b001;
+const NOP: u8 = 1;
+const OPTIONS_OFFSET: usize = EthHdr::LEN + Ipv4Hdr::LEN;
+const RESERVED: u8 = 0b100;
+const SSRR: u8 = 0x89;
+
+fn option(ctx: &XdpContext, offset: usize) -> Option<u8> {
+    if offset >= MAX_OPTIONS_LEN {
+        return None;
+    }
+
+    unsafe {
+        data_ptr::<u8>(ctx, OPTIONS_OFFSET + offset)
+            .ok()
+            .map(|byte| *byte)
+    }
+}
+
+pub(crate) fn sanity(
+    ctx: &XdpContext,
+    ipv4_hdr: *const Ipv4Hdr,
+    generation: u32,
+) -> Option<Counter> {
+    let checks = setting(generation, Setting::Sanity);
+
+    if checks == 0 {
+        return None;
+    }
+
+    let (version, ihl, tot_len, flags, offset, ttl, src, dst) = unsafe {
+        (
+            (*ipv4_hdr).version(),
+            (*ipv4_hdr).ihl() as usize,
+            (*ipv4_hdr).tot_len() as usize,
+            (*ipv4_hdr).frag_flags(),
+            (*ipv4_hdr).frag_offset() as usize,
+            (*ipv4_hdr).ttl,
+            (*ipv4_hdr).src_addr,
+            (*ipv4_hdr).dst_addr,
+        )
+    };
+    let payload_len = tot_len.saturating_sub(ihl);
+    let enabled = |sanity: Sanity| checks & sanity.bit() != 0;
+    let sanity = if enabled(Sanity::BadVersion) && version != 4 {
+        Sanity::BadVersion
+    } else if enabled(Sanity::BadIhl) && ihl < Ipv4Hdr::LEN {
+        Sanity::BadIhl
+    } else if enabled(Sanity::Truncated)
+        && (tot_len < ihl || EthHdr::LEN + tot_len > ctx.data_end() - ctx.data())
+    {
+        Sanity::Truncated
+    } else if enabled(Sanity::SourceRoute)
+        && ihl > Ipv4Hdr::LEN
+        && source_route(ctx, ihl - Ipv4Hdr::LEN)
+    {
+        Sanity::SourceRoute
+    } else if enabled(Sanity::IpOptions) && ihl > Ipv4Hdr::LEN {
+        Sanity::IpOptions
+    } else if enabled(Sanity::ZeroTtl) && ttl == 0 {
+        Sanity::ZeroTtl
+    } else if enabled(Sanity::Land) && src == dst {
+        Sanity::Land
+    } else if enabled(Sanity::BadFragment)
+        && (flags & RESERVED != 0
+            || offset * FRAGMENT_UNIT + payload_len > u16::MAX as usize
+            || flags & MORE_FRAGMENTS != 0 && payload_len % FRAGMENT_UNIT != 0)
+    {
+        Sanity::BadFragment
+    } else {
+        return None;
+    };
+
+    Some(sanity.counter())
+}
+
+fn source_route(ctx: &XdpContext, options_len: usize) -> bool {
+    let mut offset = 0;
+
+    for _ in 0..MAX_OPTIONS_LEN {
+        if offset >= options_len {
+            break;
+        }
+
+        match option(ctx, offset) {
+            None | Some(EOL) => break,
+            Some(NOP) => offset += 1,
+            Some(LSRR | SSRR) => return true,
+            Some(_) => match option(ctx, offset + 1) {
+                Some(len) if len >= 2 => offset += len as usize,
+                _ => break,
+            },
+        }
+    }
+
+    false
+}
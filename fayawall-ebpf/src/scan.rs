@@ -0,0 +1,91 @@
+use aya_ebpf::{
+    helpers::r#gen::bpf_ktime_get_ns, macros::map, maps::LruHashMap, programs::XdpContext,
+};
+use common::{Counter, EventKind, ScanTrack, ScanWindow, Setting};
+use network_types::{
+    eth::EthHdr,
+    ip::{IpProto, Ipv4Hdr},
+};
+
+use crate::xdp::{ban, count, data_ptr, event, setting};
+
+const HASH: u32 = 2_654_435_761;
+
+#[map]
+static SCANS: LruHashMap<u32, ScanWindow> = LruHashMap::pinned(8192, 0);
+
+pub(crate) fn scan(ctx: &XdpContext, ipv4_hdr: *const Ipv4Hdr, generation: u32) -> Option<Counter> {
+    let threshold = setting(generation, Setting::ScanThreshold);
+    let (protocol, offset, ihl, source, destination) = unsafe {
+        (
+            (*ipv4_hdr).proto,
+            (*ipv4_hdr).frag_offset(),
+            (*ipv4_hdr).ihl() as usize,
+            u32::from_be_bytes((*ipv4_hdr).src_addr),
+            u32::from_be_bytes((*ipv4_hdr).dst_addr),
+        )
+    };
+
+    if threshold == 0 || offset != 0 || !matches!(protocol, IpProto::Tcp | IpProto::Udp) {
+        return None;
+    }
+
+    let target = if setting(generation, Setting::ScanTrack) == ScanTrack::Hosts as u64 {
+        destination
+    } else {
+        let ports: *const [u8; 4] = unsafe { data_ptr(ctx, EthHdr::LEN + ihl).ok()? };
+
+        u16::from_be_bytes(unsafe { [(*ports)[2], (*ports)[3]] }).into()
+    };
+    let slot = target.wrapping_mul(HASH) >> 24;
+    let bit = 1u64 << (slot & 63);
+    let now = unsafe { bpf_ktime_get_ns() };
+    let Some(window) = SCANS.get_ptr_mut(&source) else {
+        let mut window = ScanWindow {
+            window_start: now,
+            bitmap: [0; 4],
+            count: 1,
+        };
+
+        if let Some(word) = window.bitmap.get_mut((slot >> 6) as usize) {
+            *word = bit;
+        }
+
+        SCANS.insert(&source, &window, 0).ok();
+
+        return None;
+    };
+    let touched = unsafe {
+        if now - (*window).window_start > setting(generation, Setting::ScanWindow) {
+            (*window).window_start = now;
+            (*window).bitmap = [0; 4];
+            (*window).count = 0;
+        }
+
+        if let Some(word) = (*window).bitmap.get_mut((slot >> 6) as usize)
+            && *word & bit == 0
+        {
+            *word |= bit;
+            (*window).count += 1;
+        }
+
+        (*window).count
+    };
+
+    if touched != threshold {
+        return None;
+    }
+
+    let ttl = setting(generation, Setting::ScanBanTtl);
+
+    if ttl == 0 {
+        event(EventKind::Scan, source, touched);
+    } else if ban(EventKind::Scan, source, touched, ttl) {
+        return Some(Counter::Scan);
+    }
+
+    // Without a stored ban the packet passes, so it is not counted as a drop.
+    count(Counter::ScanDetected);
+
+    None
+}
@@ -0,0 +1,255 @@
+use core::{ffi::c_void, mem};
+
+use aya_ebpf::{
+    bindings::{
+        BPF_F_CURRENT_NETNS, BPF_TCP_LISTEN, bpf_sock_tuple,
+        bpf_sock_tuple__bindgen_ty_1__bindgen_ty_1 as bpf_sock_tuple_ipv4,
+    },
+    helpers::r#gen::{
+        bpf_ktime_get_ns, bpf_sk_release, bpf_skc_lookup_tcp, bpf_tcp_raw_check_syncookie_ipv4,
+        bpf_tcp_raw_gen_syncookie_ipv4, bpf_xdp_adjust_tail,
+    },
+    macros::map,
+    maps::{Array, HashMap},
+    programs::XdpContext,
+};
+use common::{Counter, PortKey, RateLimitWindow, Setting};
+use network_types::{
+    eth::EthHdr,
+    ip::{IpProto, Ipv4Hdr},
+    tcp::TcpHdr,
+};
+
+use crate::xdp::{Error, count, data_ptr, setting};
+
+const ETH_P_IP: [u8; 2] = [0x08, 0x00];
+const MSS_OPTION: [u8; 2] = [2, 4];
+const SECOND: u64 = 1_000_000_000;
+const SYNACK_FLAGS: u8 = 0x12;
+const SYNACK_LEN: usize = TCP_OFFSET + SYNACK_TCP_LEN;
+const SYNACK_TCP_LEN: usize = TcpHdr::LEN + MSS_OPTION.len() + 2;
+const TCP_OFFSET: usize = EthHdr::LEN + Ipv4Hdr::LEN;
+const TTL: u8 = 64;
+
+#[map]
+static SYN_PORTS: HashMap<PortKey, u8> = HashMap::pinned(256, 0);
+
+#[map]
+static SYN_WINDOW: Array<RateLimitWindow> = Array::pinned(1, 0);
+
+fn checksum(sum: u32) -> u16 {
+    let sum = (sum & 0xffff) + (sum >> 16);
+
+    !((sum & 0xffff) + (sum >> 16)) as u16
+}
+
+fn generate(ctx: &XdpContext, ipv4_hdr: *const Ipv4Hdr, tcp_hdr: *const TcpHdr) -> Option<i64> {
+    let tcp_len = (unsafe { (*tcp_hdr).doff() } & 0xf) as usize * 4;
+
+    if tcp_len < TcpHdr::LEN || ctx.data() + TCP_OFFSET + tcp_len > ctx.data_end() {
+        return None;
+    }
+
+    let value = unsafe {
+        bpf_tcp_raw_gen_syncookie_ipv4(
+            ipv4_hdr.cast_mut().cast(),
+            tcp_hdr.cast_mut().cast(),
+            tcp_len as u32,
+        )
+    };
+
+    (value >= 0).then_some(value)
+}
+
+fn listening(ctx: &XdpContext, ipv4_hdr: *const Ipv4Hdr, tcp_hdr: *const TcpHdr) -> bool {
+    let mut tuple: bpf_sock_tuple = unsafe { mem::zeroed() };
+
+    unsafe {
+        tuple.__bindgen_anon_1.ipv4 = bpf_sock_tuple_ipv4 {
+            saddr: u32::from_ne_bytes((*ipv4_hdr).src_addr),
+            daddr: u32::from_ne_bytes((*ipv4_hdr).dst_addr),
+            sport: u16::from_ne_bytes((*tcp_hdr).source),
+            dport: u16::from_ne_bytes((*tcp_hdr).dest),
+        };
+    }
+
+    let sock = unsafe {
+        bpf_skc_lookup_tcp(
+            ctx.ctx.cast(),
+            &mut tuple,
+            mem::size_of::<bpf_sock_tuple_ipv4>() as u32,
+            BPF_F_CURRENT_NETNS as u64,
+            0,
+        )
+    };
+
+    if sock.is_null() {
+        return false;
+    }
+
+    let state = unsafe { (*sock).state };
+
+    unsafe { bpf_sk_release(sock.cast::<c_void>()) };
+
+    state == BPF_TCP_LISTEN
+}
+
+fn rate() -> u64 {
+    let now = unsafe { bpf_ktime_get_ns() };
+    let Some(window) = SYN_WINDOW.get_ptr_mut(0) else {
+        return 0;
+    };
+
+    unsafe {
+        if now - (*window).window_start > SECOND {
+            (*window).window_start = now;
+            (*window).packet_count = 1;
+        } else {
+            (*window).packet_count += 1;
+        }
+
+        (*window).packet_count
+    }
+}
+
+pub(crate) fn syn_cookie(
+    ctx: &XdpContext,
+    ipv4_hdr: *const Ipv4Hdr,
+    generation: u32,
+    cookie: &mut i64,
+) -> Option<Counter> {
+    let threshold = setting(generation, Setting::SynThreshold);
+    let (protocol, ihl) = unsafe { ((*ipv4_hdr).proto, (*ipv4_hdr).ihl() as usize) };
+
+    if threshold == 0 || protocol != IpProto::Tcp {
+        return None;
+    }
+
+    let tcp_hdr: *const TcpHdr = unsafe { data_ptr(ctx, EthHdr::LEN + ihl).ok()? };
+    let (syn, ack, rst, port) = unsafe {
+        (
+            (*tcp_hdr).syn() != 0,
+            (*tcp_hdr).ack() != 0,
+            (*tcp_hdr).rst() != 0,
+            u16::from_be_bytes((*tcp_hdr).dest),
+        )
+    };
+
+    unsafe {
+        SYN_PORTS.get(&PortKey {
+            generation,
+            port: port.into(),
+        })?
+    };
+
+    if syn && !ack {
+        if rate() <= threshold {
+            return None;
+        }
+
+        // Cookies are only answered for option-free headers, so padding the
+        // header must not let a flood bypass them.
+        if ihl != Ipv4Hdr::LEN {
+            return Some(Counter::SynOptions);
+        }
+
+        *cookie = generate(ctx, ipv4_hdr, tcp_hdr)?;
+
+        Some(Counter::SynCookie)
+    } else if ack && !syn && !rst && listening(ctx, ipv4_hdr, tcp_hdr) {
+        let valid = unsafe {
+            bpf_tcp_raw_check_syncookie_ipv4(ipv4_hdr.cast_mut().cast(), tcp_hdr.cast_mut().cast())
+        } == 0;
+
+        if valid {
+            // A valid cookie completes the handshake but still has to clear the
+            // rate limit and default action like any other packet.
+            count(Counter::SynCookieValid);
+
+            None
+        } else {
+            Some(Counter::SynCookieInvalid)
+        }
+    } else {
+        None
+    }
+}
+
+pub fn synack(ctx: &XdpContext, value: i64) -> Result<(), Error> {
+    let ipv4_hdr = unsafe { data_ptr::<Ipv4Hdr>(ctx, EthHdr::LEN)? };
+    let tcp_hdr = unsafe { data_ptr::<TcpHdr>(ctx, TCP_OFFSET)? };
+    let (eth_hdr, src, dst, sport, dport, seq) = unsafe {
+        let eth_hdr = data_ptr::<EthHdr>(ctx, 0)?;
+
+        (
+            ((*eth_hdr).dst_addr, (*eth_hdr).src_addr),
+            (*ipv4_hdr).src_addr,
+            (*ipv4_hdr).dst_addr,
+            (*tcp_hdr).source,
+            (*tcp_hdr).dest,
+            u32::from_be_bytes((*tcp_hdr).seq),
+        )
+    };
+    let cookie = (value as u32).to_be_bytes();
+    let mss = ((value >> 32) as u16).to_be_bytes();
+    let delta = SYNACK_LEN as i64 - (ctx.data_end() - ctx.data()) as i64;
+
+    if unsafe { bpf_xdp_adjust_tail(ctx.ctx, delta as i32) } != 0 {
+        return Err(Error);
+    }
+
+    let mut frame = [0u8; SYNACK_LEN];
+
+    frame[0..6].copy_from_slice(&eth_hdr.1);
+    frame[6..12].copy_from_slice(&eth_hdr.0);
+    frame[12..14].copy_from_slice(&ETH_P_IP);
+
+    let ip = &mut frame[EthHdr::LEN..TCP_OFFSET];
+
+    ip[0] = 0x45;
+    ip[2..4].copy_from_slice(&((Ipv4Hdr::LEN + SYNACK_TCP_LEN) as u16).to_be_bytes());
+    ip[6] = 0x40;
+    ip[8] = TTL;
+    ip[9] = IpProto::Tcp as u8;
+    ip[12..16].copy_from_slice(&dst);
+    ip[16..20].copy_from_slice(&src);
+
+    let sum = ip
+        .chunks_exact(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum::<u32>();
+
+    ip[10..12].copy_from_slice(&checksum(sum).to_be_bytes());
+
+    let tcp = &mut frame[TCP_OFFSET..];
+
+    tcp[0..2].copy_from_slice(&dport);
+    tcp[2..4].copy_from_slice(&sport);
+    tcp[4..8].copy_from_slice(&cookie);
+    tcp[8..12].copy_from_slice(&seq.wrapping_add(1).to_be_bytes());
+    tcp[12] = ((SYNACK_TCP_LEN / 4) as u8) << 4;
+    tcp[13] = SYNACK_FLAGS;
+    tcp[14..16].copy_from_slice(&u16::MAX.to_be_bytes());
+    tcp[20..22].copy_from_slice(&MSS_OPTION);
+    tcp[22..24].copy_from_slice(&mss);
+
+    let pseudo = [dst, src]
+        .iter()
+        .flat_map(|addr| addr.chunks_exact(2))
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum::<u32>()
+        + IpProto::Tcp as u32
+        + SYNACK_TCP_LEN as u32;
+    let sum = tcp
+        .chunks_exact(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum::<u32>();
+
+    tcp[16..18].copy_from_slice(&checksum(pseudo + sum).to_be_bytes());
+
+    let packet = unsafe { data_ptr::<[u8; SYNACK_LEN]>(ctx, 0)? }.cast_mut();
+
+    unsafe { *packet = frame };
+
+    Ok(())
+}
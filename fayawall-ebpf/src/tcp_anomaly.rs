@@ -0,0 +1,94 @@
+use aya_ebpf::{macros::map, maps::PerCpuArray, programs::XdpContext};
+use aya_log_ebpf::info;
+use common::{Counter, Setting, TcpAnomaly};
+use network_types::{
+    eth::EthHdr,
+    ip::{IpProto, Ipv4Hdr},
+    tcp::TcpHdr,
+};
+
+use crate::xdp::{data_ptr, setting};
+
+const MIN_DATA_OFFSET: u16 = 5;
+
+#[map]
+static TCP_ANOMALY_LOGS: PerCpuArray<u64> = PerCpuArray::pinned(TcpAnomaly::ALL.len() as u32, 0);
+
+fn detect(anomaly: TcpAnomaly, tcp_hdr: *const TcpHdr, segment_len: usize) -> bool {
+    let (fin, syn, rst, psh, ack, urg, doff, sport, dport) = unsafe {
+        (
+            (*tcp_hdr).fin() != 0,
+            (*tcp_hdr).syn() != 0,
+            (*tcp_hdr).rst() != 0,
+            (*tcp_hdr).psh() != 0,
+            (*tcp_hdr).ack() != 0,
+            (*tcp_hdr).urg() != 0,
+            (*tcp_hdr).doff(),
+            u16::from_be_bytes((*tcp_hdr).source),
+            u16::from_be_bytes((*tcp_hdr).dest),
+        )
+    };
+
+    match anomaly {
+        TcpAnomaly::NullScan => !(fin || syn || rst || psh || ack || urg),
+        TcpAnomaly::XmasScan => fin && psh && urg,
+        TcpAnomaly::FinScan => fin && !(syn || rst || psh || ack || urg),
+        TcpAnomaly::SynFin => syn && fin,
+        TcpAnomaly::SynRst => syn && rst,
+        TcpAnomaly::SynPayload => {
+            syn && !ack && segment_len > (doff.max(MIN_DATA_OFFSET) as usize) * 4
+        }
+        TcpAnomaly::BadDataOffset => doff < MIN_DATA_OFFSET,
+        TcpAnomaly::PortZero => sport == 0 || dport == 0,
+    }
+}
+
+pub(crate) fn tcp_anomaly(
+    ctx: &XdpContext,
+    ipv4_hdr: *const Ipv4Hdr,
+    generation: u32,
+) -> Option<Counter> {
+    let drop = setting(generation, Setting::TcpAnomalyDrop);
+    let log = setting(generation, Setting::TcpAnomalyLog);
+    let (protocol, offset, ihl, tot_len, source) = unsafe {
+        (
+            (*ipv4_hdr).proto,
+            (*ipv4_hdr).frag_offset(),
+            (*ipv4_hdr).ihl() as usize,
+            (*ipv4_hdr).tot_len() as usize,
+            (*ipv4_hdr).src_addr,
+        )
+    };
+
+    if drop | log == 0 || protocol != IpProto::Tcp || offset != 0 {
+        return None;
+    }
+
+    let tcp_hdr: *const TcpHdr = unsafe { data_ptr(ctx, EthHdr::LEN + ihl).ok()? };
+    let segment_len = tot_len.saturating_sub(ihl);
+
+    for anomaly in TcpAnomaly::ALL {
+        if (drop | log) & anomaly.bit() == 0 || !detect(anomaly, tcp_hdr, segment_len) {
+            continue;
+        }
+
+        let counter = anomaly.counter();
+
+        if drop & anomaly.bit() != 0 {
+            return Some(counter);
+        }
+
+        if let Some(logs) = TCP_ANOMALY_LOGS.get_ptr_mut(anomaly as u32) {
+            unsafe { *logs += 1 };
+        }
+
+        info!(
+            ctx,
+            "TCP anomaly {} from `{:i}`",
+            counter.name(),
+            u32::from_be_bytes(source)
+        );
+    }
+
+    None
+}
@@ -0,0 +1,60 @@
+use aya_ebpf::{macros::map, maps::HashMap, programs::XdpContext};
+use common::{Counter, EventKind, PortKey, Setting};
+use network_types::{
+    eth::EthHdr,
+    ip::{IpProto, Ipv4Hdr},
+    tcp::TcpHdr,
+};
+
+use crate::xdp::{ban, data_ptr, setting};
+
+#[map]
+static TRAP_PORTS: HashMap<PortKey, u8> = HashMap::pinned(256, 0);
+
+pub(crate) fn trap(ctx: &XdpContext, ipv4_hdr: *const Ipv4Hdr, generation: u32) -> Option<Counter> {
+    let (protocol, offset, ihl, source) = unsafe {
+        (
+            (*ipv4_hdr).proto,
+            (*ipv4_hdr).frag_offset(),
+            (*ipv4_hdr).ihl() as usize,
+            u32::from_be_bytes((*ipv4_hdr).src_addr),
+        )
+    };
+
+    if offset != 0 || protocol != IpProto::Tcp {
+        return None;
+    }
+
+    let tcp_hdr: *const TcpHdr = unsafe { data_ptr(ctx, EthHdr::LEN + ihl).ok()? };
+    let (syn, ack, rst, fin, port) = unsafe {
+        (
+            (*tcp_hdr).syn() != 0,
+            (*tcp_hdr).ack() != 0,
+            (*tcp_hdr).rst() != 0,
+            (*tcp_hdr).fin() != 0,
+            u16::from_be_bytes((*tcp_hdr).dest),
+        )
+    };
+
+    // Only a connection attempt counts; stray segments are too easy to spoof.
+    if !syn || ack || rst || fin {
+        return None;
+    }
+
+    unsafe {
+        TRAP_PORTS.get(&PortKey {
+            generation,
+            port: port.into(),
+        })?
+    };
+
+    // The probe is dropped even when its ban is rate-limited.
+    ban(
+        EventKind::Trap,
+        source,
+        port.into(),
+        setting(generation, Setting::TrapBanTtl),
+    );
+
+    Some(Counter::Trap)
+}
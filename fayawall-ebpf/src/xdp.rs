@@ -1,44 +1,150 @@
 use core::mem;
 
 use aya_ebpf::{
-    bindings::xdp_action::{XDP_DROP, XDP_PASS},
+    bindings::{
+        BPF_F_NO_PREALLOC,
+        xdp_action::{XDP_DROP, XDP_PASS, XDP_TX},
+    },
     helpers::r#gen::bpf_ktime_get_ns,
     macros::map,
-    maps::HashMap,
+    maps::{Array, HashMap, LpmTrie, LruHashMap, PerCpuArray, RingBuf, lpm_trie::Key},
     programs::XdpContext,
 };
 use aya_log_ebpf::{info, warn};
-use common::RateLimitSetting;
+use common::{
+    Check, Counter, DefaultAction, Event, EventKind, ListKey, RateLimitWindow, Setting, SettingKey,
+    SourceStat, Trace,
+};
 use network_types::{
     eth::{EthHdr, EtherType},
-    ip::Ipv4Hdr,
+    ip::{IpProto, Ipv4Hdr},
 };
 
+use crate::{asn, bogon, conntrack, fragment, geo, sanity, scan, syn_cookie, tcp_anomaly, trap};
+
+const SECOND: u64 = 1_000_000_000;
+// New bans each CPU may add per second, so spoofed triggers cannot churn BANS.
+const BANS_PER_SECOND: u64 = 64;
+
 pub struct Error;
 
-struct RateLimitWindow {
-    window_start: u64,
-    packet_count: u64,
-}
+#[map]
+static BANS: LruHashMap<u32, u64> = LruHashMap::pinned(4096, 0);
+
+#[map]
+static BAN_RATE: PerCpuArray<u64> = PerCpuArray::pinned(2, 0);
 
 #[map]
-static BLACKLIST: HashMap<u32, u32> = HashMap::<u32, u32>::with_max_entries(1024, 0);
+static BLACKLIST: HashMap<ListKey, u64> = HashMap::<ListKey, u64>::pinned(2048, 0);
 
 #[map]
-static RATE_LIMIT_SETTINGS: HashMap<u8, u64> = HashMap::with_max_entries(2, 0);
+static COUNTERS: PerCpuArray<u64> = PerCpuArray::pinned(Counter::LEN, 0);
 
 #[map]
-static RATE_LIMIT_WINDOWS: HashMap<u32, RateLimitWindow> = HashMap::with_max_entries(1024, 0);
+static EVENTS: RingBuf = RingBuf::with_byte_size(4096 * 64, 0);
 
 #[map]
-static WHITELIST: HashMap<u32, u32> = HashMap::<u32, u32>::with_max_entries(1024, 0);
+static GENERATION: Array<u32> = Array::pinned(1, 0);
+
+#[map]
+static MANAGEMENT: LpmTrie<u32, u32> = LpmTrie::pinned(256, BPF_F_NO_PREALLOC);
+
+#[map]
+static RATE_LIMIT_WINDOWS: HashMap<u32, RateLimitWindow> = HashMap::pinned(1024, 0);
+
+#[map]
+static SETTINGS: HashMap<SettingKey, u64> = HashMap::pinned(64, 0);
+
+#[map]
+static SOURCE_STATS: LruHashMap<u32, SourceStat> = LruHashMap::pinned(4096, 0);
+
+#[map]
+static TRACE: LpmTrie<u32, u32> = LpmTrie::pinned(64, BPF_F_NO_PREALLOC);
+
+#[map]
+static TRACES: RingBuf = RingBuf::with_byte_size(4096 * 16, 0);
+
+#[map]
+static WHITELIST: HashMap<ListKey, u64> = HashMap::<ListKey, u64>::pinned(2048, 0);
+
+struct Checks(u32);
+
+impl Checks {
+    fn hit(&mut self, check: Check, hit: bool) -> bool {
+        self.0 |= 1 << check as u32;
+        hit
+    }
+
+    fn hit_by(&mut self, check: Check, counter: Option<Counter>) -> Option<Counter> {
+        self.0 |= 1 << check as u32;
+        counter
+    }
+}
 
-fn blacklist(addr: u32) -> bool {
-    unsafe { BLACKLIST.get(&addr).is_some() }
+fn allow_established(generation: u32) -> bool {
+    setting(generation, Setting::AllowEstablished) != 0
+}
+
+fn ban_allowed(now: u64) -> bool {
+    let (Some(start), Some(count)) = (BAN_RATE.get_ptr_mut(0), BAN_RATE.get_ptr_mut(1)) else {
+        return false;
+    };
+
+    unsafe {
+        if now - *start >= SECOND {
+            *start = now;
+            *count = 0;
+        }
+
+        if *count >= BANS_PER_SECOND {
+            return false;
+        }
+
+        *count += 1;
+    }
+
+    true
+}
+
+pub(crate) fn ban(kind: EventKind, addr: u32, value: u64, ttl: u64) -> bool {
+    let now = unsafe { bpf_ktime_get_ns() };
+    let expires = match ttl {
+        0 => u64::MAX,
+        ttl => now.saturating_add(ttl.saturating_mul(SECOND)),
+    };
+    let banned = ban_allowed(now) && BANS.insert(&addr, &expires, 0).is_ok();
+
+    output(kind, addr, value, ttl, banned);
+
+    banned
+}
+
+fn banned(addr: u32) -> bool {
+    let Some(&expires) = (unsafe { BANS.get(&addr) }) else {
+        return false;
+    };
+
+    if unsafe { bpf_ktime_get_ns() } < expires {
+        return true;
+    }
+
+    BANS.remove(&addr).ok();
+
+    false
+}
+
+fn blacklist(generation: u32, addr: u32) -> bool {
+    hit(&BLACKLIST, generation, addr) || banned(addr)
+}
+
+pub(crate) fn count(counter: Counter) {
+    if let Some(count) = COUNTERS.get_ptr_mut(counter as u32) {
+        unsafe { *count += 1 };
+    }
 }
 
 #[inline(always)]
-unsafe fn data_ptr<T>(ctx: &XdpContext, offset: usize) -> Result<*const T, Error> {
+pub(crate) unsafe fn data_ptr<T>(ctx: &XdpContext, offset: usize) -> Result<*const T, Error> {
     let data_end = ctx.data_end();
     let data_start = ctx.data();
     let header_size = mem::size_of::<T>();
@@ -52,17 +158,59 @@ unsafe fn data_ptr<T>(ctx: &XdpContext, offset: usize) -> Result<*const T, Error
     Ok(unsafe { &*ptr })
 }
 
-fn rate_limit(addr: u32, ctx: &XdpContext) -> bool {
+fn default_action(generation: u32) -> Counter {
+    if setting(generation, Setting::DefaultAction) == DefaultAction::Drop as u64 {
+        Counter::Default
+    } else {
+        Counter::Pass
+    }
+}
+
+pub(crate) fn event(kind: EventKind, addr: u32, value: u64) {
+    output(kind, addr, value, 0, false);
+}
+
+fn generation() -> u32 {
+    GENERATION.get(0).copied().unwrap_or(0)
+}
+
+fn hit(list: &HashMap<ListKey, u64>, generation: u32, addr: u32) -> bool {
+    match list.get_ptr_mut(&ListKey { generation, addr }) {
+        Some(last_hit) => {
+            unsafe { *last_hit = bpf_ktime_get_ns() };
+            true
+        }
+        None => false,
+    }
+}
+
+fn management(addr: u32) -> bool {
+    MANAGEMENT.get(&Key::new(32, addr.to_be())).is_some()
+}
+
+fn output(kind: EventKind, addr: u32, value: u64, ttl: u64, banned: bool) {
+    EVENTS
+        .output(
+            &Event {
+                kind,
+                addr,
+                country: geo::country(generation(), addr),
+                banned: banned.into(),
+                value,
+                ttl,
+            },
+            0,
+        )
+        .ok();
+}
+
+fn rate_limit(generation: u32, addr: u32, ctx: &XdpContext) -> bool {
     let now = unsafe { bpf_ktime_get_ns() };
 
     match RATE_LIMIT_WINDOWS.get_ptr_mut(&addr) {
         Some(window) => unsafe {
-            let packet_limit = *RATE_LIMIT_SETTINGS
-                .get(&(RateLimitSetting::PacketLimit as u8))
-                .unwrap_or(&u64::MAX);
-            let window_size = *RATE_LIMIT_SETTINGS
-                .get(&(RateLimitSetting::WindowSize as u8))
-                .unwrap_or(&u64::MAX);
+            let packet_limit = setting_or(generation, Setting::PacketLimit, u64::MAX);
+            let window_size = setting_or(generation, Setting::WindowSize, u64::MAX);
 
             if now - (*window).window_start > window_size {
                 (*window).window_start = now;
@@ -73,6 +221,10 @@ fn rate_limit(addr: u32, ctx: &XdpContext) -> bool {
 
             let packet_count = (*window).packet_count;
 
+            if packet_count == packet_limit.saturating_add(1) {
+                event(EventKind::RateLimit, addr, packet_count);
+            }
+
             if packet_count > packet_limit {
                 warn!(
                     ctx,
@@ -106,8 +258,103 @@ fn rate_limit(addr: u32, ctx: &XdpContext) -> bool {
     }
 }
 
-fn whitelist(addr: u32) -> bool {
-    unsafe { WHITELIST.get(&addr).is_some() }
+pub(crate) fn setting(generation: u32, setting: Setting) -> u64 {
+    setting_or(generation, setting, 0)
+}
+
+fn setting_or(generation: u32, setting: Setting, default: u64) -> u64 {
+    unsafe {
+        SETTINGS
+            .get(&SettingKey {
+                generation,
+                setting: setting as u32,
+            })
+            .copied()
+            .unwrap_or(default)
+    }
+}
+
+fn source_stat(addr: u32, bytes: u64, action: u32) {
+    let drops = (action == XDP_DROP) as u64;
+
+    match SOURCE_STATS.get_ptr_mut(&addr) {
+        Some(stat) => unsafe {
+            (*stat).packets += 1;
+            (*stat).bytes += bytes;
+            (*stat).drops += drops;
+        },
+        None => {
+            SOURCE_STATS
+                .insert(
+                    &addr,
+                    &SourceStat {
+                        packets: 1,
+                        bytes,
+                        drops,
+                    },
+                    0,
+                )
+                .ok();
+        }
+    }
+}
+
+fn trace(
+    ctx: &XdpContext,
+    ipv4_hdr: *const Ipv4Hdr,
+    checks: Checks,
+    check: Check,
+    counter: Counter,
+    action: u32,
+) {
+    let (src, dst, protocol, ihl) = unsafe {
+        (
+            u32::from_be_bytes((*ipv4_hdr).src_addr),
+            u32::from_be_bytes((*ipv4_hdr).dst_addr),
+            (*ipv4_hdr).proto,
+            (*ipv4_hdr).ihl() as usize,
+        )
+    };
+
+    if TRACE.get(&Key::new(32, src.to_be())).is_none() {
+        return;
+    }
+
+    let (sport, dport) = match protocol {
+        IpProto::Tcp | IpProto::Udp => match unsafe { data_ptr::<[u8; 4]>(ctx, EthHdr::LEN + ihl) }
+        {
+            Ok(ports) => unsafe {
+                (
+                    u16::from_be_bytes([(*ports)[0], (*ports)[1]]),
+                    u16::from_be_bytes([(*ports)[2], (*ports)[3]]),
+                )
+            },
+            Err(_) => (0, 0),
+        },
+        _ => (0, 0),
+    };
+
+    TRACES
+        .output(
+            &Trace {
+                src,
+                dst,
+                len: (ctx.data_end() - ctx.data()) as u32,
+                protocol: protocol as u32,
+                sport,
+                dport,
+                checks: checks.0,
+                check: check as u32,
+                counter: counter as u32,
+                action,
+            },
+            0,
+        )
+        .ok();
+}
+
+fn whitelist(generation: u32, addr: u32) -> bool {
+    hit(&WHITELIST, generation, addr)
 }
 
 pub fn try_xdp_firewall(ctx: XdpContext) -> Result<u32, Error> {
@@ -119,21 +366,79 @@ pub fn try_xdp_firewall(ctx: XdpContext) -> Result<u32, Error> {
 
     let ipv4_hdr: *const Ipv4Hdr = unsafe { data_ptr(&ctx, EthHdr::LEN)? };
     let source = u32::from_be_bytes(unsafe { (*ipv4_hdr).src_addr });
-    let action = if !whitelist(source) && (blacklist(source) || rate_limit(source, &ctx)) {
-        XDP_DROP
+    let generation = generation();
+    let mut checks = Checks(0);
+    let mut cookie = 0;
+    let (check, counter) = if checks.hit(Check::Management, management(source)) {
+        (Check::Management, Counter::Pass)
+    } else if let Some(counter) =
+        checks.hit_by(Check::Sanity, sanity::sanity(&ctx, ipv4_hdr, generation))
+    {
+        (Check::Sanity, counter)
+    } else if let Some(counter) =
+        checks.hit_by(Check::Fragment, fragment::fragment(ipv4_hdr, generation))
+    {
+        (Check::Fragment, counter)
+    } else if checks.hit(Check::Whitelist, whitelist(generation, source)) {
+        (Check::Whitelist, Counter::Pass)
+    } else if checks.hit(Check::Blacklist, blacklist(generation, source)) {
+        (Check::Blacklist, Counter::Blacklist)
+    } else if checks.hit(Check::Bogon, bogon::bogon(generation, source)) {
+        (Check::Bogon, Counter::Bogon)
+    } else if let Some(counter) = checks.hit_by(Check::Trap, trap::trap(&ctx, ipv4_hdr, generation))
+    {
+        (Check::Trap, counter)
+    } else if let Some(counter) = checks.hit_by(
+        Check::TcpAnomaly,
+        tcp_anomaly::tcp_anomaly(&ctx, ipv4_hdr, generation),
+    ) {
+        (Check::TcpAnomaly, counter)
+    } else if checks.hit(Check::Geo, geo::geo(generation, source)) {
+        (Check::Geo, Counter::Geo)
+    } else if checks.hit(Check::Asn, asn::asn(generation, source)) {
+        (Check::Asn, Counter::Asn)
+    } else if let Some(counter) = checks.hit_by(Check::Scan, scan::scan(&ctx, ipv4_hdr, generation))
+    {
+        (Check::Scan, counter)
+    } else if let Some(counter) = checks.hit_by(
+        Check::SynCookie,
+        syn_cookie::syn_cookie(&ctx, ipv4_hdr, generation, &mut cookie),
+    ) {
+        (Check::SynCookie, counter)
+    } else if allow_established(generation)
+        && checks.hit(Check::Established, conntrack::established(&ctx, ipv4_hdr))
+    {
+        (Check::Established, Counter::Pass)
+    } else if checks.hit(Check::RateLimit, rate_limit(generation, source, &ctx)) {
+        (Check::RateLimit, Counter::RateLimit)
     } else {
-        XDP_PASS
+        (Check::Default, default_action(generation))
+    };
+    let action = match counter {
+        Counter::SynCookie => XDP_TX,
+        counter if counter.is_drop() => XDP_DROP,
+        _ => XDP_PASS,
     };
 
+    fragment::track(ipv4_hdr, generation, counter);
+    count(counter);
+    source_stat(source, (ctx.data_end() - ctx.data()) as u64, action);
+    trace(&ctx, ipv4_hdr, checks, check, counter, action);
+
     info!(
         &ctx,
         "SOURCE: {:i}\tACTION: {}",
         source,
         match action {
             1 => "XDP_DROP",
+            3 => "XDP_TX",
             _ => "XDP_PASS",
         }
     );
 
+    if action == XDP_TX && syn_cookie::synack(&ctx, cookie).is_err() {
+        return Ok(XDP_DROP);
+    }
+
     Ok(action)
 }
@@ -2,12 +2,24 @@ use clap::Parser;
 
 #[derive(Debug, Parser)]
 pub struct Arg {
+    #[arg(short, long, default_value = "audit.log")]
+    pub audit_log: String,
+
+    #[arg(long)]
+    pub cleanup: bool,
+
     #[arg(short, long, default_value = "eth0")]
     pub iface: String,
 
     #[arg(short, long, default_value = "license.toml")]
     pub license: String,
 
+    #[arg(long)]
+    pub pin: bool,
+
     #[arg(short, long, default_value = "policy.toml")]
     pub policy: String,
+
+    #[arg(short, long, default_value = "/var/lib/fayawall")]
+    pub state_dir: String,
 }
@@ -0,0 +1,262 @@
+use std::{
+    collections::HashSet,
+    fs::{metadata, read, read_to_string},
+    net::Ipv4Addr,
+    path::Path,
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use anyhow::bail;
+use aya::Ebpf;
+use ipnet::{Ipv4Net, Ipv4Subnets};
+use tracing::warn;
+
+use crate::{
+    audit::{Audit, Origin},
+    ebpf::Init,
+    mmdb::Mmdb,
+    policy::Policy,
+};
+
+static WATCHES: Mutex<[Option<Watch>; 2]> = Mutex::new([None, None]);
+
+#[derive(Clone)]
+struct Watch {
+    asns: Vec<u32>,
+    database: String,
+    modified: Option<SystemTime>,
+}
+
+pub struct Asn;
+
+impl Asn {
+    pub fn clear(ebpf: &mut Ebpf, generation: u32) -> anyhow::Result<()> {
+        ebpf.asn_prefixes_at(generation)?.clear()?;
+        WATCHES.lock().unwrap()[generation as usize] = None;
+
+        Ok(())
+    }
+
+    pub fn copy(ebpf: &mut Ebpf, from: u32, to: u32) -> anyhow::Result<()> {
+        ebpf.asn_prefixes_at(from)?.copy_to(to)?;
+
+        let mut watches = WATCHES.lock().unwrap();
+
+        watches[to as usize] = watches[from as usize].clone();
+
+        Ok(())
+    }
+
+    pub fn load(path: &str, asns: &[u32]) -> anyhow::Result<Vec<(Ipv4Net, u32)>> {
+        let path = Path::new(path);
+        let nets = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("mmdb") => Mmdb::new(&read(path)?)?.nets(|value| {
+                value
+                    .get("autonomous_system_number")?
+                    .uint()?
+                    .try_into()
+                    .ok()
+            })?,
+            Some("tsv") => Self::tsv(path)?,
+            _ => bail!(
+                "`{}` is neither a .mmdb nor a .tsv database",
+                path.display()
+            ),
+        };
+        let asns = asns.iter().collect::<HashSet<_>>();
+
+        Ok(nets
+            .into_iter()
+            .filter(|(_, asn)| asns.contains(asn))
+            .collect())
+    }
+
+    fn modified(path: &str) -> Option<SystemTime> {
+        metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    pub fn refresh(ebpf: &mut Ebpf) {
+        if let Err(e) = Self::reload(ebpf) {
+            warn!("ASN database not refreshed: {e}");
+        }
+    }
+
+    fn reload(ebpf: &mut Ebpf) -> anyhow::Result<()> {
+        let active = ebpf.generation()?.get()?;
+        let staged = active ^ 1;
+        let Some(watch) = WATCHES.lock().unwrap()[active as usize].clone() else {
+            return Ok(());
+        };
+
+        let modified = Self::modified(&watch.database);
+
+        if modified == watch.modified {
+            return Ok(());
+        }
+
+        Policy::clear(ebpf, staged)?;
+
+        if let Err(e) = Policy::copy(ebpf, active, staged)
+            .and_then(|()| Self::stage(ebpf, staged, watch.asns, watch.database, Origin::Policy))
+        {
+            // A bad database is reported once and retried only when it changes again.
+            if let Some(watch) = WATCHES.lock().unwrap()[active as usize].as_mut() {
+                watch.modified = modified;
+            }
+
+            Policy::clear(ebpf, staged)?;
+            return Err(e);
+        }
+
+        ebpf.generation()?.set(staged)?;
+        Policy::clear(ebpf, active)
+    }
+
+    pub fn stage(
+        ebpf: &mut Ebpf,
+        generation: u32,
+        asns: Vec<u32>,
+        database: String,
+        origin: Origin,
+    ) -> anyhow::Result<()> {
+        let modified = Self::modified(&database);
+        let result = Self::load(&database, &asns)
+            .and_then(|nets| Ok(ebpf.asn_prefixes_at(generation)?.load(&nets)?));
+
+        Audit::record(origin, "asn.load", &[&database], &result);
+
+        if result.is_ok() {
+            WATCHES.lock().unwrap()[generation as usize] = Some(Watch {
+                asns,
+                database,
+                modified,
+            });
+        }
+
+        result
+    }
+
+    fn tsv(path: &Path) -> anyhow::Result<Vec<(Ipv4Net, u32)>> {
+        Ok(read_to_string(path)?
+            .lines()
+            .filter_map(|line| {
+                let fields = line.split('\t').collect::<Vec<_>>();
+                let start = fields.first()?.parse::<Ipv4Addr>().ok()?;
+                let end = fields.get(1)?.parse::<Ipv4Addr>().ok()?;
+                let asn = fields.get(2)?.parse::<u32>().ok()?;
+
+                Some((start, end, asn))
+            })
+            .filter(|&(_, _, asn)| asn != 0)
+            .flat_map(|(start, end, asn)| {
+                Ipv4Subnets::new(start, end, 0).map(move |net| (net, asn))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{File, remove_file, write},
+        time::Duration,
+    };
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::mmdb::tests::{database, string};
+
+    #[test]
+    fn load_ip2asn_tsv() {
+        let path = temp_dir().join("fayawall-asn-test.tsv");
+
+        write(
+            &path,
+            "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+             1.0.1.0\t1.0.2.127\t14061\tUS\tDIGITALOCEAN-ASN\n\
+             1.0.4.0\t1.0.7.255\t0\tNone\tNot routed\n",
+        )
+        .unwrap();
+
+        let nets = Asn::load(path.to_str().unwrap(), &[14061]).unwrap();
+
+        remove_file(&path).ok();
+
+        assert_eq!(
+            nets,
+            [
+                ("1.0.1.0/24".parse().unwrap(), 14061),
+                ("1.0.2.0/25".parse().unwrap(), 14061),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_mmdb() {
+        let path = temp_dir().join("fayawall-asn-test.mmdb");
+        let mut leaf = vec![0xe1];
+
+        leaf.extend(string("autonomous_system_number"));
+        leaf.extend_from_slice(&[0xc2, 0x36, 0xed]);
+        write(&path, database(&leaf)).unwrap();
+
+        let nets = Asn::load(path.to_str().unwrap(), &[14061]).unwrap();
+        let none = Asn::load(path.to_str().unwrap(), &[13335]).unwrap();
+
+        remove_file(&path).ok();
+
+        assert_eq!(nets, [("1.0.0.0/8".parse().unwrap(), 14061)]);
+        assert!(none.is_empty());
+        assert!(Asn::load("asn.dat", &[14061]).is_err());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn refresh_switches_generation() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let path = temp_dir().join("fayawall-asn-refresh.tsv");
+        let database = path.to_str().unwrap().to_string();
+
+        write(
+            &path,
+            "192.0.2.0\t192.0.2.255\t14061\tUS\tDIGITALOCEAN-ASN\n",
+        )
+        .unwrap();
+        Asn::stage(&mut ebpf, 0, vec![14061], database.clone(), Origin::Policy).unwrap();
+        write(
+            &path,
+            "198.51.100.0\t198.51.100.255\t14061\tUS\tDIGITALOCEAN-ASN\n",
+        )
+        .unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        Asn::refresh(&mut ebpf);
+
+        assert_eq!(ebpf.generation().unwrap().get().unwrap(), 1);
+        assert_eq!(
+            ebpf.asn_prefixes().unwrap().get(),
+            [("198.51.100.0/24".parse().unwrap(), 14061)]
+        );
+        assert_eq!(ebpf.asn_prefixes_at(0).unwrap().len(), 0);
+
+        remove_file(&path).ok();
+        Asn::refresh(&mut ebpf);
+
+        assert_eq!(ebpf.generation().unwrap().get().unwrap(), 1);
+        assert_eq!(ebpf.asn_prefixes().unwrap().len(), 1);
+        assert_eq!(ebpf.asn_prefixes_at(0).unwrap().len(), 0);
+        assert_eq!(
+            WATCHES.lock().unwrap()[1].as_ref().unwrap().database,
+            database
+        );
+        assert_eq!(WATCHES.lock().unwrap()[1].as_ref().unwrap().modified, None);
+        Policy::clear(&mut ebpf, 1).unwrap();
+    }
+}
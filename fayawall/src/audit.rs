@@ -0,0 +1,314 @@
+use std::{
+    cell::RefCell,
+    env,
+    fmt::{self, Display, Formatter},
+    fs::{OpenOptions, read_to_string},
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    time::SystemTime,
+};
+
+use humantime::format_rfc3339_seconds;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::sink::Sink;
+
+const DEFAULT_N: usize = 20;
+
+thread_local! {
+    static DEFERRED: RefCell<Option<Vec<Entry>>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    Dashboard,
+    Dataplane,
+    Policy,
+    Repl,
+    Replay,
+    State,
+    Timeout,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let origin = match self {
+            Self::Dashboard => "dashboard",
+            Self::Dataplane => "dataplane",
+            Self::Policy => "policy",
+            Self::Repl => "repl",
+            Self::Replay => "replay",
+            Self::State => "state",
+            Self::Timeout => "timeout",
+        };
+
+        write!(f, "{origin}")
+    }
+}
+
+impl FromStr for Origin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dashboard" => Ok(Self::Dashboard),
+            "dataplane" => Ok(Self::Dataplane),
+            "policy" => Ok(Self::Policy),
+            "repl" => Ok(Self::Repl),
+            "replay" => Ok(Self::Replay),
+            "state" => Ok(Self::State),
+            "timeout" => Ok(Self::Timeout),
+            _ => Err(format!("unknown origin `{s}`")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    pub timestamp: String,
+    pub origin: Origin,
+    pub user: Option<String>,
+    pub operation: String,
+    pub args: Vec<String>,
+    pub result: String,
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.timestamp,
+            self.origin,
+            self.user.as_deref().unwrap_or("-"),
+            self.operation,
+            self.args.join(" "),
+            self.result
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    n: usize,
+    operation: Option<String>,
+    origin: Option<Origin>,
+}
+
+impl Query {
+    fn matches(&self, entry: &Entry) -> bool {
+        self.operation
+            .as_ref()
+            .is_none_or(|operation| *operation == entry.operation)
+            && self.origin.is_none_or(|origin| origin == entry.origin)
+    }
+
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut query = Self {
+            n: DEFAULT_N,
+            operation: None,
+            origin: None,
+        };
+        let mut args = args.iter();
+
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--op" => {
+                    query.operation =
+                        Some(args.next().ok_or("`--op` requires a value")?.to_string());
+                }
+                "--origin" => {
+                    query.origin = Some(args.next().ok_or("`--origin` requires a value")?.parse()?);
+                }
+                n => {
+                    query.n = n
+                        .parse()
+                        .map_err(|e| format!("Invalid number of entries `{n}`: {e}"))?;
+                }
+            }
+        }
+
+        Ok(query)
+    }
+}
+
+pub struct Audit;
+
+impl Audit {
+    fn append<P: AsRef<Path>>(path: P, entry: &Entry) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        writeln!(file, "{}", serde_json::to_string(entry)?)
+    }
+
+    pub fn defer<T>(f: impl FnOnce() -> T) -> (T, Vec<Entry>) {
+        let previous = DEFERRED.replace(Some(Vec::new()));
+        let result = f();
+        let entries = DEFERRED.replace(previous).unwrap_or_default();
+
+        (result, entries)
+    }
+
+    pub fn flush(entries: Vec<Entry>) {
+        entries.into_iter().for_each(Self::write);
+    }
+
+    pub fn query(query: &Query) -> io::Result<Vec<Entry>> {
+        match Sink::current().audit_log {
+            Some(audit_log) => Self::read(audit_log, query),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "audit log is disabled",
+            )),
+        }
+    }
+
+    fn read<P: AsRef<Path>>(path: P, query: &Query) -> io::Result<Vec<Entry>> {
+        let log = read_to_string(path)?;
+        let entries = log
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+            .filter(|entry| query.matches(entry))
+            .collect::<Vec<_>>();
+        let skip = entries.len().saturating_sub(query.n);
+
+        Ok(entries.into_iter().skip(skip).collect())
+    }
+
+    pub fn record<T: Display, E: Display>(
+        origin: Origin,
+        operation: &str,
+        args: &[T],
+        result: &Result<(), E>,
+    ) {
+        let entry = Entry {
+            timestamp: format_rfc3339_seconds(SystemTime::now()).to_string(),
+            origin,
+            user: user(),
+            operation: operation.to_string(),
+            args: args.iter().map(ToString::to_string).collect(),
+            result: match result {
+                Ok(()) => "ok".to_string(),
+                Err(e) => e.to_string(),
+            },
+        };
+
+        Self::write(entry);
+    }
+
+    fn write(entry: Entry) {
+        let Some(entry) = DEFERRED.with_borrow_mut(|deferred| match deferred {
+            Some(deferred) => {
+                deferred.push(entry);
+                None
+            }
+            None => Some(entry),
+        }) else {
+            return;
+        };
+        let Some(audit_log) = Sink::current().audit_log else {
+            return;
+        };
+
+        if let Err(e) = Self::append(&audit_log, &entry) {
+            error!(
+                "Audit entry could not be written to `{}`: {e}",
+                audit_log.display()
+            );
+        }
+    }
+}
+
+pub fn user() -> Option<String> {
+    env::var("SUDO_USER").or_else(|_| env::var("USER")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_file};
+
+    use super::*;
+
+    fn entry(origin: Origin, operation: &str) -> Entry {
+        Entry {
+            timestamp: "2025-01-01T00:00:00Z".to_string(),
+            origin,
+            user: None,
+            operation: operation.to_string(),
+            args: vec!["127.0.0.1".to_string()],
+            result: "ok".to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_query() {
+        let expected = Query {
+            n: 5,
+            operation: Some("blacklist.add".to_string()),
+            origin: Some(Origin::Repl),
+        };
+
+        assert_eq!(
+            Query::parse(&["5", "--op", "blacklist.add", "--origin", "repl"]),
+            Ok(expected)
+        );
+        assert!(Query::parse(&["--origin", "invalid"]).is_err());
+        assert!(Query::parse(&["--op"]).is_err());
+    }
+
+    #[test]
+    fn append_and_query_entries() {
+        let path = temp_dir().join("fayawall-audit-test.log");
+        let query = Query::parse(&["--origin", "policy"]).unwrap();
+
+        remove_file(&path).ok();
+        Audit::append(&path, &entry(Origin::Repl, "blacklist.add")).unwrap();
+        Audit::append(&path, &entry(Origin::Policy, "whitelist.add")).unwrap();
+        Audit::append(&path, &entry(Origin::Policy, "blacklist.add")).unwrap();
+
+        let entries = Audit::read(&path, &query).unwrap();
+
+        remove_file(&path).ok();
+        assert_eq!(
+            entries,
+            vec![
+                entry(Origin::Policy, "whitelist.add"),
+                entry(Origin::Policy, "blacklist.add")
+            ]
+        );
+    }
+
+    #[test]
+    fn defer_entries_until_flushed() {
+        let ((), entries) = Audit::defer(|| {
+            Audit::record(
+                Origin::Policy,
+                "blacklist.add",
+                &["127.0.0.1"],
+                &Ok::<(), &str>(()),
+            );
+        });
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].operation, "blacklist.add");
+        assert!(DEFERRED.with_borrow(Option::is_none));
+    }
+
+    #[test]
+    fn query_last_n_entries() {
+        let path = temp_dir().join("fayawall-audit-last-test.log");
+        let query = Query::parse(&["1"]).unwrap();
+
+        remove_file(&path).ok();
+        Audit::append(&path, &entry(Origin::Repl, "blacklist.add")).unwrap();
+        Audit::append(&path, &entry(Origin::Repl, "blacklist.del")).unwrap();
+
+        let entries = Audit::read(&path, &query).unwrap();
+
+        remove_file(&path).ok();
+        assert_eq!(entries, vec![entry(Origin::Repl, "blacklist.del")]);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    fmt::Write as _,
+    fs::{read_to_string, write},
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use aya::Ebpf;
+use common::Setting;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    audit::Origin,
+    ebpf::Init,
+    packet::{self, ETH_P_IPV6, IPPROTO_TCP},
+    sink::Sink,
+    test_run::{self, test_run},
+};
+
+const BLACKLISTED: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 2);
+const DEFAULT_ENTRIES: [u32; 3] = [0, 256, 2047];
+const DEFAULT_REPEAT: u32 = 100_000;
+const DEFAULT_THRESHOLD: f64 = 10.0;
+const ETH_P_ARP: u16 = 0x0806;
+const FILLER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 0);
+const MAX_ENTRIES: u32 = 2047;
+const RATE_LIMITED: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 3);
+const WHITELISTED: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+#[derive(Clone, Copy)]
+enum Scenario {
+    Blacklisted,
+    Ipv6,
+    NonIp,
+    RateLimited,
+    Whitelisted,
+}
+
+impl Scenario {
+    const ALL: [Self; 5] = [
+        Self::Blacklisted,
+        Self::Ipv6,
+        Self::NonIp,
+        Self::RateLimited,
+        Self::Whitelisted,
+    ];
+
+    fn frame(self) -> Vec<u8> {
+        match self {
+            Self::Blacklisted => packet::udp_v4(BLACKLISTED, 53),
+            Self::Ipv6 => packet::ethernet(
+                ETH_P_IPV6,
+                &packet::ipv6(
+                    Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+                    IPPROTO_TCP,
+                    &packet::tcp(40000, 443, 0x02),
+                ),
+            ),
+            Self::NonIp => packet::ethernet(ETH_P_ARP, &[0; 28]),
+            Self::RateLimited => packet::tcp_v4(RATE_LIMITED, 443),
+            Self::Whitelisted => packet::tcp_v4(WHITELISTED, 22),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Blacklisted => "blacklisted",
+            Self::Ipv6 => "ipv6",
+            Self::NonIp => "non_ip",
+            Self::RateLimited => "rate_limited",
+            Self::Whitelisted => "whitelisted",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Sample {
+    pub scenario: String,
+    pub entries: u32,
+    pub ns_per_packet: u32,
+    pub verdict: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Bench {
+    baseline: Option<String>,
+    entries: Vec<u32>,
+    output: Option<String>,
+    repeat: u32,
+    threshold: f64,
+}
+
+impl Bench {
+    fn change(baseline: &[Sample], sample: &Sample) -> Option<(u32, f64)> {
+        let base = baseline
+            .iter()
+            .find(|base| base.scenario == sample.scenario && base.entries == sample.entries)?
+            .ns_per_packet;
+
+        (base > 0).then(|| {
+            let change = (f64::from(sample.ns_per_packet) - f64::from(base)) / f64::from(base);
+
+            (base, change * 100.0)
+        })
+    }
+
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut bench = Self {
+            baseline: None,
+            entries: DEFAULT_ENTRIES.to_vec(),
+            output: None,
+            repeat: DEFAULT_REPEAT,
+            threshold: DEFAULT_THRESHOLD,
+        };
+        let mut args = args.iter();
+
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--baseline" => {
+                    bench.baseline = Some(
+                        args.next()
+                            .ok_or("`--baseline` requires a value")?
+                            .to_string(),
+                    );
+                }
+                "--entries" => {
+                    let entries = args.next().ok_or("`--entries` requires a value")?;
+
+                    bench.entries = entries
+                        .split(',')
+                        .map(|n| match n.parse() {
+                            Ok(n) if n <= MAX_ENTRIES => Ok(n),
+                            Ok(n) => Err(format!("At most {MAX_ENTRIES} entries, got `{n}`")),
+                            Err(e) => Err(format!("Invalid number of entries `{n}`: {e}")),
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                "--output" => {
+                    bench.output = Some(
+                        args.next()
+                            .ok_or("`--output` requires a value")?
+                            .to_string(),
+                    );
+                }
+                "--repeat" => {
+                    let repeat = args.next().ok_or("`--repeat` requires a value")?;
+
+                    bench.repeat = match repeat.parse() {
+                        Ok(0) => return Err("`--repeat` must be at least 1".to_string()),
+                        Ok(repeat) => repeat,
+                        Err(e) => return Err(format!("Invalid repeat count `{repeat}`: {e}")),
+                    };
+                }
+                "--threshold" => {
+                    let threshold = args.next().ok_or("`--threshold` requires a value")?;
+
+                    bench.threshold = threshold
+                        .parse()
+                        .map_err(|e| format!("Invalid threshold `{threshold}`: {e}"))?;
+                }
+                invalid => return Err(format!("Unexpected argument `{invalid}`")),
+            }
+        }
+
+        Ok(bench)
+    }
+
+    fn populate(ebpf: &mut Ebpf, entries: u32) -> anyhow::Result<()> {
+        let fillers = (0..entries)
+            .map(|i| Ipv4Addr::from_bits(FILLER.to_bits() + i).to_string())
+            .collect::<Vec<_>>();
+        let fillers = fillers.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut settings = ebpf.settings()?;
+
+        settings.set(Setting::PacketLimit, 1, Origin::Repl)?;
+        settings.set(Setting::WindowSize, u64::MAX, Origin::Repl)?;
+
+        let mut blacklist = ebpf.blacklist()?;
+
+        blacklist.add(&fillers, Origin::Repl);
+        blacklist.add(&[&BLACKLISTED.to_string()], Origin::Repl);
+
+        let mut whitelist = ebpf.whitelist()?;
+
+        whitelist.add(&fillers, Origin::Repl);
+        whitelist.add(&[&WHITELISTED.to_string()], Origin::Repl);
+
+        Ok(())
+    }
+
+    fn regressions(&self, baseline: &[Sample], samples: &[Sample]) -> Vec<String> {
+        samples
+            .iter()
+            .filter_map(|sample| {
+                let (base, change) = Self::change(baseline, sample)?;
+
+                (change > self.threshold).then(|| {
+                    format!(
+                        "{} with {} entries regressed {change:.1}% ({base} -> {} ns/packet)",
+                        sample.scenario, sample.entries, sample.ns_per_packet
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn report(samples: &[Sample], baseline: Option<&[Sample]>) -> anyhow::Result<String> {
+        let mut report = format!(
+            "{:<15} {:>8} {:>12} {:>12}",
+            "SCENARIO", "ENTRIES", "VERDICT", "NS/PACKET"
+        );
+
+        if baseline.is_some() {
+            write!(report, " {:>12} {:>8}", "BASELINE", "CHANGE")?;
+        }
+        writeln!(report)?;
+
+        for sample in samples {
+            write!(
+                report,
+                "{:<15} {:>8} {:>12} {:>12}",
+                sample.scenario,
+                sample.entries,
+                sample.verdict.trim_start_matches("XDP_"),
+                sample.ns_per_packet
+            )?;
+
+            if let Some(baseline) = baseline {
+                match Self::change(baseline, sample) {
+                    Some((base, change)) => write!(report, " {base:>12} {change:>+7.1}%")?,
+                    None => write!(report, " {:>12} {:>8}", "-", "-")?,
+                }
+            }
+            writeln!(report)?;
+        }
+
+        Ok(report)
+    }
+
+    pub fn run(&self) -> anyhow::Result<Vec<String>> {
+        let samples = Sink::simulation().scope(|| self.sample())?;
+        let baseline = self
+            .baseline
+            .as_ref()
+            .map(|path| {
+                serde_json::from_str::<Vec<Sample>>(&read_to_string(path)?)
+                    .map_err(anyhow::Error::from)
+            })
+            .transpose()?;
+
+        print!("{}", Self::report(&samples, baseline.as_deref())?);
+
+        if let Some(ref path) = self.output {
+            write(path, serde_json::to_string_pretty(&samples)?)?;
+            info!("Benchmark results written to `{path}`");
+        }
+
+        let regressions = baseline
+            .map(|baseline| self.regressions(&baseline, &samples))
+            .unwrap_or_default();
+
+        regressions
+            .iter()
+            .for_each(|regression| warn!("{regression}"));
+
+        Ok(regressions)
+    }
+
+    fn sample(&self) -> anyhow::Result<Vec<Sample>> {
+        let mut samples = Vec::new();
+
+        for &entries in &self.entries {
+            let mut ebpf = Ebpf::detached()?;
+
+            Self::populate(&mut ebpf, entries)?;
+
+            for scenario in Scenario::ALL {
+                let frame = scenario.frame();
+
+                test_run(&ebpf, &frame)?;
+
+                let (action, ns_per_packet) = test_run::bench(&ebpf, &frame, self.repeat)?;
+
+                samples.push(Sample {
+                    scenario: scenario.name().to_string(),
+                    entries,
+                    ns_per_packet,
+                    verdict: test_run::verdict(action).to_string(),
+                });
+            }
+        }
+
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_file};
+
+    use serial_test::serial;
+
+    use super::*;
+
+    fn sample(scenario: &str, entries: u32, ns_per_packet: u32) -> Sample {
+        Sample {
+            scenario: scenario.to_string(),
+            entries,
+            ns_per_packet,
+            verdict: "XDP_PASS".to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_bench() {
+        let expected = Bench {
+            baseline: Some("base.json".to_string()),
+            entries: vec![0, 1024],
+            output: Some("out.json".to_string()),
+            repeat: 10,
+            threshold: 5.0,
+        };
+
+        assert_eq!(
+            Bench::parse(&[
+                "--baseline",
+                "base.json",
+                "--entries",
+                "0,1024",
+                "--output",
+                "out.json",
+                "--repeat",
+                "10",
+                "--threshold",
+                "5"
+            ]),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn parse_invalid_bench() {
+        assert!(Bench::parse(&["--entries", "4096"]).is_err());
+        assert!(Bench::parse(&["--entries", "a"]).is_err());
+        assert!(Bench::parse(&["--repeat", "0"]).is_err());
+        assert!(Bench::parse(&["--threshold"]).is_err());
+        assert!(Bench::parse(&["invalid"]).is_err());
+    }
+
+    #[test]
+    fn detect_regressions() {
+        let bench = Bench::parse(&["--threshold", "10"]).unwrap();
+        let baseline = [sample("ipv6", 0, 100), sample("whitelisted", 0, 100)];
+        let samples = [
+            sample("ipv6", 0, 109),
+            sample("whitelisted", 0, 150),
+            sample("whitelisted", 256, 500),
+        ];
+
+        assert_eq!(
+            bench.regressions(&baseline, &samples),
+            ["whitelisted with 0 entries regressed 50.0% (100 -> 150 ns/packet)"]
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn bench_writes_results() {
+        let output = temp_dir().join("fayawall-bench-test.json");
+        let bench = Bench::parse(&[
+            "--entries",
+            "0,16",
+            "--repeat",
+            "10",
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        bench.run().unwrap();
+
+        let samples: Vec<Sample> = serde_json::from_str(&read_to_string(&output).unwrap()).unwrap();
+
+        remove_file(&output).ok();
+
+        let verdicts = samples
+            .iter()
+            .filter(|sample| sample.entries == 16)
+            .map(|sample| (sample.scenario.as_str(), sample.verdict.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(samples.len(), 10);
+        assert_eq!(
+            verdicts,
+            [
+                ("blacklisted", "XDP_DROP"),
+                ("ipv6", "XDP_PASS"),
+                ("non_ip", "XDP_PASS"),
+                ("rate_limited", "XDP_DROP"),
+                ("whitelisted", "XDP_PASS"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs::read_to_string;
+
+use anyhow::anyhow;
+use aya::Ebpf;
+use common::Setting;
+use ipnet::Ipv4Net;
+
+use crate::{
+    audit::{Audit, Origin},
+    ebpf::Init,
+};
+
+const BUILTIN: [&str; 14] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+];
+
+pub struct Bogon;
+
+impl Bogon {
+    pub fn enable(ebpf: &mut Ebpf, generation: u32, origin: Origin) -> anyhow::Result<()> {
+        if ebpf.bogons_at(generation)?.len() == 0 {
+            Self::update(ebpf, None, generation, origin)?;
+        }
+
+        ebpf.settings_at(generation)?
+            .set(Setting::Bogon, 1, origin)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: Option<&str>) -> anyhow::Result<Vec<Ipv4Net>> {
+        match path {
+            Some(path) => Self::parse(&read_to_string(path)?),
+            None => Self::parse(&BUILTIN.join("\n")),
+        }
+    }
+
+    fn parse(list: &str) -> anyhow::Result<Vec<Ipv4Net>> {
+        list.lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.parse::<Ipv4Net>()
+                    .map(|net| net.trunc())
+                    .map_err(|e| anyhow!("Invalid bogon prefix `{line}`: {e}"))
+            })
+            .collect()
+    }
+
+    pub fn update(
+        ebpf: &mut Ebpf,
+        path: Option<&str>,
+        generation: u32,
+        origin: Origin,
+    ) -> anyhow::Result<()> {
+        let result =
+            Self::load(path).and_then(|nets| Ok(ebpf.bogons_at(generation)?.load(&nets)?));
+
+        Audit::record(origin, "bogon.load", &[path.unwrap_or("builtin")], &result);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bogon_list() {
+        let nets = Bogon::parse(
+            "# last updated 1700000000 (Tue Nov 14 22:13:20 2023 GMT)\n\
+             0.0.0.0/8\n\
+             \n\
+             10.0.0.1/8 # not truncated upstream\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            nets,
+            [
+                "0.0.0.0/8".parse::<Ipv4Net>().unwrap(),
+                "10.0.0.0/8".parse().unwrap(),
+            ]
+        );
+        assert_eq!(Bogon::load(None).unwrap().len(), BUILTIN.len());
+        assert!(Bogon::parse("10.0.0.0/33").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use aya::{Ebpf, maps::MapData};
+use humantime::format_duration;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{error, info, warn};
+
+use crate::{
+    audit::{Audit, Origin},
+    ebpf::Init,
+    maps::generation::Generation,
+    policy::Policy,
+    state::State,
+};
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Confirmed,
+    Pending,
+    Reverted,
+}
+
+pub struct Commit {
+    rollback: u32,
+    status: Arc<Mutex<Status>>,
+    timer: JoinHandle<()>,
+}
+
+impl Commit {
+    pub fn confirm(self, ebpf: &mut Ebpf) -> anyhow::Result<()> {
+        {
+            let mut status = self.status.lock().unwrap();
+
+            if *status == Status::Reverted {
+                warn!("Changes were already reverted before `commit`");
+                return self.finish(ebpf);
+            }
+
+            *status = Status::Confirmed;
+        }
+
+        self.timer.abort();
+
+        let result = Policy::clear(ebpf, self.rollback);
+
+        Audit::record(
+            Origin::Repl,
+            "commit.confirm",
+            &[] as &[&str],
+            &result.as_ref().map(|_| ()),
+        );
+        result?;
+        info!("Changes confirmed");
+
+        Ok(())
+    }
+
+    pub fn confirmed(ebpf: &mut Ebpf, timeout: Duration) -> anyhow::Result<Self> {
+        let active = ebpf.generation()?.get()?;
+        let rollback = active ^ 1;
+        let result =
+            Policy::clear(ebpf, rollback).and_then(|()| Policy::copy(ebpf, active, rollback));
+
+        Audit::record(
+            Origin::Repl,
+            "commit.confirmed",
+            &[format_duration(timeout)],
+            &result.as_ref().map(|_| ()),
+        );
+        result?;
+        info!(
+            "Changes will be reverted in {} unless confirmed with `commit`",
+            format_duration(timeout)
+        );
+
+        let status = Arc::new(Mutex::new(Status::Pending));
+        let timer = tokio::spawn(Self::revert(
+            ebpf.generation_handle()?,
+            rollback,
+            timeout,
+            status.clone(),
+        ));
+
+        Ok(Self {
+            rollback,
+            status,
+            timer,
+        })
+    }
+
+    pub fn finish(&self, ebpf: &mut Ebpf) -> anyhow::Result<()> {
+        Policy::clear(ebpf, self.rollback ^ 1)?;
+        State::compact(ebpf)
+    }
+
+    async fn revert(
+        mut generation: Generation<MapData>,
+        rollback: u32,
+        timeout: Duration,
+        status: Arc<Mutex<Status>>,
+    ) {
+        sleep(timeout).await;
+
+        loop {
+            {
+                let mut status = status.lock().unwrap();
+
+                if *status != Status::Pending {
+                    return;
+                }
+
+                let result = generation.set(rollback);
+
+                Audit::record(Origin::Timeout, "commit.revert", &[rollback], &result);
+
+                match result {
+                    Ok(()) => {
+                        *status = Status::Reverted;
+                        warn!("Changes not confirmed in time and reverted");
+                        return;
+                    }
+                    Err(e) => error!("Changes could not be reverted, retrying: {e}"),
+                }
+            }
+
+            sleep(RETRY_INTERVAL).await;
+        }
+    }
+
+    pub fn reverted(&self) -> bool {
+        *self.status.lock().unwrap() == Status::Reverted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aya::Ebpf;
+    use serial_test::serial;
+    use tokio::time::sleep;
+
+    use super::Commit;
+    use crate::{audit::Origin, ebpf::Init};
+
+    #[serial]
+    #[tokio::test]
+    async fn confirm_commit_keeps_changes() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let commit = Commit::confirmed(&mut ebpf, Duration::from_secs(60)).unwrap();
+
+        ebpf.blacklist().unwrap().add(&["127.0.0.1"], Origin::Repl);
+        commit.confirm(&mut ebpf).unwrap();
+
+        assert_eq!(ebpf.blacklist().unwrap().to_string(), "127.0.0.1");
+        assert_eq!(ebpf.blacklist_at(1).unwrap().to_string(), "");
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn revert_commit_restores_previous_state() {
+        let mut ebpf = Ebpf::detached().unwrap();
+
+        ebpf.whitelist().unwrap().add(&["10.0.0.1"], Origin::Repl);
+
+        let commit = Commit::confirmed(&mut ebpf, Duration::ZERO).unwrap();
+
+        ebpf.blacklist().unwrap().add(&["127.0.0.1"], Origin::Repl);
+        ebpf.whitelist().unwrap().del(&["10.0.0.1"], Origin::Repl);
+        sleep(Duration::from_millis(10)).await;
+
+        assert!(commit.reverted());
+        assert_eq!(ebpf.generation().unwrap().get().unwrap(), 1);
+        assert_eq!(ebpf.blacklist().unwrap().to_string(), "");
+        assert_eq!(ebpf.whitelist().unwrap().to_string(), "10.0.0.1");
+        assert!(format!("{:#}", ebpf.whitelist().unwrap()).contains("origin=repl"));
+
+        commit.finish(&mut ebpf).unwrap();
+
+        assert_eq!(ebpf.blacklist_at(0).unwrap().to_string(), "");
+    }
+}
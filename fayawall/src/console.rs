@@ -0,0 +1,54 @@
+use std::{
+    io::{Write, stdin, stdout},
+    sync::mpsc,
+    thread,
+};
+
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+pub struct Console {
+    lines: UnboundedReceiver<String>,
+    pending: bool,
+    ready: mpsc::Sender<()>,
+}
+
+impl Console {
+    pub async fn read_line(&mut self) -> Option<String> {
+        if !self.pending {
+            self.ready.send(()).ok()?;
+            self.pending = true;
+        }
+
+        let line = self.lines.recv().await;
+
+        self.pending = false;
+
+        line
+    }
+
+    pub fn spawn() -> Self {
+        let (ready, ready_rx) = mpsc::channel();
+        let (tx, lines) = unbounded_channel();
+
+        thread::spawn(move || {
+            for () in ready_rx {
+                let mut line = String::new();
+
+                print!("fayawall> ");
+                stdout().flush().ok();
+
+                match stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) if tx.send(line).is_err() => break,
+                    Ok(_) => {}
+                }
+            }
+        });
+
+        Self {
+            lines,
+            pending: false,
+            ready,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use aya::Ebpf;
+use common::{Counter, SourceStat};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, Paragraph, Row, Table, TableState},
+};
+
+use crate::{
+    audit::Origin,
+    ebpf::Init,
+    events::Recent,
+    lockout::Lockout,
+    log::Log,
+    maps::{ipv4_list::Ipv4List, source_stats::TopBy},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const TOP_N: usize = 20;
+
+pub struct Dashboard<'a> {
+    counts: [u64; Counter::LEN as usize],
+    ebpf: &'a mut Ebpf,
+    events: &'a Recent,
+    housekeep: &'a mut dyn FnMut(&mut Ebpf),
+    occupancy: Vec<(&'static str, (usize, u32))>,
+    rates: [f64; Counter::LEN as usize],
+    sampled_at: Option<Instant>,
+    sources: Vec<(Ipv4Addr, SourceStat)>,
+    state: TableState,
+    status: String,
+}
+
+impl<'a> Dashboard<'a> {
+    fn draw(&mut self, frame: &mut Frame) {
+        let [summary_area, middle_area, events_area, help_area] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(8),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [reasons_area, sources_area] =
+            Layout::horizontal([Constraint::Length(40), Constraint::Min(0)]).areas(middle_area);
+
+        let (pass_rate, drop_rate) = Counter::ALL.iter().zip(self.rates).fold(
+            (0.0, 0.0),
+            |(pass, drop), (counter, rate)| {
+                if counter.is_drop() {
+                    (pass, drop + rate)
+                } else {
+                    (pass + rate, drop)
+                }
+            },
+        );
+        let occupancy = self
+            .occupancy
+            .iter()
+            .map(|(name, (len, capacity))| format!("{name} {len}/{capacity}"))
+            .collect::<Vec<_>>()
+            .join("   ");
+        let summary = Paragraph::new(vec![
+            Line::from(format!(
+                "pass {pass_rate:.0} pkt/s   drop {drop_rate:.0} pkt/s"
+            )),
+            Line::from(occupancy),
+        ])
+        .block(Block::bordered().title("fayawall"));
+
+        let reasons = Table::new(
+            Counter::ALL
+                .iter()
+                .enumerate()
+                .filter(|(_, counter)| counter.is_drop())
+                .map(|(i, counter)| {
+                    Row::new(vec![
+                        counter.name().to_string(),
+                        format!("{:.0}/s", self.rates[i]),
+                        self.counts[i].to_string(),
+                    ])
+                }),
+            [
+                Constraint::Min(14),
+                Constraint::Length(10),
+                Constraint::Length(12),
+            ],
+        )
+        .header(Row::new(["REASON", "RATE", "TOTAL"]))
+        .block(Block::bordered().title("Drops"));
+
+        let sources = Table::new(
+            self.sources.iter().map(|(addr, stat)| {
+                Row::new(vec![
+                    addr.to_string(),
+                    stat.packets.to_string(),
+                    stat.bytes.to_string(),
+                    stat.drops.to_string(),
+                ])
+            }),
+            [
+                Constraint::Length(15),
+                Constraint::Length(12),
+                Constraint::Length(14),
+                Constraint::Length(12),
+            ],
+        )
+        .header(Row::new(["SOURCE", "PACKETS", "BYTES", "DROPS"]))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title("Top sources"));
+
+        let events = List::new(
+            self.events
+                .lock()
+                .unwrap()
+                .iter()
+                .rev()
+                .map(|record| record.to_string())
+                .collect::<Vec<_>>(),
+        )
+        .block(Block::bordered().title("Recent events"));
+
+        let help = Paragraph::new(format!(
+            "q quit   ↑/↓ select   b blacklist   w whitelist   {}",
+            self.status
+        ));
+
+        frame.render_widget(summary, summary_area);
+        frame.render_widget(reasons, reasons_area);
+        frame.render_stateful_widget(sources, sources_area, &mut self.state);
+        frame.render_widget(events, events_area);
+        frame.render_widget(help, help_area);
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            if self
+                .sampled_at
+                .is_none_or(|sampled_at| sampled_at.elapsed() >= REFRESH_INTERVAL)
+            {
+                (self.housekeep)(self.ebpf);
+                self.sample()?;
+            }
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(POLL_INTERVAL)? {
+                continue;
+            }
+
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Char('j') | KeyCode::Down => self.state.select_next(),
+                    KeyCode::Char('k') | KeyCode::Up => self.state.select_previous(),
+                    KeyCode::Char('b') => self.list(|ebpf| ebpf.blacklist(), "blacklist")?,
+                    KeyCode::Char('w') => self.list(|ebpf| ebpf.whitelist(), "whitelist")?,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn list<F>(&mut self, list: F, label: &str) -> anyhow::Result<()>
+    where
+        F: for<'b> FnOnce(&'b mut Ebpf) -> Result<Ipv4List<'b>, aya::EbpfError>,
+    {
+        let Some(&(addr, _)) = self.state.selected().and_then(|i| self.sources.get(i)) else {
+            self.status = "No source selected".to_string();
+            return Ok(());
+        };
+
+        let addr = addr.to_string();
+
+        if label == "blacklist" && Lockout::guard(self.ebpf, &[&addr])?.is_empty() {
+            self.status = format!("{addr} is a management address");
+            return Ok(());
+        }
+
+        list(self.ebpf)?.add(&[&addr], Origin::Dashboard);
+        self.status = format!("{addr} added to {label}");
+
+        Ok(())
+    }
+
+    pub fn run(
+        ebpf: &'a mut Ebpf,
+        events: &'a Recent,
+        housekeep: &'a mut dyn FnMut(&mut Ebpf),
+    ) -> anyhow::Result<()> {
+        let mut dashboard = Self {
+            counts: [0; Counter::LEN as usize],
+            ebpf,
+            events,
+            housekeep,
+            occupancy: Vec::new(),
+            rates: [0.0; Counter::LEN as usize],
+            sampled_at: None,
+            sources: Vec::new(),
+            state: TableState::default(),
+            status: String::new(),
+        };
+
+        Log::stdout(false);
+
+        let mut terminal = ratatui::init();
+        let result = dashboard.event_loop(&mut terminal);
+
+        ratatui::restore();
+        Log::stdout(true);
+
+        result
+    }
+
+    fn sample(&mut self) -> anyhow::Result<()> {
+        let counters = self.ebpf.counters()?;
+        let elapsed = self
+            .sampled_at
+            .map(|sampled_at| sampled_at.elapsed().as_secs_f64());
+
+        for (i, &counter) in Counter::ALL.iter().enumerate() {
+            let count = counters.get(counter)?;
+
+            self.rates[i] = elapsed.map_or(0.0, |secs| {
+                count.saturating_sub(self.counts[i]) as f64 / secs
+            });
+            self.counts[i] = count;
+        }
+
+        self.sources = self.ebpf.source_stats()?.top(TOP_N, TopBy::Drops);
+        self.occupancy = vec![
+            ("blacklist", self.ebpf.blacklist()?.occupancy()?),
+            ("whitelist", self.ebpf.whitelist()?.occupancy()?),
+            ("sources", self.ebpf.source_stats()?.occupancy()?),
+        ];
+        self.sampled_at = Some(Instant::now());
+
+        Ok(())
+    }
+}
@@ -1,70 +1,425 @@
+use std::{io, mem, os::fd::AsFd, sync::Mutex};
+
 use aya::{
     Ebpf, EbpfError,
-    maps::HashMap,
-    programs::{Xdp, XdpFlags},
+    maps::{Array, HashMap, LpmTrie, Map, MapData, PerCpuArray, PerCpuHashMap},
+    programs::{
+        ProgramError, SchedClassifier, TcAttachType, Xdp, XdpFlags,
+        links::{FdLink, PinnedLink},
+        tc::{self, NlOptions, TcAttachOptions},
+        xdp::{XdpLink, XdpLinkId},
+    },
 };
 use aya_log::EbpfLogger;
 use clap::Parser;
-use tracing::warn;
+use tracing::{info, warn};
 
 use crate::{
     arg::Arg,
-    maps::{ipv4_list::Ipv4List, rate_limit_settings::RateLimitSettings},
+    maps::{
+        asn_prefixes::AsnPrefixes, bans::Bans, bogons::Bogons, conntrack::Conntrack,
+        counters::Counters, generation::Generation, geo_db::GeoDb, geo_drops::GeoDrops,
+        geo_rules::GeoRules, ipv4_list::Ipv4List, port_set::PortSet, prefix_list::PrefixList,
+        rate_limit_windows::RateLimitWindows, settings::Settings, source_stats::SourceStats,
+        tcp_anomaly_logs::TcpAnomalyLogs,
+    },
+    pin::Pin,
 };
 
+const CONNTRACK: &str = "conntrack";
+const OBJECT: &[u8] = aya::include_bytes_aligned!(concat!(env!("OUT_DIR"), "/fayawall"));
+
+static LINK: Mutex<Option<XdpLinkId>> = Mutex::new(None);
+
 pub trait Init {
+    fn asn_prefixes(&'_ mut self) -> Result<AsnPrefixes<'_>, EbpfError>;
+    fn asn_prefixes_at(&'_ mut self, generation: u32) -> Result<AsnPrefixes<'_>, EbpfError>;
+    fn bans(&'_ mut self) -> Result<Bans<'_>, EbpfError>;
     fn blacklist(&'_ mut self) -> Result<Ipv4List<'_>, EbpfError>;
-    fn init() -> Result<Ebpf, EbpfError>;
-    fn rate_limit_settings(&'_ mut self) -> Result<RateLimitSettings<'_>, EbpfError>;
+    fn blacklist_at(&'_ mut self, generation: u32) -> Result<Ipv4List<'_>, EbpfError>;
+    fn bogons(&'_ mut self) -> Result<Bogons<'_>, EbpfError>;
+    fn bogons_at(&'_ mut self, generation: u32) -> Result<Bogons<'_>, EbpfError>;
+    fn conntrack(&'_ mut self) -> Result<Conntrack<'_>, EbpfError>;
+    fn counters(&'_ mut self) -> Result<Counters<'_>, EbpfError>;
+    fn detached() -> anyhow::Result<Ebpf>;
+    fn generation(&'_ mut self) -> Result<Generation<&'_ mut MapData>, EbpfError>;
+    fn generation_handle(&self) -> anyhow::Result<Generation<MapData>>;
+    fn geo_db(&'_ mut self) -> Result<GeoDb<'_>, EbpfError>;
+    fn geo_db_at(&'_ mut self, generation: u32) -> Result<GeoDb<'_>, EbpfError>;
+    fn geo_drops(&'_ mut self) -> Result<GeoDrops<'_>, EbpfError>;
+    fn geo_rules(&'_ mut self) -> Result<GeoRules<'_>, EbpfError>;
+    fn geo_rules_at(&'_ mut self, generation: u32) -> Result<GeoRules<'_>, EbpfError>;
+    fn init() -> anyhow::Result<Ebpf>;
+    fn management(&'_ mut self) -> Result<PrefixList<'_>, EbpfError>;
+    fn rate_limit_windows(&'_ mut self) -> Result<RateLimitWindows<'_>, EbpfError>;
+    fn settings(&'_ mut self) -> Result<Settings<'_>, EbpfError>;
+    fn settings_at(&'_ mut self, generation: u32) -> Result<Settings<'_>, EbpfError>;
+    fn source_stats(&'_ mut self) -> Result<SourceStats<'_>, EbpfError>;
+    fn syn_ports(&'_ mut self) -> Result<PortSet<'_>, EbpfError>;
+    fn syn_ports_at(&'_ mut self, generation: u32) -> Result<PortSet<'_>, EbpfError>;
+    fn tcp_anomaly_logs(&'_ mut self) -> Result<TcpAnomalyLogs<'_>, EbpfError>;
+    fn trace(&'_ mut self) -> Result<PrefixList<'_>, EbpfError>;
+    fn trap_ports(&'_ mut self) -> Result<PortSet<'_>, EbpfError>;
+    fn trap_ports_at(&'_ mut self, generation: u32) -> Result<PortSet<'_>, EbpfError>;
     fn whitelist(&'_ mut self) -> Result<Ipv4List<'_>, EbpfError>;
+    fn whitelist_at(&'_ mut self, generation: u32) -> Result<Ipv4List<'_>, EbpfError>;
 }
 
 impl Init for Ebpf {
+    fn asn_prefixes(&'_ mut self) -> Result<AsnPrefixes<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.asn_prefixes_at(generation)
+    }
+
+    fn asn_prefixes_at(&'_ mut self, generation: u32) -> Result<AsnPrefixes<'_>, EbpfError> {
+        let map = self.map_mut("ASN").expect("BPF map ASN not found");
+        let lpm_trie = LpmTrie::try_from(map)?;
+
+        Ok(AsnPrefixes::new(lpm_trie, generation))
+    }
+
+    fn bans(&'_ mut self) -> Result<Bans<'_>, EbpfError> {
+        let map = self.map_mut("BANS").expect("BPF map BANS not found");
+        let hash_map = HashMap::try_from(map)?;
+
+        Ok(Bans(hash_map))
+    }
+
     fn blacklist(&'_ mut self) -> Result<Ipv4List<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.blacklist_at(generation)
+    }
+
+    fn blacklist_at(&'_ mut self, generation: u32) -> Result<Ipv4List<'_>, EbpfError> {
         let map = self
             .map_mut("BLACKLIST")
             .expect("BPF map BLACKLIST not found");
         let hash_map = HashMap::try_from(map)?;
 
-        Ok(Ipv4List::new("blacklist", hash_map))
+        Ok(Ipv4List::new("blacklist", hash_map, generation))
     }
 
-    fn init() -> Result<Ebpf, EbpfError> {
-        let mut ebpf = Ebpf::load(aya::include_bytes_aligned!(concat!(
-            env!("OUT_DIR"),
-            "/fayawall"
-        )))?;
+    fn bogons(&'_ mut self) -> Result<Bogons<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
 
-        if let Err(e) = EbpfLogger::init(&mut ebpf) {
-            warn!("eBPF logger failed to initialize: {e}");
-        }
+        self.bogons_at(generation)
+    }
+
+    fn bogons_at(&'_ mut self, generation: u32) -> Result<Bogons<'_>, EbpfError> {
+        let map = self.map_mut("BOGONS").expect("BPF map BOGONS not found");
+        let lpm_trie = LpmTrie::try_from(map)?;
+
+        Ok(Bogons::new(lpm_trie, generation))
+    }
+
+    fn conntrack(&'_ mut self) -> Result<Conntrack<'_>, EbpfError> {
+        let map = self
+            .map_mut("CONNTRACK")
+            .expect("BPF map CONNTRACK not found");
+        let hash_map = HashMap::try_from(map)?;
+
+        Ok(Conntrack(hash_map))
+    }
+
+    fn counters(&'_ mut self) -> Result<Counters<'_>, EbpfError> {
+        let map = self
+            .map_mut("COUNTERS")
+            .expect("BPF map COUNTERS not found");
+        let per_cpu_array = PerCpuArray::try_from(map)?;
+
+        Ok(Counters(per_cpu_array))
+    }
+
+    fn detached() -> anyhow::Result<Ebpf> {
+        load(OBJECT)
+    }
+
+    fn generation(&'_ mut self) -> Result<Generation<&'_ mut MapData>, EbpfError> {
+        let map = self
+            .map_mut("GENERATION")
+            .expect("BPF map GENERATION not found");
+        let array = Array::try_from(map)?;
+
+        Ok(Generation(array))
+    }
+
+    fn generation_handle(&self) -> anyhow::Result<Generation<MapData>> {
+        let map = self
+            .map("GENERATION")
+            .expect("BPF map GENERATION not found");
+        let Map::Array(map_data) = map else {
+            anyhow::bail!("BPF map GENERATION is not an array");
+        };
+        let fd = map_data.fd().as_fd().try_clone_to_owned()?;
+        let array = Array::try_from(Map::Array(MapData::from_fd(fd)?))?;
+
+        Ok(Generation(array))
+    }
+
+    fn geo_db(&'_ mut self) -> Result<GeoDb<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.geo_db_at(generation)
+    }
+
+    fn geo_db_at(&'_ mut self, generation: u32) -> Result<GeoDb<'_>, EbpfError> {
+        let map = self.map_mut("GEO").expect("BPF map GEO not found");
+        let lpm_trie = LpmTrie::try_from(map)?;
 
-        let prog: &mut Xdp = ebpf
-            .program_mut("xdp_firewall")
-            .expect("BPF program xdp_firewall not found")
-            .try_into()?;
+        Ok(GeoDb::new(lpm_trie, generation))
+    }
+
+    fn geo_drops(&'_ mut self) -> Result<GeoDrops<'_>, EbpfError> {
+        let map = self
+            .map_mut("GEO_DROPS")
+            .expect("BPF map GEO_DROPS not found");
+        let hash_map = PerCpuHashMap::try_from(map)?;
+
+        Ok(GeoDrops(hash_map))
+    }
 
-        prog.load()?;
-        prog.attach(&Arg::parse().iface, XdpFlags::SKB_MODE)?;
+    fn geo_rules(&'_ mut self) -> Result<GeoRules<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.geo_rules_at(generation)
+    }
+
+    fn geo_rules_at(&'_ mut self, generation: u32) -> Result<GeoRules<'_>, EbpfError> {
+        let map = self
+            .map_mut("GEO_RULES")
+            .expect("BPF map GEO_RULES not found");
+        let hash_map = HashMap::try_from(map)?;
+
+        Ok(GeoRules::new(hash_map, generation))
+    }
+
+    fn init() -> anyhow::Result<Ebpf> {
+        let Arg { iface, pin, .. } = Arg::parse();
+        let pins = Pin::new(&iface);
+        let mut ebpf = if pin {
+            pins.prune(OBJECT)?;
+            setup(pins.load(OBJECT)?)?
+        } else {
+            load(OBJECT)?
+        };
+
+        let link = match PinnedLink::from_pin(pins.link()) {
+            Ok(link) if pin => {
+                info!("xdp_firewall replaced on pinned link for {iface}");
+                XdpLink::try_from(FdLink::from(link))?
+            }
+            _ => {
+                let prog = program(&mut ebpf)?;
+                let link_id = prog.attach(&iface, XdpFlags::SKB_MODE)?;
+                let link = prog.take_link(link_id)?;
+
+                if pin {
+                    let link = FdLink::try_from(link)?.pin(pins.link())?;
+
+                    XdpLink::try_from(FdLink::from(link))?
+                } else {
+                    link
+                }
+            }
+        };
+
+        let link_id = program(&mut ebpf)?.attach_to_link(link)?;
+
+        *LINK.lock().unwrap() = Some(link_id);
+
+        if let Err(e) = egress(&mut ebpf, &iface, pin) {
+            warn!("conntrack could not be attached to {iface} egress: {e}");
+        }
 
         Ok(ebpf)
     }
 
-    fn rate_limit_settings(&'_ mut self) -> Result<RateLimitSettings<'_>, EbpfError> {
+    fn management(&'_ mut self) -> Result<PrefixList<'_>, EbpfError> {
+        let map = self
+            .map_mut("MANAGEMENT")
+            .expect("BPF map MANAGEMENT not found");
+        let lpm_trie = LpmTrie::try_from(map)?;
+
+        Ok(PrefixList::new("management", lpm_trie))
+    }
+
+    fn rate_limit_windows(&'_ mut self) -> Result<RateLimitWindows<'_>, EbpfError> {
         let map = self
-            .map_mut("RATE_LIMIT_SETTINGS")
-            .expect("BPF map RATE_LIMIT_SETTINGS not found");
+            .map_mut("RATE_LIMIT_WINDOWS")
+            .expect("BPF map RATE_LIMIT_WINDOWS not found");
         let hash_map = HashMap::try_from(map)?;
 
-        Ok(RateLimitSettings(hash_map))
+        Ok(RateLimitWindows(hash_map))
+    }
+
+    fn settings(&'_ mut self) -> Result<Settings<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.settings_at(generation)
+    }
+
+    fn settings_at(&'_ mut self, generation: u32) -> Result<Settings<'_>, EbpfError> {
+        let map = self
+            .map_mut("SETTINGS")
+            .expect("BPF map SETTINGS not found");
+        let hash_map = HashMap::try_from(map)?;
+
+        Ok(Settings::new(hash_map, generation))
+    }
+
+    fn source_stats(&'_ mut self) -> Result<SourceStats<'_>, EbpfError> {
+        let map = self
+            .map_mut("SOURCE_STATS")
+            .expect("BPF map SOURCE_STATS not found");
+        let hash_map = HashMap::try_from(map)?;
+
+        Ok(SourceStats(hash_map))
+    }
+
+    fn syn_ports(&'_ mut self) -> Result<PortSet<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.syn_ports_at(generation)
+    }
+
+    fn syn_ports_at(&'_ mut self, generation: u32) -> Result<PortSet<'_>, EbpfError> {
+        let map = self
+            .map_mut("SYN_PORTS")
+            .expect("BPF map SYN_PORTS not found");
+        let hash_map = HashMap::try_from(map)?;
+
+        Ok(PortSet::new("syn_ports", hash_map, generation))
+    }
+
+    fn tcp_anomaly_logs(&'_ mut self) -> Result<TcpAnomalyLogs<'_>, EbpfError> {
+        let map = self
+            .map_mut("TCP_ANOMALY_LOGS")
+            .expect("BPF map TCP_ANOMALY_LOGS not found");
+        let per_cpu_array = PerCpuArray::try_from(map)?;
+
+        Ok(TcpAnomalyLogs(per_cpu_array))
+    }
+
+    fn trace(&'_ mut self) -> Result<PrefixList<'_>, EbpfError> {
+        let map = self.map_mut("TRACE").expect("BPF map TRACE not found");
+        let lpm_trie = LpmTrie::try_from(map)?;
+
+        Ok(PrefixList::new("trace", lpm_trie))
+    }
+
+    fn trap_ports(&'_ mut self) -> Result<PortSet<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.trap_ports_at(generation)
+    }
+
+    fn trap_ports_at(&'_ mut self, generation: u32) -> Result<PortSet<'_>, EbpfError> {
+        let map = self
+            .map_mut("TRAP_PORTS")
+            .expect("BPF map TRAP_PORTS not found");
+        let hash_map = HashMap::try_from(map)?;
+
+        Ok(PortSet::new("trap_ports", hash_map, generation))
     }
 
     fn whitelist(&'_ mut self) -> Result<Ipv4List<'_>, EbpfError> {
+        let generation = self.generation()?.get()?;
+
+        self.whitelist_at(generation)
+    }
+
+    fn whitelist_at(&'_ mut self, generation: u32) -> Result<Ipv4List<'_>, EbpfError> {
         let map = self
             .map_mut("WHITELIST")
             .expect("BPF map WHITELIST not found");
         let hash_map = HashMap::try_from(map)?;
 
-        Ok(Ipv4List::new("whitelist", hash_map))
+        Ok(Ipv4List::new("whitelist", hash_map, generation))
+    }
+}
+
+fn classifier(ebpf: &mut Ebpf) -> Result<&mut SchedClassifier, ProgramError> {
+    ebpf.program_mut(CONNTRACK)
+        .expect("BPF program conntrack not found")
+        .try_into()
+}
+
+pub fn detach_egress(iface: &str) -> io::Result<()> {
+    match tc::qdisc_detach_program(iface, TcAttachType::Egress, CONNTRACK) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn egress(ebpf: &mut Ebpf, iface: &str, persistent: bool) -> anyhow::Result<()> {
+    if let Err(e) = tc::qdisc_add_clsact(iface)
+        && e.kind() != io::ErrorKind::AlreadyExists
+    {
+        return Err(e.into());
+    }
+
+    detach_egress(iface)?;
+
+    let prog = classifier(ebpf)?;
+    let link_id = prog.attach_with_options(
+        iface,
+        TcAttachType::Egress,
+        TcAttachOptions::Netlink(NlOptions::default()),
+    )?;
+
+    if persistent {
+        mem::forget(prog.take_link(link_id)?);
+    }
+
+    Ok(())
+}
+
+pub fn load(object: &[u8]) -> anyhow::Result<Ebpf> {
+    let scratch = Pin::scratch();
+    let ebpf = scratch.load(object).and_then(setup);
+
+    if let Err(e) = scratch.remove()
+        && ebpf.is_ok()
+    {
+        warn!("Scratch pins could not be removed: {e}");
     }
+
+    ebpf
+}
+
+fn setup(mut ebpf: Ebpf) -> anyhow::Result<Ebpf> {
+    if let Err(e) = EbpfLogger::init(&mut ebpf) {
+        warn!("eBPF logger failed to initialize: {e}");
+    }
+
+    program(&mut ebpf)?.load()?;
+    classifier(&mut ebpf)?.load()?;
+
+    Ok(ebpf)
+}
+
+fn program(ebpf: &mut Ebpf) -> Result<&mut Xdp, ProgramError> {
+    ebpf.program_mut("xdp_firewall")
+        .expect("BPF program xdp_firewall not found")
+        .try_into()
+}
+
+pub fn relink(from: &mut Ebpf, to: &mut Ebpf) -> anyhow::Result<()> {
+    let mut link_id = LINK.lock().unwrap();
+    let link = match link_id.take() {
+        Some(id) => program(from)?.take_link(id)?,
+        None => anyhow::bail!("xdp_firewall is not attached"),
+    };
+
+    *link_id = Some(program(to)?.attach_to_link(link)?);
+
+    let Arg { iface, pin, .. } = Arg::parse();
+
+    if let Err(e) = egress(to, &iface, pin) {
+        warn!("conntrack could not be attached to {iface} egress: {e}");
+    }
+
+    Ok(())
 }
@@ -0,0 +1,189 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+    io, mem,
+    net::Ipv4Addr,
+    ptr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use aya::{Ebpf, maps::RingBuf};
+use common::{Event, EventKind};
+use humantime::{format_duration, format_rfc3339_seconds};
+use tokio::{
+    io::{Interest, unix::AsyncFd},
+    task::JoinHandle,
+};
+use tracing::{error, info};
+
+use crate::{
+    audit::Origin,
+    geo::Geo,
+    maps::bans::Bans,
+    metadata::{Annotation, Metadata},
+};
+
+pub const TARGET: &str = "events";
+
+const RECENT_LEN: usize = 100;
+
+#[derive(Clone, Copy)]
+pub struct Record {
+    pub event: Event,
+    pub time: SystemTime,
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            format_rfc3339_seconds(self.time),
+            self.event.kind.name(),
+            Ipv4Addr::from_bits(self.event.addr),
+            self.event.value
+        )?;
+
+        if self.event.country != 0 {
+            write!(f, " country={}", Geo::name(self.event.country))?;
+        }
+
+        if self.event.banned != 0 && self.event.ttl != 0 {
+            write!(
+                f,
+                " ban={}",
+                format_duration(Duration::from_secs(self.event.ttl))
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+pub type Recent = Arc<Mutex<VecDeque<Record>>>;
+
+pub struct Events;
+
+impl Events {
+    fn ban(record: &Record) {
+        let reason = match record.event.kind {
+            EventKind::Trap => format!("trap:{}", record.event.value),
+            kind => kind.name().to_string(),
+        };
+        let annotation = Annotation {
+            reason: Some(reason),
+            labels: Vec::new(),
+        };
+        let mut metadata = Metadata::new(annotation, Origin::Dataplane);
+
+        metadata.expires_at =
+            (record.event.ttl != 0).then(|| record.time + Duration::from_secs(record.event.ttl));
+        Bans::record(Ipv4Addr::from_bits(record.event.addr), metadata);
+    }
+
+    fn decode(item: &[u8]) -> Result<Event, String> {
+        let kind = item
+            .first_chunk::<4>()
+            .map(|kind| u32::from_ne_bytes(*kind))
+            .ok_or_else(|| format!("Event of {} bytes is truncated", item.len()))?;
+
+        EventKind::try_from(kind).map_err(|kind| format!("Event kind {kind} is unknown"))?;
+
+        if item.len() < mem::size_of::<Event>() {
+            return Err(format!("Event of {} bytes is truncated", item.len()));
+        }
+
+        Ok(unsafe { ptr::read_unaligned(item.as_ptr().cast::<Event>()) })
+    }
+
+    pub fn listen(ebpf: &mut Ebpf, recent: Recent) -> anyhow::Result<JoinHandle<()>> {
+        let map = ebpf.take_map("EVENTS").expect("BPF map EVENTS not found");
+        let ring_buf = RingBuf::try_from(map)?;
+        let mut ring_buf = unsafe { AsyncFd::register_with_interest(ring_buf, Interest::READABLE) }
+            .map_err(io::Error::from)?;
+
+        let listener = tokio::spawn(async move {
+            loop {
+                let mut guard = match ring_buf.readable_mut().await {
+                    Ok(guard) => guard,
+                    Err(e) => {
+                        error!(target: TARGET, "Event ring buffer could not be polled: {e}");
+                        break;
+                    }
+                };
+
+                while let Some(item) = guard.get_inner_mut().next() {
+                    let event = match Self::decode(&item) {
+                        Ok(event) => event,
+                        Err(e) => {
+                            error!(target: TARGET, "Skipping event: {e}");
+                            continue;
+                        }
+                    };
+                    let record = Record {
+                        event,
+                        time: SystemTime::now(),
+                    };
+                    let mut records = recent.lock().unwrap();
+
+                    info!(target: TARGET, "{record}");
+
+                    if record.event.banned != 0 {
+                        Self::ban(&record);
+                    }
+
+                    if records.len() == RECENT_LEN {
+                        records.pop_front();
+                    }
+                    records.push_back(record);
+                }
+
+                guard.clear_ready();
+            }
+        });
+
+        Ok(listener)
+    }
+
+    pub fn spawn(ebpf: &mut Ebpf) -> anyhow::Result<(Recent, JoinHandle<()>)> {
+        let recent = Recent::default();
+        let listener = Self::listen(ebpf, recent.clone())?;
+
+        Ok((recent, listener))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(event: Event) -> Vec<u8> {
+        let mut item = vec![0; mem::size_of::<Event>()];
+
+        unsafe { ptr::write_unaligned(item.as_mut_ptr().cast::<Event>(), event) };
+        item
+    }
+
+    #[test]
+    fn decode_event() {
+        let mut item = bytes(Event {
+            kind: EventKind::Scan,
+            addr: Ipv4Addr::new(192, 0, 2, 1).to_bits(),
+            country: 0,
+            banned: 1,
+            value: 20,
+            ttl: 600,
+        });
+        let event = Events::decode(&item).unwrap();
+
+        assert_eq!(
+            (event.kind, event.value, event.ttl),
+            (EventKind::Scan, 20, 600)
+        );
+        assert!(Events::decode(&item[..8]).is_err());
+
+        item[..4].copy_from_slice(&u32::MAX.to_ne_bytes());
+        assert!(Events::decode(&item).is_err());
+    }
+}
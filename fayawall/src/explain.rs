@@ -0,0 +1,522 @@
+use std::{fmt::Write as _, net::Ipv4Addr, time::Duration};
+
+use aya::Ebpf;
+use common::{Counter, DefaultAction, FlowState, FragmentMode, GeoRule, RateLimitWindow, Setting};
+use humantime::{format_duration, format_rfc3339_seconds};
+
+use crate::{
+    ebpf::Init,
+    geo::Geo,
+    metadata::{ktime_now, ktime_to_system_time},
+    test_run::{XDP_DROP, XDP_PASS, verdict},
+};
+
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+#[derive(Debug, PartialEq)]
+pub struct Explain {
+    addr: Ipv4Addr,
+    dport: Option<u16>,
+    protocol: Option<u8>,
+}
+
+impl Explain {
+    fn packet(&self) -> String {
+        let mut packet = format!("src {}", self.addr);
+
+        if let Some(protocol) = self.protocol {
+            match protocol {
+                IPPROTO_ICMP => packet.push_str(" icmp"),
+                IPPROTO_TCP => packet.push_str(" tcp"),
+                IPPROTO_UDP => packet.push_str(" udp"),
+                _ => write!(packet, " proto {protocol}").unwrap(),
+            }
+        }
+
+        if let Some(dport) = self.dport {
+            write!(packet, " dport {dport}").unwrap();
+        }
+
+        packet
+    }
+
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let (addr, protocol, dport) = match args {
+            [addr] => (addr, None, None),
+            [addr, protocol] => (addr, Some(protocol), None),
+            [addr, protocol, dport] => (addr, Some(protocol), Some(dport)),
+            _ => return Err("Usage: explain <src-ip> [proto [dport]]".to_string()),
+        };
+        let protocol = protocol
+            .map(|&protocol| match protocol {
+                "icmp" => Ok(IPPROTO_ICMP),
+                "tcp" => Ok(IPPROTO_TCP),
+                "udp" => Ok(IPPROTO_UDP),
+                number => number
+                    .parse()
+                    .map_err(|e| format!("Invalid protocol `{protocol}`: {e}")),
+            })
+            .transpose()?;
+
+        Ok(Self {
+            addr: addr
+                .parse()
+                .map_err(|e| format!("Invalid source address `{addr}`: {e}"))?,
+            dport: dport
+                .map(|dport| {
+                    dport
+                        .parse()
+                        .map_err(|e| format!("Invalid destination port `{dport}`: {e}"))
+                })
+                .transpose()?,
+            protocol,
+        })
+    }
+
+    fn rate_limit(
+        window: Option<RateLimitWindow>,
+        packet_limit: u64,
+        window_size: u64,
+        now: u64,
+    ) -> (u64, bool) {
+        let packet_count = match window {
+            Some(window) if now.saturating_sub(window.window_start) <= window_size => {
+                window.packet_count + 1
+            }
+            _ => 1,
+        };
+
+        (
+            packet_count,
+            window.is_some() && packet_count > packet_limit,
+        )
+    }
+
+    pub fn run(&self, ebpf: &mut Ebpf) -> anyhow::Result<String> {
+        let mut report = format!("Packet: {}\n", self.packet());
+        let mut decision = None;
+
+        writeln!(report, "\n{:<15} {:<10} ENTRY", "RULE", "RESULT")?;
+
+        if ebpf.management()?.get(self.addr).is_some() {
+            writeln!(
+                report,
+                "{:<15} {:<10} {} is within a management prefix",
+                "management", "match", self.addr
+            )?;
+            decision = Some(Counter::Pass);
+        } else {
+            writeln!(report, "{:<15} {:<10}", "management", "no match")?;
+        }
+
+        let sanity = ebpf.settings()?.get(Setting::Sanity);
+
+        match decision {
+            Some(_) => writeln!(report, "{:<15} {:<10}", "sanity", "skipped")?,
+            None if sanity == 0 => writeln!(report, "{:<15} {:<10}", "sanity", "disabled")?,
+            None => writeln!(
+                report,
+                "{:<15} {:<10} well-formed header assumed",
+                "sanity", "no match"
+            )?,
+        }
+
+        let mode = match ebpf.settings()?.get(Setting::FragmentMode) {
+            mode if mode == FragmentMode::Drop as u64 => FragmentMode::Drop,
+            mode if mode == FragmentMode::Track as u64 => FragmentMode::Track,
+            _ => FragmentMode::Pass,
+        };
+
+        match decision {
+            Some(_) => writeln!(report, "{:<15} {:<10}", "fragment", "skipped")?,
+            None => writeln!(
+                report,
+                "{:<15} {:<10} unfragmented packet assumed, mode {}",
+                "fragment",
+                "no match",
+                mode.name()
+            )?,
+        }
+
+        let whitelisted = ebpf.whitelist()?.describe(self.addr);
+        let blacklisted = match ebpf.blacklist()?.describe(self.addr) {
+            Some(entry) => Some(entry),
+            None => ebpf.bans()?.describe(self.addr),
+        };
+
+        for (rule, entry, counter) in [
+            ("whitelist", whitelisted, Counter::Pass),
+            ("blacklist", blacklisted, Counter::Blacklist),
+        ] {
+            match entry {
+                _ if decision.is_some() => writeln!(report, "{rule:<15} {:<10}", "skipped")?,
+                Some(entry) => {
+                    writeln!(report, "{rule:<15} {:<10} {entry}", "match")?;
+                    decision = Some(counter);
+                }
+                None => writeln!(report, "{rule:<15} {:<10}", "no match")?,
+            }
+        }
+
+        let bogon = ebpf.settings()?.get(Setting::Bogon) != 0;
+
+        match ebpf.bogons()?.contains(self.addr) {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "bogon", "skipped")?,
+            true if bogon => {
+                writeln!(
+                    report,
+                    "{:<15} {:<10} {} is a bogon source",
+                    "bogon", "match", self.addr
+                )?;
+                decision = Some(Counter::Bogon);
+            }
+            _ => writeln!(report, "{:<15} {:<10}", "bogon", "no match")?,
+        }
+
+        let trap_ports = ebpf.trap_ports()?.get();
+        let trapped = self.dport.filter(|dport| {
+            trap_ports.contains(dport)
+                && self.protocol.is_none_or(|protocol| protocol == IPPROTO_TCP)
+        });
+
+        match trapped {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "trap", "skipped")?,
+            Some(dport) => {
+                writeln!(
+                    report,
+                    "{:<15} {:<10} port {dport} is a trap port, plain SYN assumed",
+                    "trap", "match"
+                )?;
+                decision = Some(Counter::Trap);
+            }
+            None => writeln!(report, "{:<15} {:<10}", "trap", "no match")?,
+        }
+
+        let settings = ebpf.settings()?;
+        let anomalies =
+            settings.get(Setting::TcpAnomalyDrop) | settings.get(Setting::TcpAnomalyLog);
+
+        match self.protocol {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "tcp_anomaly", "skipped")?,
+            Some(protocol) if protocol != IPPROTO_TCP => {
+                writeln!(report, "{:<15} {:<10} not tcp", "tcp_anomaly", "no match")?
+            }
+            _ if anomalies == 0 => writeln!(report, "{:<15} {:<10}", "tcp_anomaly", "disabled")?,
+            _ => writeln!(
+                report,
+                "{:<15} {:<10} plain SYN assumed",
+                "tcp_anomaly", "no match"
+            )?,
+        }
+
+        let country = ebpf.geo_db()?.country(self.addr);
+        let rule = ebpf
+            .geo_rules()?
+            .get()
+            .into_iter()
+            .find(|&(rule_country, _)| Some(rule_country) == country)
+            .map(|(_, rule)| rule);
+        let allow_only = ebpf.settings()?.get(Setting::GeoAllowOnly) != 0;
+
+        match country.map(Geo::name) {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "geo", "skipped")?,
+            None => writeln!(report, "{:<15} {:<10} country unknown", "geo", "no match")?,
+            Some(name) => match rule {
+                Some(GeoRule::Block) => {
+                    writeln!(report, "{:<15} {:<10} {name} is blocked", "geo", "match")?;
+                    decision = Some(Counter::Geo);
+                }
+                None if allow_only => {
+                    writeln!(
+                        report,
+                        "{:<15} {:<10} {name} is not allowed",
+                        "geo", "match"
+                    )?;
+                    decision = Some(Counter::Geo);
+                }
+                _ => writeln!(report, "{:<15} {:<10} country {name}", "geo", "no match")?,
+            },
+        }
+
+        match ebpf.asn_prefixes()?.asn(self.addr) {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "asn", "skipped")?,
+            Some(asn) => {
+                writeln!(report, "{:<15} {:<10} AS{asn} is blocked", "asn", "match")?;
+                decision = Some(Counter::Asn);
+            }
+            None => writeln!(report, "{:<15} {:<10}", "asn", "no match")?,
+        }
+
+        let settings = ebpf.settings()?;
+        let threshold = settings.get(Setting::ScanThreshold);
+        let response = if settings.get(Setting::ScanBanTtl) == 0 {
+            "logs"
+        } else {
+            "bans"
+        };
+
+        match self.protocol {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "scan", "skipped")?,
+            Some(protocol) if !matches!(protocol, IPPROTO_TCP | IPPROTO_UDP) => {
+                writeln!(report, "{:<15} {:<10} not tcp or udp", "scan", "no match")?
+            }
+            _ if threshold == 0 => writeln!(report, "{:<15} {:<10}", "scan", "disabled")?,
+            _ => writeln!(
+                report,
+                "{:<15} {:<10} {response} at {threshold} distinct targets",
+                "scan", "no match"
+            )?,
+        }
+
+        let threshold = ebpf.settings()?.get(Setting::SynThreshold);
+        let syn_ports = ebpf.syn_ports()?.get();
+        let protected = self
+            .dport
+            .filter(|dport| syn_ports.contains(dport) && self.protocol == Some(IPPROTO_TCP));
+
+        match protected {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "syn_cookie", "skipped")?,
+            _ if threshold == 0 => writeln!(report, "{:<15} {:<10}", "syn_cookie", "disabled")?,
+            Some(dport) => writeln!(
+                report,
+                "{:<15} {:<10} port {dport} answers SYNs with cookies above {threshold}/s",
+                "syn_cookie", "no match"
+            )?,
+            None => writeln!(report, "{:<15} {:<10}", "syn_cookie", "no match")?,
+        }
+
+        let established = if ebpf.settings()?.get(Setting::AllowEstablished) == 0 {
+            None
+        } else {
+            Some(ebpf.conntrack()?.flows().into_iter().find(|(key, flow)| {
+                key.remote == self.addr.to_bits()
+                    && flow.state != FlowState::SynSent
+                    && self
+                        .protocol
+                        .is_none_or(|protocol| key.protocol == u32::from(protocol))
+                    && self.dport.is_none_or(|dport| key.local_port == dport)
+            }))
+        };
+
+        match established {
+            _ if decision.is_some() => writeln!(report, "{:<15} {:<10}", "established", "skipped")?,
+            None => writeln!(
+                report,
+                "{:<15} {:<10} allow_established is off",
+                "established", "disabled"
+            )?,
+            Some(Some((key, flow))) => {
+                writeln!(
+                    report,
+                    "{:<15} {:<10} flow to local port {} is {}",
+                    "established",
+                    "match",
+                    key.local_port,
+                    flow.state.name()
+                )?;
+                decision = Some(Counter::Pass);
+            }
+            Some(None) => writeln!(report, "{:<15} {:<10}", "established", "no match")?,
+        }
+
+        let settings = ebpf.settings()?;
+        let packet_limit = settings.find(Setting::PacketLimit).unwrap_or(u64::MAX);
+        let window_size = settings.find(Setting::WindowSize).unwrap_or(u64::MAX);
+        let window = ebpf.rate_limit_windows()?.get(self.addr);
+        let now = ktime_now().unwrap_or_default().as_nanos() as u64;
+        let (packet_count, limited) = Self::rate_limit(window, packet_limit, window_size, now);
+
+        match decision {
+            Some(_) => writeln!(report, "{:<15} {:<10}", "rate_limit", "skipped")?,
+            None if limited => {
+                writeln!(
+                    report,
+                    "{:<15} {:<10} next packet would be {packet_count} > packet_limit {packet_limit}",
+                    "rate_limit", "match"
+                )?;
+                decision = Some(Counter::RateLimit);
+            }
+            None => writeln!(
+                report,
+                "{:<15} {:<10} next packet would be {packet_count} of packet_limit {packet_limit}",
+                "rate_limit", "no match"
+            )?,
+        }
+
+        let default_action = ebpf.settings()?.default_action();
+
+        match decision {
+            Some(_) => writeln!(report, "{:<15} {:<10}", "default", "skipped")?,
+            None => writeln!(
+                report,
+                "{:<15} {:<10} default_action {}",
+                "default",
+                "match",
+                default_action.name()
+            )?,
+        }
+
+        let counter = decision.unwrap_or(match default_action {
+            DefaultAction::Drop => Counter::Default,
+            DefaultAction::Pass => Counter::Pass,
+        });
+        let action = if counter.is_drop() {
+            XDP_DROP
+        } else {
+            XDP_PASS
+        };
+
+        writeln!(
+            report,
+            "\nVerdict: {} ({})",
+            verdict(action),
+            counter.name()
+        )?;
+
+        match window {
+            Some(window) => writeln!(
+                report,
+                "Rate window: packets={} window_start={} elapsed={} window_size={}",
+                window.packet_count,
+                ktime_to_system_time(window.window_start).map_or_else(
+                    || "-".to_string(),
+                    |t| format_rfc3339_seconds(t).to_string()
+                ),
+                format_duration(Duration::from_millis(
+                    now.saturating_sub(window.window_start) / 1_000_000
+                )),
+                window_size
+            )?,
+            None => report.push_str("Rate window: none\n"),
+        }
+
+        writeln!(
+            report,
+            "Country: {}",
+            country.map_or_else(|| "unknown".to_string(), Geo::name)
+        )?;
+
+        match ebpf.source_stats()?.get(self.addr) {
+            Some(stat) => writeln!(
+                report,
+                "Source stats: packets={} bytes={} drops={}",
+                stat.packets, stat.bytes, stat.drops
+            )?,
+            None => report.push_str("Source stats: none\n"),
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{audit::Origin, packet::tcp_v4, test_run::test_run};
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+    fn window(window_start: u64, packet_count: u64) -> Option<RateLimitWindow> {
+        Some(RateLimitWindow {
+            window_start,
+            packet_count,
+        })
+    }
+
+    #[test]
+    fn parse_explain() {
+        let expected = Explain {
+            addr: SOURCE,
+            dport: Some(443),
+            protocol: Some(IPPROTO_TCP),
+        };
+
+        assert_eq!(Explain::parse(&["192.0.2.1", "tcp", "443"]), Ok(expected));
+        assert_eq!(
+            Explain::parse(&["192.0.2.1", "47"]).unwrap().protocol,
+            Some(47)
+        );
+    }
+
+    #[test]
+    fn parse_invalid_explain() {
+        assert!(Explain::parse(&[]).is_err());
+        assert!(Explain::parse(&["invalid"]).is_err());
+        assert!(Explain::parse(&["192.0.2.1", "sctp"]).is_err());
+        assert!(Explain::parse(&["192.0.2.1", "tcp", "65536"]).is_err());
+        assert!(Explain::parse(&["192.0.2.1", "tcp", "443", "extra"]).is_err());
+    }
+
+    #[test]
+    fn rate_limit_mirrors_ebpf() {
+        assert_eq!(Explain::rate_limit(None, 0, 100, 1_000), (1, false));
+        assert_eq!(
+            Explain::rate_limit(window(950, 2), 2, 100, 1_000),
+            (3, true)
+        );
+        assert_eq!(
+            Explain::rate_limit(window(950, 1), 2, 100, 1_000),
+            (2, false)
+        );
+        assert_eq!(
+            Explain::rate_limit(window(800, 5), 2, 100, 1_000),
+            (1, false)
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn explain_blacklisted_source() {
+        let mut ebpf = Ebpf::detached().unwrap();
+
+        ebpf.blacklist()
+            .unwrap()
+            .add(&[&SOURCE.to_string(), "--reason", "scanner"], Origin::Repl);
+        test_run(&ebpf, &tcp_v4(SOURCE, 22)).unwrap();
+
+        let report = Explain::parse(&["192.0.2.1"])
+            .unwrap()
+            .run(&mut ebpf)
+            .unwrap();
+
+        assert!(report.contains("sanity          disabled"));
+        assert!(
+            report.contains("fragment        no match   unfragmented packet assumed, mode pass")
+        );
+        assert!(report.find("sanity") < report.find("fragment"));
+        assert!(report.find("fragment") < report.find("whitelist"));
+        assert!(report.contains("whitelist       no match"));
+        assert!(report.contains("reason=\"scanner\""));
+        assert!(report.contains("tcp_anomaly     skipped"));
+        assert!(report.contains("geo             skipped"));
+        assert!(report.contains("asn             skipped"));
+        assert!(report.contains("scan            skipped"));
+        assert!(report.contains("syn_cookie      skipped"));
+        assert!(report.contains("established     skipped"));
+        assert!(report.contains("rate_limit      skipped"));
+        assert!(report.contains("Verdict: XDP_DROP (blacklist)"));
+        assert!(report.contains("Source stats: packets=1 bytes=54 drops=1"));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn explain_trap_port() {
+        let mut ebpf = Ebpf::detached().unwrap();
+
+        ebpf.trap_ports().unwrap().insert(23, Origin::Repl).unwrap();
+
+        let report = Explain::parse(&["192.0.2.1", "tcp", "23"])
+            .unwrap()
+            .run(&mut ebpf)
+            .unwrap();
+
+        assert!(
+            report.contains("trap            match      port 23 is a trap port, plain SYN assumed")
+        );
+        assert!(report.contains("Verdict: XDP_DROP (trap)"));
+    }
+}
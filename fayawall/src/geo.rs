@@ -0,0 +1,184 @@
+use std::{
+    collections::HashMap,
+    fs::{read, read_to_string},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+use aya::Ebpf;
+use ipnet::Ipv4Net;
+
+use crate::{
+    audit::{Audit, Origin},
+    ebpf::Init,
+    mmdb::Mmdb,
+};
+
+const LOCATIONS: &str = "GeoLite2-Country-Locations-en.csv";
+
+pub struct Geo;
+
+impl Geo {
+    pub fn code(code: &str) -> Result<u32, String> {
+        match code.as_bytes() {
+            &[a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+                Ok(u32::from(u16::from_be_bytes([
+                    a.to_ascii_uppercase(),
+                    b.to_ascii_uppercase(),
+                ])))
+            }
+            _ => Err(format!("Invalid country code `{code}`")),
+        }
+    }
+
+    fn csv(path: &Path) -> anyhow::Result<Vec<(Ipv4Net, u32)>> {
+        let locations = path.with_file_name(LOCATIONS);
+        let countries = read_to_string(&locations)
+            .with_context(|| format!("`{}` not read", locations.display()))?
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let fields = line.split(',').collect::<Vec<_>>();
+
+                Some((
+                    fields.first()?.to_string(),
+                    Self::code(fields.get(4)?).ok()?,
+                ))
+            })
+            .collect::<HashMap<_, _>>();
+
+        Ok(read_to_string(path)?
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let fields = line.split(',').collect::<Vec<_>>();
+                let net = fields.first()?.parse().ok()?;
+                let country = [1, 2]
+                    .iter()
+                    .find_map(|&i| countries.get(*fields.get(i)?))?;
+
+                Some((net, *country))
+            })
+            .collect())
+    }
+
+    pub fn load(path: &str) -> anyhow::Result<Vec<(Ipv4Net, u32)>> {
+        let path = Path::new(path);
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Self::csv(path),
+            Some("mmdb") => Mmdb::new(&read(path)?)?.nets(|value| {
+                value
+                    .get("country")
+                    .or_else(|| value.get("registered_country"))?
+                    .get("iso_code")?
+                    .str()
+                    .and_then(|code| Self::code(code).ok())
+            }),
+            _ => bail!(
+                "`{}` is neither a .mmdb nor a .csv database",
+                path.display()
+            ),
+        }
+    }
+
+    pub fn name(country: u32) -> String {
+        String::from_utf8_lossy(&(country as u16).to_be_bytes()).into_owned()
+    }
+
+    pub fn update(
+        ebpf: &mut Ebpf,
+        path: &str,
+        generation: u32,
+        origin: Origin,
+    ) -> anyhow::Result<()> {
+        let result =
+            Self::load(path).and_then(|nets| Ok(ebpf.geo_db_at(generation)?.load(&nets)?));
+
+        Audit::record(origin, "geo.load", &[path], &result);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, remove_file, write},
+    };
+
+    use super::*;
+    use crate::mmdb::tests::{database, string};
+
+    fn mmdb() -> Vec<u8> {
+        let mut leaf = vec![0xe1];
+
+        leaf.extend(string("country"));
+        leaf.push(0xe1);
+        leaf.extend(string("iso_code"));
+        leaf.extend(string("AU"));
+
+        database(&leaf)
+    }
+
+    #[test]
+    fn parse_country_code() {
+        assert_eq!(Geo::code("au"), Geo::code("AU"));
+        assert_eq!(Geo::name(Geo::code("AU").unwrap()), "AU");
+        assert!(Geo::code("AUS").is_err());
+        assert!(Geo::code("1A").is_err());
+    }
+
+    #[test]
+    fn load_geolite2_csv() {
+        let dir = temp_dir().join("fayawall-geo-test");
+        let blocks = dir.join("GeoLite2-Country-Blocks-IPv4.csv");
+
+        create_dir_all(&dir).unwrap();
+        write(
+            dir.join(LOCATIONS),
+            "geoname_id,locale_code,continent_code,continent_name,country_iso_code,country_name,is_in_european_union\n\
+             2077456,en,OC,Oceania,AU,Australia,0\n\
+             1835841,en,AS,Asia,KR,\"Korea, Republic of\",0\n",
+        )
+        .unwrap();
+        write(
+            &blocks,
+            "network,geoname_id,registered_country_geoname_id,represented_country_geoname_id,is_anonymous_proxy,is_satellite_provider\n\
+             1.0.0.0/24,2077456,2077456,,0,0\n\
+             1.11.0.0/16,,1835841,,0,0\n\
+             1.12.0.0/16,,,,1,0\n",
+        )
+        .unwrap();
+
+        let nets = Geo::load(blocks.to_str().unwrap()).unwrap();
+
+        remove_dir_all(&dir).ok();
+
+        assert_eq!(
+            nets,
+            [
+                ("1.0.0.0/24".parse().unwrap(), Geo::code("AU").unwrap()),
+                ("1.11.0.0/16".parse().unwrap(), Geo::code("KR").unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_mmdb() {
+        let path = temp_dir().join("fayawall-geo-test.mmdb");
+
+        write(&path, mmdb()).unwrap();
+
+        let nets = Geo::load(path.to_str().unwrap()).unwrap();
+
+        remove_file(&path).ok();
+
+        assert_eq!(
+            nets,
+            [("1.0.0.0/8".parse().unwrap(), Geo::code("AU").unwrap())]
+        );
+        assert!(Geo::load("geo.dat").is_err());
+    }
+}
@@ -0,0 +1,612 @@
+use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
+
+use aya::Ebpf;
+use common::{
+    Counter, DefaultAction, Flow, FlowKey, FlowState, FragmentMode, GeoRule, IPPROTO_TCP as TCP,
+    Sanity, Setting, TcpAnomaly,
+};
+use serial_test::serial;
+
+use crate::{
+    audit::Origin,
+    ebpf::Init,
+    geo::Geo,
+    metadata::ktime_now,
+    packet::{
+        ETH_P_IP, ETH_P_IPV6, IPPROTO_TCP, checksum, ethernet, ipv4, ipv6, tcp, tcp_v4, udp_v4,
+    },
+    test_run::{XDP_ABORTED, XDP_DROP, XDP_PASS, XDP_TX, test_run, test_run_output},
+};
+
+const SOURCE: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+fn count(ebpf: &mut Ebpf, counter: Counter) -> u64 {
+    ebpf.counters().unwrap().get(counter).unwrap()
+}
+
+fn fragment(src: Ipv4Addr, frags: u16, payload: &[u8]) -> Vec<u8> {
+    let mut packet = ipv4(src, IPPROTO_TCP, payload);
+
+    packet[6..8].copy_from_slice(&frags.to_be_bytes());
+    ethernet(ETH_P_IP, &packet)
+}
+
+#[test]
+fn checksum_ipv4_header() {
+    let header = ipv4(SOURCE, IPPROTO_TCP, &[]);
+
+    assert_eq!(checksum(&header), 0);
+}
+
+#[serial]
+#[tokio::test]
+async fn pass_unlisted_source() {
+    let mut ebpf = Ebpf::detached().unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+    assert_eq!(test_run(&ebpf, &udp_v4(SOURCE, 53)).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::Pass), 2);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_blacklisted_source() {
+    let mut ebpf = Ebpf::detached().unwrap();
+
+    ebpf.blacklist()
+        .unwrap()
+        .add(&[&SOURCE.to_string()], Origin::Repl);
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_DROP);
+    assert_eq!(
+        test_run(&ebpf, &tcp_v4(Ipv4Addr::new(192, 0, 2, 2), 80)).unwrap(),
+        XDP_PASS
+    );
+    assert_eq!(count(&mut ebpf, Counter::Blacklist), 1);
+    assert_eq!(count(&mut ebpf, Counter::Pass), 1);
+}
+
+#[serial]
+#[tokio::test]
+async fn whitelist_takes_precedence_over_blacklist() {
+    let mut ebpf = Ebpf::detached().unwrap();
+
+    ebpf.blacklist()
+        .unwrap()
+        .add(&[&SOURCE.to_string()], Origin::Repl);
+    ebpf.whitelist()
+        .unwrap()
+        .add(&[&SOURCE.to_string()], Origin::Repl);
+
+    assert_eq!(test_run(&ebpf, &udp_v4(SOURCE, 53)).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::Blacklist), 0);
+    assert_eq!(count(&mut ebpf, Counter::Pass), 1);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_rate_limited_source() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let mut settings = ebpf.settings().unwrap();
+
+    settings.set(Setting::PacketLimit, 2, Origin::Repl).unwrap();
+    settings
+        .set(Setting::WindowSize, u64::MAX, Origin::Repl)
+        .unwrap();
+
+    let verdicts = (0..4)
+        .map(|_| test_run(&ebpf, &tcp_v4(SOURCE, 443)).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(verdicts, [XDP_PASS, XDP_PASS, XDP_DROP, XDP_DROP]);
+    assert_eq!(count(&mut ebpf, Counter::RateLimit), 2);
+    assert_eq!(count(&mut ebpf, Counter::Pass), 2);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_unlisted_source_by_default() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let management = Ipv4Addr::new(192, 0, 2, 10);
+    let whitelisted = Ipv4Addr::new(192, 0, 2, 2);
+
+    ebpf.management()
+        .unwrap()
+        .insert(management.into(), 0)
+        .unwrap();
+    ebpf.whitelist()
+        .unwrap()
+        .add(&[&whitelisted.to_string()], Origin::Repl);
+    ebpf.settings()
+        .unwrap()
+        .set_default_action(DefaultAction::Drop, Origin::Repl)
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_DROP);
+    assert_eq!(test_run(&ebpf, &tcp_v4(management, 22)).unwrap(), XDP_PASS);
+    assert_eq!(test_run(&ebpf, &tcp_v4(whitelisted, 80)).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::Default), 1);
+    assert_eq!(count(&mut ebpf, Counter::Pass), 2);
+}
+
+#[serial]
+#[tokio::test]
+async fn pass_established_flow_by_default_drop() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let key = FlowKey {
+        remote: SOURCE.to_bits(),
+        local: Ipv4Addr::new(192, 0, 2, 254).to_bits(),
+        remote_port: 40000,
+        local_port: 80,
+        protocol: TCP,
+    };
+    let flow = Flow {
+        state: FlowState::Established,
+        packets: 1,
+        last_seen: ktime_now().unwrap().as_nanos() as u64,
+    };
+
+    ebpf.management()
+        .unwrap()
+        .insert(Ipv4Addr::new(192, 0, 2, 10).into(), 0)
+        .unwrap();
+    ebpf.settings()
+        .unwrap()
+        .set_default_action(DefaultAction::Drop, Origin::Repl)
+        .unwrap();
+    ebpf.conntrack().unwrap().0.insert(key, flow, 0).unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_DROP);
+
+    ebpf.settings()
+        .unwrap()
+        .set(Setting::AllowEstablished, 1, Origin::Repl)
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 443)).unwrap(), XDP_DROP);
+    assert_eq!(ebpf.conntrack().unwrap().flows()[0].1.packets, 2);
+}
+
+#[serial]
+#[tokio::test]
+async fn answer_syn_flood_with_cookies() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    ebpf.settings()
+        .unwrap()
+        .set(Setting::SynThreshold, 2, Origin::Repl)
+        .unwrap();
+    ebpf.syn_ports()
+        .unwrap()
+        .insert(port, Origin::Repl)
+        .unwrap();
+
+    let syn = tcp_v4(SOURCE, port);
+    let verdicts = (0..2)
+        .map(|_| test_run(&ebpf, &syn).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(verdicts, [XDP_PASS, XDP_PASS]);
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+
+    let (verdict, synack) = test_run_output(&ebpf, &syn).unwrap();
+
+    assert_eq!(verdict, XDP_TX);
+    assert_eq!(count(&mut ebpf, Counter::SynCookie), 1);
+    assert_eq!(synack.len(), 58);
+
+    let mut padded = ipv4(SOURCE, IPPROTO_TCP, &tcp(40000, port, 0x02));
+
+    padded.splice(20..20, [1, 1, 1, 1]);
+    padded[0] = 0x46;
+    padded[2..4].copy_from_slice(&44u16.to_be_bytes());
+    padded[10..12].fill(0);
+
+    let sum = checksum(&padded[..24]);
+
+    padded[10..12].copy_from_slice(&sum.to_be_bytes());
+
+    assert_eq!(
+        test_run(&ebpf, &ethernet(ETH_P_IP, &padded)).unwrap(),
+        XDP_DROP
+    );
+    assert_eq!(count(&mut ebpf, Counter::SynOptions), 1);
+    assert_eq!(synack[0..6], syn[6..12]);
+    assert_eq!(synack[6..12], syn[0..6]);
+    assert_eq!(synack[26..30], syn[30..34]);
+    assert_eq!(synack[30..34], syn[26..30]);
+    assert_eq!(checksum(&synack[14..34]), 0);
+
+    let (ip, segment) = synack[14..].split_at(20);
+    let mut pseudo = ip[12..20].to_vec();
+
+    pseudo.extend_from_slice(&[0, IPPROTO_TCP]);
+    pseudo.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+    pseudo.extend_from_slice(segment);
+
+    assert_eq!(checksum(&pseudo), 0);
+    assert_eq!(segment[0..2], port.to_be_bytes());
+    assert_eq!(segment[2..4], 40000u16.to_be_bytes());
+    assert_eq!(segment[8..12], 1u32.to_be_bytes());
+    assert_eq!(segment[13], 0x12);
+    assert_eq!(segment[20..22], [2, 4]);
+    assert!([536, 1300, 1440, 1460].contains(&u16::from_be_bytes([segment[22], segment[23]])));
+
+    let cookie = u32::from_be_bytes(segment[4..8].try_into().unwrap());
+    let ack = |ack: u32| {
+        let mut segment = tcp(40000, port, 0x10);
+
+        segment[4..8].copy_from_slice(&1u32.to_be_bytes());
+        segment[8..12].copy_from_slice(&ack.to_be_bytes());
+        ethernet(ETH_P_IP, &ipv4(SOURCE, IPPROTO_TCP, &segment))
+    };
+
+    assert_eq!(
+        test_run(&ebpf, &ack(cookie.wrapping_add(1))).unwrap(),
+        XDP_PASS
+    );
+    assert_eq!(count(&mut ebpf, Counter::SynCookieValid), 1);
+    assert_eq!(
+        test_run(&ebpf, &ack(cookie.wrapping_add(2))).unwrap(),
+        XDP_DROP
+    );
+    assert_eq!(count(&mut ebpf, Counter::SynCookieInvalid), 1);
+
+    ebpf.settings()
+        .unwrap()
+        .set_default_action(DefaultAction::Drop, Origin::Repl)
+        .unwrap();
+
+    assert_eq!(
+        test_run(&ebpf, &ack(cookie.wrapping_add(1))).unwrap(),
+        XDP_DROP
+    );
+    assert_eq!(count(&mut ebpf, Counter::SynCookieValid), 2);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_blocked_country() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let blocked = Geo::code("CN").unwrap();
+    let allowed = Ipv4Addr::new(198, 51, 100, 1);
+
+    ebpf.geo_db()
+        .unwrap()
+        .load(&[
+            ("192.0.2.0/24".parse().unwrap(), blocked),
+            ("198.51.100.0/24".parse().unwrap(), Geo::code("AU").unwrap()),
+        ])
+        .unwrap();
+    ebpf.geo_rules()
+        .unwrap()
+        .insert(blocked, GeoRule::Block, Origin::Repl)
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_DROP);
+    assert_eq!(test_run(&ebpf, &tcp_v4(allowed, 80)).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::Geo), 1);
+    assert_eq!(ebpf.geo_drops().unwrap().entries(), [(blocked, 1)]);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_blocked_asn() {
+    let mut ebpf = Ebpf::detached().unwrap();
+
+    ebpf.asn_prefixes()
+        .unwrap()
+        .load(&[("192.0.2.0/24".parse().unwrap(), 14061)])
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_DROP);
+    assert_eq!(
+        test_run(&ebpf, &tcp_v4(Ipv4Addr::new(198, 51, 100, 1), 80)).unwrap(),
+        XDP_PASS
+    );
+    assert_eq!(count(&mut ebpf, Counter::Asn), 1);
+    assert_eq!(ebpf.asn_prefixes().unwrap().asn(SOURCE), Some(14061));
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_bogon_source_when_enabled() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let routable = Ipv4Addr::new(1, 1, 1, 1);
+
+    ebpf.bogons()
+        .unwrap()
+        .load(&["192.0.2.0/24".parse().unwrap()])
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+
+    ebpf.settings()
+        .unwrap()
+        .set(Setting::Bogon, 1, Origin::Repl)
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_DROP);
+    assert_eq!(test_run(&ebpf, &tcp_v4(routable, 80)).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::Bogon), 1);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_malformed_ipv4() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let packet = ipv4(SOURCE, IPPROTO_TCP, &tcp(40000, 80, 0x02));
+    let with_options = |options: &[u8]| {
+        let mut packet = packet.clone();
+        let len = packet.len() as u16 + 4;
+
+        packet[0] = 0x46;
+        packet[2..4].copy_from_slice(&len.to_be_bytes());
+        packet.splice(20..20, options.iter().copied());
+        packet
+    };
+    let patched = |offset: usize, bytes: &[u8]| {
+        let mut packet = packet.clone();
+
+        packet[offset..offset + bytes.len()].copy_from_slice(bytes);
+        packet
+    };
+    let land = ipv4(
+        Ipv4Addr::new(192, 0, 2, 254),
+        IPPROTO_TCP,
+        &tcp(40000, 80, 0x02),
+    );
+    let all = Sanity::ALL
+        .iter()
+        .fold(0, |checks, sanity| checks | sanity.bit());
+
+    assert_eq!(
+        test_run(&ebpf, &ethernet(ETH_P_IP, &land)).unwrap(),
+        XDP_PASS
+    );
+
+    ebpf.settings()
+        .unwrap()
+        .set(Setting::Sanity, all, Origin::Repl)
+        .unwrap();
+
+    for (packet, counter) in [
+        (patched(0, &[0x65]), Counter::BadVersion),
+        (patched(0, &[0x44]), Counter::BadIhl),
+        (patched(2, &200u16.to_be_bytes()), Counter::Truncated),
+        (with_options(&[0x83, 3, 4, 0]), Counter::SourceRoute),
+        (with_options(&[1, 1, 1, 0]), Counter::IpOptions),
+        (patched(8, &[0]), Counter::ZeroTtl),
+        (land, Counter::Land),
+        (patched(6, &[0x80, 0]), Counter::BadFragment),
+    ] {
+        assert_eq!(
+            test_run(&ebpf, &ethernet(ETH_P_IP, &packet)).unwrap(),
+            XDP_DROP
+        );
+        assert_eq!(count(&mut ebpf, counter), 1, "{}", counter.name());
+    }
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_fragments_by_mode() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let first = fragment(SOURCE, 0x2000, &tcp(40000, 80, 0x02));
+    let tiny = fragment(SOURCE, 0x2000, &tcp(40000, 80, 0x02)[..8]);
+    let overlapping = fragment(SOURCE, 0x0001, &[0; 16]);
+
+    ebpf.settings()
+        .unwrap()
+        .set(Setting::FragmentDropTiny, 1, Origin::Repl)
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &first).unwrap(), XDP_PASS);
+    assert_eq!(test_run(&ebpf, &tiny).unwrap(), XDP_DROP);
+    assert_eq!(test_run(&ebpf, &overlapping).unwrap(), XDP_DROP);
+    assert_eq!(count(&mut ebpf, Counter::TinyFragment), 2);
+
+    ebpf.settings()
+        .unwrap()
+        .set(
+            Setting::FragmentMode,
+            FragmentMode::Drop as u64,
+            Origin::Repl,
+        )
+        .unwrap();
+
+    assert_eq!(test_run(&ebpf, &first).unwrap(), XDP_DROP);
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::Fragment), 1);
+}
+
+#[serial]
+#[tokio::test]
+async fn track_first_fragment_verdicts() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let blacklisted = Ipv4Addr::new(198, 51, 100, 1);
+
+    ebpf.settings()
+        .unwrap()
+        .set(
+            Setting::FragmentMode,
+            FragmentMode::Track as u64,
+            Origin::Repl,
+        )
+        .unwrap();
+    ebpf.blacklist()
+        .unwrap()
+        .add(&[&blacklisted.to_string()], Origin::Repl);
+
+    assert_eq!(
+        test_run(&ebpf, &fragment(SOURCE, 0x0003, &[0; 16])).unwrap(),
+        XDP_DROP
+    );
+    assert_eq!(count(&mut ebpf, Counter::FragmentUntracked), 1);
+
+    for (src, action) in [(SOURCE, XDP_PASS), (blacklisted, XDP_DROP)] {
+        let first = fragment(src, 0x2000, &tcp(40000, 80, 0x02));
+
+        assert_eq!(test_run(&ebpf, &first).unwrap(), action);
+        assert_eq!(
+            test_run(&ebpf, &fragment(src, 0x0003, &[0; 16])).unwrap(),
+            action
+        );
+    }
+
+    assert_eq!(count(&mut ebpf, Counter::Blacklist), 2);
+    assert_eq!(count(&mut ebpf, Counter::FragmentUntracked), 1);
+}
+
+#[serial]
+#[tokio::test]
+async fn drop_tcp_flag_anomalies() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let segment = |segment: Vec<u8>| ethernet(ETH_P_IP, &ipv4(SOURCE, IPPROTO_TCP, &segment));
+    let mut syn_payload = tcp(40000, 80, 0x02);
+    let mut bad_data_offset = tcp(40000, 80, 0x10);
+    let drop = TcpAnomaly::ALL
+        .iter()
+        .filter(|&&anomaly| anomaly != TcpAnomaly::SynPayload)
+        .fold(0, |checks, anomaly| checks | anomaly.bit());
+
+    syn_payload.extend_from_slice(b"fayawall");
+    bad_data_offset[12] = 0x40;
+
+    let mut settings = ebpf.settings().unwrap();
+
+    settings
+        .set(Setting::TcpAnomalyDrop, drop, Origin::Repl)
+        .unwrap();
+    settings
+        .set(
+            Setting::TcpAnomalyLog,
+            TcpAnomaly::SynPayload.bit(),
+            Origin::Repl,
+        )
+        .unwrap();
+
+    for (segment, counter) in [
+        (segment(tcp(40000, 80, 0x00)), Counter::NullScan),
+        (segment(tcp(40000, 80, 0x29)), Counter::XmasScan),
+        (segment(tcp(40000, 80, 0x01)), Counter::FinScan),
+        (segment(tcp(40000, 80, 0x03)), Counter::SynFin),
+        (segment(tcp(40000, 80, 0x06)), Counter::SynRst),
+        (segment(bad_data_offset), Counter::BadDataOffset),
+        (segment(tcp(40000, 0, 0x02)), Counter::PortZero),
+    ] {
+        assert_eq!(test_run(&ebpf, &segment).unwrap(), XDP_DROP);
+        assert_eq!(count(&mut ebpf, counter), 1, "{}", counter.name());
+    }
+
+    assert_eq!(test_run(&ebpf, &segment(syn_payload)).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::SynPayload), 0);
+    assert_eq!(
+        ebpf.tcp_anomaly_logs()
+            .unwrap()
+            .get(TcpAnomaly::SynPayload)
+            .unwrap(),
+        1
+    );
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+    assert_eq!(
+        test_run(&ebpf, &segment(tcp(40000, 80, 0x11))).unwrap(),
+        XDP_PASS
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn ban_port_scanner() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let mut settings = ebpf.settings().unwrap();
+
+    for (setting, value) in [
+        (Setting::ScanThreshold, 3),
+        (Setting::ScanWindow, 10_000_000_000),
+        (Setting::ScanBanTtl, 600),
+    ] {
+        settings.set(setting, value, Origin::Repl).unwrap();
+    }
+
+    for (dport, action) in [
+        (22, XDP_PASS),
+        (22, XDP_PASS),
+        (23, XDP_PASS),
+        (80, XDP_DROP),
+    ] {
+        assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, dport)).unwrap(), action);
+    }
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 22)).unwrap(), XDP_DROP);
+    assert_eq!(count(&mut ebpf, Counter::Scan), 1);
+    assert_eq!(count(&mut ebpf, Counter::Blacklist), 1);
+    assert!(ebpf.bans().unwrap().describe(SOURCE).is_some());
+}
+
+#[serial]
+#[tokio::test]
+async fn log_port_scanner_without_ban() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let mut settings = ebpf.settings().unwrap();
+
+    for (setting, value) in [
+        (Setting::ScanThreshold, 3),
+        (Setting::ScanWindow, 10_000_000_000),
+    ] {
+        settings.set(setting, value, Origin::Repl).unwrap();
+    }
+
+    for dport in [22, 23, 80, 443] {
+        assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, dport)).unwrap(), XDP_PASS);
+    }
+
+    assert_eq!(count(&mut ebpf, Counter::Scan), 0);
+    assert_eq!(count(&mut ebpf, Counter::ScanDetected), 1);
+    assert!(ebpf.bans().unwrap().describe(SOURCE).is_none());
+}
+
+#[serial]
+#[tokio::test]
+async fn ban_trap_port_sender() {
+    let mut ebpf = Ebpf::detached().unwrap();
+
+    let ack = ethernet(ETH_P_IP, &ipv4(SOURCE, IPPROTO_TCP, &tcp(40000, 23, 0x10)));
+
+    ebpf.trap_ports().unwrap().insert(23, Origin::Repl).unwrap();
+
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_PASS);
+    assert_eq!(test_run(&ebpf, &udp_v4(SOURCE, 23)).unwrap(), XDP_PASS);
+    assert_eq!(test_run(&ebpf, &ack).unwrap(), XDP_PASS);
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 23)).unwrap(), XDP_DROP);
+    assert_eq!(test_run(&ebpf, &tcp_v4(SOURCE, 80)).unwrap(), XDP_DROP);
+    assert_eq!(count(&mut ebpf, Counter::Trap), 1);
+    assert_eq!(count(&mut ebpf, Counter::Blacklist), 1);
+    assert!(ebpf.bans().unwrap().describe(SOURCE).is_some());
+}
+
+#[serial]
+#[tokio::test]
+async fn pass_ipv6_without_counting() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let src = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    let frame = ethernet(ETH_P_IPV6, &ipv6(src, IPPROTO_TCP, &tcp(40000, 80, 0x02)));
+
+    assert_eq!(test_run(&ebpf, &frame).unwrap(), XDP_PASS);
+    assert_eq!(count(&mut ebpf, Counter::Pass), 0);
+}
+
+#[serial]
+#[tokio::test]
+async fn abort_truncated_ipv4() {
+    let mut ebpf = Ebpf::detached().unwrap();
+    let frame = ethernet(ETH_P_IP, &ipv4(SOURCE, IPPROTO_TCP, &[])[..12]);
+
+    assert_eq!(test_run(&ebpf, &frame).unwrap(), XDP_ABORTED);
+    assert_eq!(count(&mut ebpf, Counter::Pass), 0);
+}
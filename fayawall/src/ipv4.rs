@@ -1,5 +1,6 @@
 use std::net::Ipv4Addr;
 
+use ipnet::Ipv4Net;
 use tracing::warn;
 
 #[derive(Debug, PartialEq)]
@@ -21,6 +22,30 @@ impl Addr {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct Net(pub Vec<Ipv4Net>);
+
+impl Net {
+    pub fn parse(args: &[&str]) -> Self {
+        Self(
+            args.iter()
+                .filter_map(|arg| {
+                    match arg
+                        .parse::<Ipv4Net>()
+                        .or_else(|_| arg.parse::<Ipv4Addr>().map(Ipv4Net::from))
+                    {
+                        Ok(net) => Some(net.trunc()),
+                        Err(e) => {
+                            warn!(r#""{arg}" could not be parsed into Ipv4Net: {e}"#);
+                            None
+                        }
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,4 +78,21 @@ mod tests {
         let result = Addr::parse(&["127.0.0.1", "invalid"]);
         assert_eq!(result, Addr(vec![Ipv4Addr::new(127, 0, 0, 1)]));
     }
+
+    #[test]
+    fn parse_invalid_nets() {
+        let result = Net::parse(&["10.0.0.0/33", "invalid"]);
+        assert_eq!(result, Net(vec![]));
+    }
+
+    #[test]
+    fn parse_valid_nets() {
+        let result = Net::parse(&["10.0.0.1/8", "192.168.0.1"]);
+        let expected = Net(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "192.168.0.1/32".parse().unwrap(),
+        ]);
+
+        assert_eq!(result, expected);
+    }
 }
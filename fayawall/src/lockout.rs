@@ -0,0 +1,167 @@
+use std::{env, net::Ipv4Addr};
+
+use anyhow::bail;
+use aya::Ebpf;
+use common::DefaultAction;
+use ipnet::Ipv4Net;
+use tracing::warn;
+
+use crate::{
+    ebpf::Init,
+    ipv4::Net,
+    policy::{Ipv4Entry, Ipv4ListPolicy},
+};
+
+pub struct Lockout;
+
+impl Lockout {
+    pub fn apply(ebpf: &mut Ebpf, policy: Option<Ipv4ListPolicy>) -> anyhow::Result<()> {
+        Self::apply_from(ebpf, Self::ssh_peer(), policy)
+    }
+
+    fn apply_from(
+        ebpf: &mut Ebpf,
+        peer: Option<Ipv4Addr>,
+        policy: Option<Ipv4ListPolicy>,
+    ) -> anyhow::Result<()> {
+        let nets = Self::nets_from(peer, policy);
+
+        Self::check(&nets, ebpf.settings()?.default_action())?;
+        ebpf.management()?.replace(&nets)?;
+
+        Ok(())
+    }
+
+    pub fn check(nets: &[(Ipv4Net, u32)], default_action: DefaultAction) -> anyhow::Result<()> {
+        if nets.is_empty() && default_action == DefaultAction::Drop {
+            bail!("The last management address cannot be removed while default_action = \"drop\"");
+        }
+
+        Ok(())
+    }
+
+    pub fn default_drop(ebpf: &mut Ebpf) -> anyhow::Result<()> {
+        Self::default_drop_from(ebpf, Self::ssh_peer())
+    }
+
+    fn default_drop_from(ebpf: &mut Ebpf, peer: Option<Ipv4Addr>) -> anyhow::Result<()> {
+        let management = ebpf.management()?;
+
+        if management.is_empty() {
+            bail!("default_action = \"drop\" requires at least one management address");
+        }
+
+        if let Some(peer) = peer
+            && management.get(peer).is_none()
+        {
+            bail!("default_action = \"drop\" would lock out the SSH session from {peer}");
+        }
+
+        Ok(())
+    }
+
+    pub fn guard<'a>(ebpf: &mut Ebpf, args: &[&'a str]) -> anyhow::Result<Vec<&'a str>> {
+        let management = ebpf.management()?;
+
+        Ok(args
+            .iter()
+            .copied()
+            .filter(|arg| match arg.parse::<Ipv4Addr>() {
+                Ok(addr) if management.get(addr).is_some() => {
+                    warn!("{addr} is a management address and cannot be blacklisted");
+                    false
+                }
+                _ => true,
+            })
+            .collect())
+    }
+
+    pub fn nets(policy: Option<Ipv4ListPolicy>) -> Vec<(Ipv4Net, u32)> {
+        Self::nets_from(Self::ssh_peer(), policy)
+    }
+
+    fn nets_from(peer: Option<Ipv4Addr>, policy: Option<Ipv4ListPolicy>) -> Vec<(Ipv4Net, u32)> {
+        let mut nets = peer
+            .map(|addr| (Ipv4Net::from(addr), 0))
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        if let Some(Ipv4ListPolicy { ipv4: Some(ipv4) }) = policy {
+            let args = ipv4.iter().map(Ipv4Entry::addr).collect::<Vec<_>>();
+
+            nets.extend(Net::parse(&args).0.into_iter().map(|net| (net, 0)));
+        }
+
+        nets
+    }
+
+    fn peer(ssh_connection: &str) -> Option<Ipv4Addr> {
+        ssh_connection.split_whitespace().next()?.parse().ok()
+    }
+
+    fn ssh_peer() -> Option<Ipv4Addr> {
+        env::var("SSH_CONNECTION")
+            .or_else(|_| env::var("SSH_CLIENT"))
+            .ok()
+            .and_then(|ssh_connection| Self::peer(&ssh_connection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::audit::Origin;
+
+    #[serial]
+    #[tokio::test]
+    async fn default_drop_requires_ssh_peer_in_management() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let peer = Ipv4Addr::new(192, 0, 2, 10);
+
+        ebpf.management()
+            .unwrap()
+            .insert("10.0.0.0/8".parse().unwrap(), 0)
+            .unwrap();
+
+        assert!(Lockout::default_drop_from(&mut ebpf, Some(peer)).is_err());
+        assert!(Lockout::default_drop_from(&mut ebpf, None).is_ok());
+
+        ebpf.management()
+            .unwrap()
+            .insert("192.0.2.0/24".parse().unwrap(), 0)
+            .unwrap();
+
+        assert!(Lockout::default_drop_from(&mut ebpf, Some(peer)).is_ok());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn keep_last_management_address_under_default_drop() {
+        let mut ebpf = Ebpf::detached().unwrap();
+
+        Lockout::apply_from(&mut ebpf, Some(Ipv4Addr::new(192, 0, 2, 10)), None).unwrap();
+        ebpf.settings()
+            .unwrap()
+            .set_default_action(DefaultAction::Drop, Origin::Repl)
+            .unwrap();
+
+        assert!(Lockout::apply_from(&mut ebpf, None, None).is_err());
+        assert!(!ebpf.management().unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_ssh_peer() {
+        assert_eq!(
+            Lockout::peer("192.0.2.10 51234 192.0.2.1 22"),
+            Some(Ipv4Addr::new(192, 0, 2, 10))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_ssh_peer() {
+        assert_eq!(Lockout::peer("fe80::1 51234 fe80::2 22"), None);
+        assert_eq!(Lockout::peer(""), None);
+    }
+}
@@ -1,29 +1,62 @@
-use std::io::stdout;
+use std::{
+    io::stdout,
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
 
 use log::LevelFilter::Info;
 use tracing::{Level, subscriber::set_global_default};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_log::LogTracer;
-use tracing_subscriber::{Registry, filter::Targets, fmt::Layer, prelude::*};
+use tracing_subscriber::{
+    Registry,
+    filter::{FilterExt, Targets, filter_fn},
+    fmt::Layer,
+    prelude::*,
+};
+
+use crate::trace;
+
+static STDOUT: AtomicBool = AtomicBool::new(true);
 
 pub struct Log;
 
 impl Log {
-    pub fn init() -> anyhow::Result<WorkerGuard> {
+    pub fn init() -> anyhow::Result<[WorkerGuard; 2]> {
         let file_appender = tracing_appender::rolling::never(".", "fayawall.log");
         let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
-        let file_layer = Layer::default().with_ansi(false).with_writer(file_writer);
+        let file_layer = Layer::default()
+            .with_ansi(false)
+            .with_writer(file_writer)
+            .with_filter(filter_fn(|metadata| metadata.target() != trace::TARGET));
+        let trace_appender = tracing_appender::rolling::never(".", "trace.log");
+        let (trace_writer, trace_guard) = tracing_appender::non_blocking(trace_appender);
+        let trace_layer = Layer::default()
+            .with_ansi(false)
+            .with_target(false)
+            .with_writer(trace_writer)
+            .with_filter(Targets::new().with_target(trace::TARGET, Level::INFO));
         let stdout_layer = Layer::default()
             .with_level(false)
             .with_target(false)
             .with_writer(stdout)
             .without_time()
-            .with_filter(Targets::new().with_target("fayawall::", Level::INFO));
-        let subscriber = Registry::default().with(file_layer).with(stdout_layer);
+            .with_filter(
+                Targets::new()
+                    .with_target("fayawall::", Level::INFO)
+                    .and(filter_fn(|_| STDOUT.load(Relaxed))),
+            );
+        let subscriber = Registry::default()
+            .with(file_layer)
+            .with(stdout_layer)
+            .with(trace_layer);
 
         LogTracer::builder().with_max_level(Info).init()?;
         set_global_default(subscriber)?;
 
-        Ok(guard)
+        Ok([guard, trace_guard])
+    }
+
+    pub fn stdout(enabled: bool) {
+        STDOUT.store(enabled, Relaxed);
     }
 }
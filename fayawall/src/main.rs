@@ -1,102 +1,489 @@
-use std::io::{Write, stdin, stdout};
+use std::time::Duration;
 
 use aya::Ebpf;
-use tracing::{info, warn};
+use clap::Parser;
+use common::{Counter, DefaultAction, FragmentMode, Sanity, ScanTrack, Setting, TcpAnomaly};
+use humantime::{format_duration, parse_duration};
+use tokio::time::interval;
+use tracing::{error, info, warn};
 
-use crate::{ebpf::Init, log::Log, policy::Policy};
+use crate::{
+    arg::Arg,
+    asn::Asn,
+    audit::{Audit, Origin, Query},
+    bench::Bench,
+    bogon::Bogon,
+    commit::Commit,
+    console::Console,
+    dashboard::Dashboard,
+    ebpf::Init,
+    events::Events,
+    explain::Explain,
+    geo::Geo,
+    lockout::Lockout,
+    log::Log,
+    pin::Pin,
+    policy::{Policy, SynProtectionPolicy},
+    replay::Replay,
+    state::State,
+    top::Top,
+    trace::Trace,
+    upgrade::Upgrade,
+};
 
 mod arg;
+mod asn;
+mod audit;
+mod bench;
+mod bogon;
+mod commit;
+mod console;
+mod dashboard;
 mod ebpf;
+mod events;
+mod explain;
+mod geo;
+#[cfg(test)]
+mod harness;
 mod ipv4;
 mod license;
+mod lockout;
 mod log;
 mod maps;
+mod metadata;
+mod mmdb;
+mod packet;
+mod pcap;
+mod pin;
 mod policy;
+mod replay;
+mod sink;
+mod state;
+mod test_run;
+mod top;
+mod trace;
+mod upgrade;
 
 const TARGET: &str = "fayawall::main";
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let _guard = Log::init()?;
+    let _guards = Log::init()?;
 
     info!(target: TARGET, "Starting");
 
-    let mut cmd = String::new();
+    let mut commit = None::<Commit>;
+    let mut console = Console::spawn();
     let mut ebpf = Ebpf::init()?;
+    let (events, mut listener) = Events::spawn(&mut ebpf)?;
+
+    let mut tracer = Trace::listen(&mut ebpf)?;
 
     #[cfg(all(feature = "license", not(test)))]
     license::License::verify().await?;
 
-    Policy::apply(&mut ebpf)?;
+    if let Err(e) = Lockout::apply(&mut ebpf, None) {
+        warn!(target: TARGET, "Management addresses kept: {e}");
+    }
+    if let Err(e) = Policy::apply(&mut ebpf) {
+        error!(target: TARGET, "Policy not applied, rolled back: {e}");
+    }
+    State::replay(&mut ebpf)?;
 
-    loop {
-        cmd.clear();
-        print!("fayawall> ");
-        stdout().flush()?;
-        stdin().read_line(&mut cmd)?;
+    let mut ticks = interval(TICK_INTERVAL);
 
-        let args = cmd.split_whitespace().collect::<Vec<_>>();
+    loop {
+        let cmd = tokio::select! {
+            line = console.read_line() => match line {
+                Some(line) => line,
+                None => break,
+            },
+            _ = ticks.tick() => {
+                housekeep(&mut ebpf, &mut commit);
+                continue;
+            }
+        };
+        let Some(words) = shlex::split(&cmd) else {
+            warn!(target: TARGET, "Unbalanced quotes in `{cmd}`");
+            continue;
+        };
+        let args = words.iter().map(String::as_str).collect::<Vec<_>>();
 
-        if let Some((head, tail)) = args.split_at_checked(2) {
-            match head {
-                ["blacklist", "add"] => ebpf.blacklist()?.add(tail),
+        match args.as_slice() {
+            [] => continue,
 
-                ["blacklist", "del"] => ebpf.blacklist()?.del(tail),
+            ["asn", "get"] => println!("{} blocked prefixes", ebpf.asn_prefixes()?.len()),
 
-                ["blacklist", "get"] => println!("{}", ebpf.blacklist()?),
+            ["audit", tail @ ..] => match Query::parse(tail) {
+                Ok(query) => match Audit::query(&query) {
+                    Ok(entries) => entries.iter().for_each(|entry| println!("{entry}")),
+                    Err(e) => warn!(target: TARGET, "Audit log could not be read: {e}"),
+                },
+                Err(e) => warn!(target: TARGET, "Invalid audit arguments: {e}"),
+            },
 
-                ["packet_limit", "get"] => {
-                    if let Ok(packet_limit) = ebpf.rate_limit_settings()?.get_packet_limit() {
-                        println!("{packet_limit}");
-                    } else {
-                        info!(target: TARGET, "`packet_limit` not set");
+            ["bench", tail @ ..] => match Bench::parse(tail) {
+                Ok(bench) => {
+                    if let Err(e) = bench.run() {
+                        warn!(target: TARGET, "Benchmark failed: {e}");
                     }
                 }
+                Err(e) => warn!(target: TARGET, "Invalid bench arguments: {e}"),
+            },
 
-                ["packet_limit", "set"] => {
-                    let arg = tail.first().unwrap_or(&"").parse::<u64>();
+            ["bans", "del", tail @ ..] => ebpf.bans()?.del(tail, Origin::Repl),
 
-                    match arg {
-                        Ok(limit) => ebpf.rate_limit_settings()?.set_packet_limit(limit)?,
-                        Err(e) => warn!(target: TARGET, "Invalid packet limit: {e}"),
+            ["bans", "get"] => println!("{}", ebpf.bans()?),
+
+            ["blacklist", "add", tail @ ..] => {
+                let tail = Lockout::guard(&mut ebpf, tail)?;
+
+                ebpf.blacklist()?.add(&tail, Origin::Repl);
+            }
+
+            ["blacklist", "del", tail @ ..] => ebpf.blacklist()?.del(tail, Origin::Repl),
+
+            ["blacklist", "get"] => println!("{:#}", ebpf.blacklist()?),
+
+            ["commit"] => match commit.take() {
+                Some(pending) => {
+                    if let Err(e) = pending.confirm(&mut ebpf) {
+                        error!(target: TARGET, "Changes could not be confirmed: {e}");
                     }
                 }
+                None => info!(target: TARGET, "No changes pending confirmation"),
+            },
 
-                ["whitelist", "add"] => ebpf.whitelist()?.add(tail),
+            ["commit", "confirmed", timeout] => match parse_duration(timeout) {
+                Ok(_) if commit.is_some() => {
+                    warn!(target: TARGET, "Changes are already pending confirmation")
+                }
+                Ok(timeout) => commit = Some(Commit::confirmed(&mut ebpf, timeout)?),
+                Err(e) => warn!(target: TARGET, "Invalid timeout: {e}"),
+            },
 
-                ["whitelist", "del"] => ebpf.whitelist()?.del(tail),
+            ["bogon", "disable"] => ebpf.settings()?.set(Setting::Bogon, 0, Origin::Repl)?,
 
-                ["whitelist", "get"] => println!("{}", ebpf.whitelist()?),
+            ["bogon", "enable"] => {
+                let generation = ebpf.generation()?.get()?;
 
-                ["window_size", "get"] => {
-                    if let Ok(window_size) = ebpf.rate_limit_settings()?.get_window_size() {
-                        println!("{window_size}");
-                    } else {
-                        info!(target: TARGET, "`window_size` not set");
+                Bogon::enable(&mut ebpf, generation, Origin::Repl)?;
+            }
+
+            ["bogon", "get"] => {
+                let enabled = ebpf.settings()?.get(Setting::Bogon) != 0;
+
+                println!("enabled: {enabled}");
+                println!("list: {} prefixes", ebpf.bogons()?.len());
+                println!("drops: {}", ebpf.counters()?.get(Counter::Bogon)?);
+            }
+
+            ["bogon", "load", path] => {
+                let generation = ebpf.generation()?.get()?;
+
+                if let Err(e) = Bogon::update(&mut ebpf, Some(path), generation, Origin::Repl) {
+                    warn!(target: TARGET, "Bogon list not loaded: {e}");
+                }
+            }
+
+            ["conntrack", "flush"] => ebpf.conntrack()?.flush(Origin::Repl)?,
+
+            ["conntrack", "list"] => print!("{}", ebpf.conntrack()?),
+
+            ["default_action", "get"] => println!("{}", ebpf.settings()?.default_action().name()),
+
+            ["default_action", "set", "drop"] => match Lockout::default_drop(&mut ebpf) {
+                Ok(()) => {
+                    if commit.is_none() {
+                        warn!(target: TARGET, "Consider `commit confirmed <timeout>` before dropping by default");
                     }
+                    ebpf.settings()?
+                        .set_default_action(DefaultAction::Drop, Origin::Repl)?;
+                }
+                Err(e) => warn!(target: TARGET, "{e}"),
+            },
+
+            ["default_action", "set", "pass"] => ebpf
+                .settings()?
+                .set_default_action(DefaultAction::Pass, Origin::Repl)?,
+
+            ["dashboard"] => {
+                Dashboard::run(&mut ebpf, &events, &mut |ebpf| housekeep(ebpf, &mut commit))?
+            }
+
+            ["exit"] => break,
+
+            ["explain", tail @ ..] => match Explain::parse(tail) {
+                Ok(explain) => print!("{}", explain.run(&mut ebpf)?),
+                Err(e) => warn!(target: TARGET, "Invalid explain arguments: {e}"),
+            },
+
+            ["fragments", "get"] => {
+                let settings = ebpf.settings()?;
+                let mode = match settings.get(Setting::FragmentMode) {
+                    mode if mode == FragmentMode::Drop as u64 => FragmentMode::Drop,
+                    mode if mode == FragmentMode::Track as u64 => FragmentMode::Track,
+                    _ => FragmentMode::Pass,
+                };
+                let drop_tiny = settings.get(Setting::FragmentDropTiny) != 0;
+                let counters = ebpf.counters()?;
+
+                println!("mode={} drop_tiny={drop_tiny}", mode.name());
+
+                for counter in [
+                    Counter::Fragment,
+                    Counter::TinyFragment,
+                    Counter::FragmentUntracked,
+                ] {
+                    println!("{} drops={}", counter.name(), counters.get(counter)?);
+                }
+            }
+
+            ["geo", "get"] => {
+                println!("database: {} prefixes", ebpf.geo_db()?.len());
+
+                for (country, rule) in ebpf.geo_rules()?.get() {
+                    println!("{} {}", Geo::name(country), rule.name());
+                }
+
+                for (country, drops) in ebpf.geo_drops()?.entries() {
+                    println!("{} drops={drops}", Geo::name(country));
+                }
+            }
+
+            ["geo", "load", path] => {
+                let generation = ebpf.generation()?.get()?;
+
+                if let Err(e) = Geo::update(&mut ebpf, path, generation, Origin::Repl) {
+                    warn!(target: TARGET, "Geo database not loaded: {e}");
+                }
+            }
+
+            ["packet_limit", "get"] => {
+                if let Some(packet_limit) = ebpf.settings()?.find(Setting::PacketLimit) {
+                    println!("{packet_limit}");
+                } else {
+                    info!(target: TARGET, "`packet_limit` not set");
+                }
+            }
+
+            ["packet_limit", "set", tail @ ..] => {
+                let arg = tail.first().unwrap_or(&"").parse::<u64>();
+
+                match arg {
+                    Ok(limit) => ebpf
+                        .settings()?
+                        .set(Setting::PacketLimit, limit, Origin::Repl)?,
+                    Err(e) => warn!(target: TARGET, "Invalid packet limit: {e}"),
                 }
+            }
+
+            ["policy", "reload"] if commit.is_some() => {
+                warn!(target: TARGET, "Confirm or revert pending changes before reloading policy")
+            }
 
-                ["window_size", "set"] => {
-                    let arg = tail.first().unwrap_or(&"").parse::<u64>();
+            ["policy", "reload"] => match Policy::apply(&mut ebpf) {
+                Ok(()) => State::replay(&mut ebpf)?,
+                Err(e) => {
+                    error!(target: TARGET, "Policy not reloaded, running generation kept: {e}")
+                }
+            },
 
-                    match arg {
-                        Ok(size) => ebpf.rate_limit_settings()?.set_window_size(size)?,
-                        Err(e) => warn!(target: TARGET, "Invalid window size: {e}"),
+            ["replay", tail @ ..] => match Replay::parse(tail) {
+                Ok(replay) => {
+                    if let Err(e) = replay.run() {
+                        warn!(target: TARGET, "Replay failed: {e}");
                     }
                 }
+                Err(e) => warn!(target: TARGET, "Invalid replay arguments: {e}"),
+            },
+
+            ["sanity", "get"] => {
+                let checks = ebpf.settings()?.get(Setting::Sanity);
+                let counters = ebpf.counters()?;
+
+                for sanity in Sanity::ALL {
+                    let counter = sanity.counter();
+
+                    println!(
+                        "{} enabled={} drops={}",
+                        counter.name(),
+                        checks & sanity.bit() != 0,
+                        counters.get(counter)?
+                    );
+                }
+            }
 
-                invalid_cmd => warn!(target: TARGET, "Invalid command: {invalid_cmd:?}"),
+            ["scan_detection", "get"] => {
+                let settings = ebpf.settings()?;
+                let threshold = settings.get(Setting::ScanThreshold);
+                let window = Duration::from_nanos(settings.get(Setting::ScanWindow));
+                let track = if settings.get(Setting::ScanTrack) == ScanTrack::Hosts as u64 {
+                    ScanTrack::Hosts
+                } else {
+                    ScanTrack::Ports
+                };
+                let ban = Duration::from_secs(settings.get(Setting::ScanBanTtl));
+                let counters = ebpf.counters()?;
+
+                println!(
+                    "threshold={threshold} window={} track={} ban={} detections={}",
+                    format_duration(window),
+                    track.name(),
+                    format_duration(ban),
+                    counters.get(Counter::Scan)? + counters.get(Counter::ScanDetected)?
+                );
             }
-        } else {
-            match args.as_slice() {
-                [] => continue,
-                ["exit"] => break,
-                invalid_cmd => warn!(target: TARGET, "Invalid command: {invalid_cmd:?}"),
+
+            ["state", "clear"] => State::clear(),
+
+            ["syn_protection", "get"] => {
+                let threshold = ebpf.settings()?.get(Setting::SynThreshold);
+
+                let syncookies = SynProtectionPolicy::syncookies()
+                    .map_or_else(|| "unknown".to_owned(), |value| value.to_string());
+
+                println!(
+                    "threshold={threshold} ports={} tcp_syncookies={syncookies}",
+                    ebpf.syn_ports()?
+                );
             }
+
+            ["tcp_anomalies", "get"] => {
+                let settings = ebpf.settings()?;
+                let drop = settings.get(Setting::TcpAnomalyDrop);
+                let log = settings.get(Setting::TcpAnomalyLog);
+                let logs = ebpf.tcp_anomaly_logs()?;
+                let logs = TcpAnomaly::ALL
+                    .map(|anomaly| logs.get(anomaly))
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()?;
+                let counters = ebpf.counters()?;
+
+                for (anomaly, logged) in TcpAnomaly::ALL.into_iter().zip(logs) {
+                    let counter = anomaly.counter();
+                    let action = if drop & anomaly.bit() != 0 {
+                        "drop"
+                    } else if log & anomaly.bit() != 0 {
+                        "log"
+                    } else {
+                        "pass"
+                    };
+
+                    println!(
+                        "{} action={action} count={} logged={logged}",
+                        counter.name(),
+                        counters.get(counter)?
+                    );
+                }
+            }
+
+            ["top", tail @ ..] => match Top::parse(tail) {
+                Ok(top) => {
+                    top.run(&mut ebpf, |ebpf| housekeep(ebpf, &mut commit))
+                        .await?
+                }
+                Err(e) => warn!(target: TARGET, "Invalid top arguments: {e}"),
+            },
+
+            ["trace", "add", tail @ ..] => match Trace::parse(tail) {
+                Ok((nets, duration)) => Trace::add(&mut ebpf, &nets, duration)?,
+                Err(e) => warn!(target: TARGET, "Invalid trace arguments: {e}"),
+            },
+
+            ["trace", "del", tail @ ..] => Trace::del(&mut ebpf, tail, Origin::Repl)?,
+
+            ["trace", "get"] => println!("{}", Trace::get(&mut ebpf)?),
+
+            ["trap", "get"] => {
+                let ban = Duration::from_secs(ebpf.settings()?.get(Setting::TrapBanTtl));
+                let hits = ebpf.counters()?.get(Counter::Trap)?;
+
+                println!(
+                    "ports={} ban={} hits={hits}",
+                    ebpf.trap_ports()?,
+                    format_duration(ban)
+                );
+            }
+
+            ["upgrade", _] if commit.is_some() => {
+                warn!(target: TARGET, "Confirm or revert pending changes before upgrading")
+            }
+
+            ["upgrade", path] => {
+                if Upgrade::run(&mut ebpf, path).is_ok() {
+                    listener.abort();
+                    listener = Events::listen(&mut ebpf, events.clone())?;
+                    tracer.abort();
+                    tracer = Trace::listen(&mut ebpf)?;
+                }
+            }
+
+            ["whitelist", "add", tail @ ..] => ebpf.whitelist()?.add(tail, Origin::Repl),
+
+            ["whitelist", "del", tail @ ..] => ebpf.whitelist()?.del(tail, Origin::Repl),
+
+            ["whitelist", "get"] => println!("{:#}", ebpf.whitelist()?),
+
+            ["window_size", "get"] => {
+                if let Some(window_size) = ebpf.settings()?.find(Setting::WindowSize) {
+                    println!("{window_size}");
+                } else {
+                    info!(target: TARGET, "`window_size` not set");
+                }
+            }
+
+            ["window_size", "set", tail @ ..] => {
+                let arg = tail.first().unwrap_or(&"").parse::<u64>();
+
+                match arg {
+                    Ok(size) => ebpf
+                        .settings()?
+                        .set(Setting::WindowSize, size, Origin::Repl)?,
+                    Err(e) => warn!(target: TARGET, "Invalid window size: {e}"),
+                }
+            }
+
+            invalid_cmd => warn!(target: TARGET, "Invalid command: {invalid_cmd:?}"),
         }
     }
 
     info!(target: TARGET, "Exiting");
 
+    if let Arg {
+        cleanup: true,
+        iface,
+        ..
+    } = Arg::parse()
+    {
+        Pin::cleanup(&iface)?;
+        ebpf::detach_egress(&iface)?;
+    }
+
     Ok(())
 }
+
+fn housekeep(ebpf: &mut Ebpf, commit: &mut Option<Commit>) {
+    if let Some(pending) = commit.take_if(|pending| pending.reverted())
+        && let Err(e) = pending.finish(ebpf)
+    {
+        error!(target: TARGET, "Reverted changes could not be cleared: {e}");
+        *commit = Some(pending);
+    }
+    if let Err(e) = Trace::expire(ebpf) {
+        error!(target: TARGET, "Traces could not be expired: {e}");
+    }
+    // A pending commit keeps its rollback in the inactive generation.
+    if commit.is_none() {
+        Asn::refresh(ebpf);
+    }
+    match ebpf.bans() {
+        Ok(mut bans) => bans.expire(),
+        Err(e) => error!(target: TARGET, "Bans could not be expired: {e}"),
+    }
+    match ebpf.blacklist() {
+        Ok(mut blacklist) => blacklist.expire(),
+        Err(e) => error!(target: TARGET, "Blacklist could not be expired: {e}"),
+    }
+}
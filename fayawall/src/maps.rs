@@ -1,2 +1,26 @@
+use aya::{
+    Pod,
+    maps::{IterableMap, MapError},
+};
+
+pub mod asn_prefixes;
+pub mod bans;
+pub mod bogons;
+pub mod conntrack;
+pub mod counters;
+pub mod generation;
+pub mod geo_db;
+pub mod geo_drops;
+pub mod geo_rules;
 pub mod ipv4_list;
-pub mod rate_limit_settings;
+pub mod port_set;
+pub mod prefix_list;
+pub mod rate_limit_windows;
+pub mod raw;
+pub mod settings;
+pub mod source_stats;
+pub mod tcp_anomaly_logs;
+
+pub fn capacity<K: Pod, V, M: IterableMap<K, V>>(map: &M) -> Result<u32, MapError> {
+    Ok(map.map().info()?.max_entries())
+}
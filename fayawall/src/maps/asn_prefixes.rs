@@ -0,0 +1,113 @@
+use std::{collections::HashSet, net::Ipv4Addr};
+
+use aya::maps::{LpmTrie, MapData, MapError, lpm_trie::Key};
+use ipnet::Ipv4Net;
+use tracing::info;
+
+pub struct AsnPrefixes<'a> {
+    generation: u32,
+    inner: LpmTrie<&'a mut MapData, [u8; 8], u32>,
+}
+
+impl<'a> AsnPrefixes<'a> {
+    pub fn asn(&self, addr: Ipv4Addr) -> Option<u32> {
+        self.inner.get(&self.key(addr, 32), 0).ok()
+    }
+
+    pub fn clear(&mut self) -> Result<(), MapError> {
+        for key in self.keys() {
+            self.inner.remove(&key)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn copy_to(&mut self, generation: u32) -> Result<(), MapError> {
+        for (net, asn) in self.get() {
+            let key = Self::key_at(generation, net.network(), net.prefix_len());
+
+            self.inner.insert(&key, asn, 0)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self) -> Vec<(Ipv4Net, u32)> {
+        self.inner
+            .iter()
+            .flatten()
+            .filter_map(|(key, asn)| Some((self.net(&key)?, asn)))
+            .collect()
+    }
+
+    fn key(&self, addr: Ipv4Addr, prefix_len: u8) -> Key<[u8; 8]> {
+        Self::key_at(self.generation, addr, prefix_len)
+    }
+
+    fn key_at(generation: u32, addr: Ipv4Addr, prefix_len: u8) -> Key<[u8; 8]> {
+        let mut data = [0; 8];
+
+        data[..4].copy_from_slice(&generation.to_be_bytes());
+        data[4..].copy_from_slice(&addr.octets());
+
+        Key::new(32 + u32::from(prefix_len), data)
+    }
+
+    fn keys(&self) -> Vec<Key<[u8; 8]>> {
+        self.inner
+            .keys()
+            .flatten()
+            .filter(|key| self.net(key).is_some())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys().len()
+    }
+
+    pub fn load(&mut self, nets: &[(Ipv4Net, u32)]) -> Result<(), MapError> {
+        for &(net, asn) in nets {
+            self.inner
+                .insert(&self.key(net.network(), net.prefix_len()), asn, 0)?;
+        }
+
+        let keep = nets.iter().map(|&(net, _)| net).collect::<HashSet<_>>();
+        let stale = self
+            .keys()
+            .into_iter()
+            .filter(|key| self.net(key).is_some_and(|net| !keep.contains(&net)))
+            .collect::<Vec<_>>();
+
+        for key in &stale {
+            self.inner.remove(key)?;
+        }
+
+        info!(
+            "{} asn prefixes loaded, {} stale prefixes removed",
+            nets.len(),
+            stale.len()
+        );
+
+        Ok(())
+    }
+
+    fn net(&self, key: &Key<[u8; 8]>) -> Option<Ipv4Net> {
+        let data = key.data();
+        let (generation, addr) = data.split_at(4);
+
+        if generation != self.generation.to_be_bytes() {
+            return None;
+        }
+
+        let addr = Ipv4Addr::from(<[u8; 4]>::try_from(addr).ok()?);
+
+        Ipv4Net::new(addr, u8::try_from(key.prefix_len().checked_sub(32)?).ok()?).ok()
+    }
+
+    pub fn new(map: LpmTrie<&'a mut MapData, [u8; 8], u32>, generation: u32) -> Self {
+        Self {
+            generation,
+            inner: map,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    net::Ipv4Addr,
+};
+
+use aya::maps::{HashMap, MapData, MapError};
+use tracing::{error, info};
+
+use crate::{
+    audit::{Audit, Origin},
+    ipv4::Addr,
+    metadata::{Metadata, ktime_now, system_time_to_ktime},
+    state::{Record, State},
+};
+
+// Bans outlive policy generations, so their metadata is kept under generation 0.
+const GENERATION: u32 = 0;
+pub const LABEL: &str = "bans";
+
+pub struct Bans<'a>(pub HashMap<&'a mut MapData, u32, u64>);
+
+impl<'a> Bans<'a> {
+    pub fn del(&mut self, args: &[&str], origin: Origin) {
+        for &addr in Addr::parse(args).0.as_slice().iter() {
+            let result = self.0.remove(&addr.to_bits());
+
+            if let Err(ref e) = result {
+                error!("{addr} could not be removed from {LABEL}: {e}");
+            } else {
+                Metadata::remove(LABEL, GENERATION, addr);
+                info!("{addr} removed from {LABEL}");
+                State::record(Record::Del {
+                    list: LABEL.to_string(),
+                    addr,
+                });
+            }
+
+            Audit::record(origin, &format!("{LABEL}.del"), &[addr], &result);
+        }
+    }
+
+    pub fn describe(&self, addr: Ipv4Addr) -> Option<String> {
+        self.0.get(&addr.to_bits(), 0).ok()?;
+
+        Some(match Metadata::get(LABEL, GENERATION, addr) {
+            Some(metadata) => format!("{addr} {metadata}"),
+            None => format!("{addr} origin=dataplane"),
+        })
+    }
+
+    pub fn entries(&self) -> Vec<(Ipv4Addr, Option<Metadata>)> {
+        self.0
+            .keys()
+            .flatten()
+            .map(Ipv4Addr::from_bits)
+            .map(|addr| (addr, Metadata::get(LABEL, GENERATION, addr)))
+            .collect()
+    }
+
+    pub fn expire(&mut self) {
+        let Some(now) = ktime_now() else {
+            return;
+        };
+        let now = now.as_nanos() as u64;
+        let expired = self
+            .0
+            .iter()
+            .flatten()
+            .filter(|&(_, expires)| expires <= now)
+            .map(|(addr, _)| Ipv4Addr::from_bits(addr).to_string())
+            .collect::<Vec<_>>();
+
+        if !expired.is_empty() {
+            let expired = expired.iter().map(String::as_str).collect::<Vec<_>>();
+
+            self.del(&expired, Origin::Timeout);
+        }
+    }
+
+    pub fn record(addr: Ipv4Addr, metadata: Metadata) {
+        Metadata::insert(LABEL, GENERATION, addr, metadata.clone());
+        State::record(Record::Add {
+            list: LABEL.to_string(),
+            addr,
+            metadata,
+        });
+        Audit::record(
+            Origin::Dataplane,
+            &format!("{LABEL}.add"),
+            &[addr],
+            &Ok::<(), &str>(()),
+        );
+    }
+
+    pub fn restore(&mut self, addr: Ipv4Addr, metadata: Metadata) -> Result<(), MapError> {
+        let expires = match metadata.expires_at {
+            Some(expires_at) => system_time_to_ktime(expires_at).unwrap_or_default(),
+            None => u64::MAX,
+        };
+        let result = self.0.insert(addr.to_bits(), expires, 0);
+
+        if let Err(ref e) = result {
+            error!("{addr} could not be restored to {LABEL}: {e}");
+        } else {
+            Metadata::insert(LABEL, GENERATION, addr, metadata);
+            info!("{addr} restored to {LABEL}");
+        }
+
+        result
+    }
+}
+
+impl<'a> Display for Bans<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let bans = self
+            .entries()
+            .into_iter()
+            .map(|(addr, _)| self.describe(addr).unwrap_or_else(|| addr.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        write!(f, "{bans}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use aya::Ebpf;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{ebpf::Init, metadata::Annotation};
+
+    #[serial]
+    #[tokio::test]
+    async fn expire_bans() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut bans = ebpf.bans().unwrap();
+        let mut expired = Metadata::new(Annotation::default(), Origin::Dataplane);
+        let mut active = expired.clone();
+
+        expired.expires_at = Some(SystemTime::now() - Duration::from_secs(1));
+        active.expires_at = Some(SystemTime::now() + Duration::from_secs(600));
+        bans.restore(Ipv4Addr::new(192, 0, 2, 1), expired).unwrap();
+        bans.restore(Ipv4Addr::new(192, 0, 2, 2), active).unwrap();
+        bans.expire();
+
+        assert_eq!(
+            bans.entries()
+                .into_iter()
+                .map(|(addr, _)| addr)
+                .collect::<Vec<_>>(),
+            [Ipv4Addr::new(192, 0, 2, 2)]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    net::Ipv4Addr,
+    time::Duration,
+};
+
+use aya::maps::{HashMap, MapData, MapError};
+use common::{Flow, FlowKey, IPPROTO_ICMP, IPPROTO_TCP, IPPROTO_UDP};
+use humantime::format_duration;
+use tracing::info;
+
+use crate::{
+    audit::{Audit, Origin},
+    metadata::ktime_now,
+};
+
+pub struct Conntrack<'a>(pub HashMap<&'a mut MapData, FlowKey, Flow>);
+
+impl<'a> Conntrack<'a> {
+    pub fn flows(&self) -> Vec<(FlowKey, Flow)> {
+        let now = ktime_now().unwrap_or_default().as_nanos() as u64;
+
+        self.0
+            .iter()
+            .flatten()
+            .filter(|(key, flow)| !flow.expired(key.protocol, now))
+            .collect()
+    }
+
+    pub fn flush(&mut self, origin: Origin) -> Result<(), MapError> {
+        let keys = self.0.keys().flatten().collect::<Vec<_>>();
+        let result = keys.iter().try_for_each(|key| self.0.remove(key));
+
+        if result.is_ok() {
+            info!("{} flows flushed from conntrack", keys.len());
+        }
+
+        Audit::record(origin, "conntrack.flush", &[keys.len()], &result);
+
+        result
+    }
+}
+
+impl<'a> Display for Conntrack<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let now = ktime_now().unwrap_or_default().as_nanos() as u64;
+
+        writeln!(
+            f,
+            "{:<5} {:<21} {:<21} {:<12} {:>8} {:>10}",
+            "PROTO", "REMOTE", "LOCAL", "STATE", "PACKETS", "IDLE"
+        )?;
+
+        for (key, flow) in self.flows() {
+            let protocol = match key.protocol {
+                IPPROTO_ICMP => "icmp",
+                IPPROTO_TCP => "tcp",
+                IPPROTO_UDP => "udp",
+                _ => "-",
+            };
+            let idle = now.saturating_sub(flow.last_seen) / 1_000_000_000;
+
+            writeln!(
+                f,
+                "{protocol:<5} {:<21} {:<21} {:<12} {:>8} {:>10}",
+                format!("{}:{}", Ipv4Addr::from_bits(key.remote), key.remote_port),
+                format!("{}:{}", Ipv4Addr::from_bits(key.local), key.local_port),
+                flow.state.name(),
+                flow.packets,
+                format_duration(Duration::from_secs(idle)).to_string()
+            )?;
+        }
+
+        Ok(())
+    }
+}
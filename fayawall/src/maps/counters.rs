@@ -0,0 +1,31 @@
+use aya::maps::{MapData, MapError, PerCpuArray};
+use common::Counter;
+
+pub struct Counters<'a>(pub PerCpuArray<&'a mut MapData, u64>);
+
+impl<'a> Counters<'a> {
+    pub fn get(&self, counter: Counter) -> Result<u64, MapError> {
+        Ok(self.0.get(&(counter as u32), 0)?.iter().sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aya::Ebpf;
+    use common::Counter;
+    use serial_test::serial;
+
+    use crate::ebpf::Init;
+
+    #[serial]
+    #[tokio::test]
+    async fn get_counters() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let counters = ebpf.counters().unwrap();
+
+        for counter in Counter::ALL {
+            assert!(counters.get(counter).is_ok());
+        }
+        assert_eq!(counters.get(Counter::Blacklist).unwrap(), 0);
+    }
+}
@@ -0,0 +1,17 @@
+use std::borrow::{Borrow, BorrowMut};
+
+use aya::maps::{Array, MapData, MapError};
+
+pub struct Generation<T>(pub Array<T, u32>);
+
+impl<T: Borrow<MapData>> Generation<T> {
+    pub fn get(&self) -> Result<u32, MapError> {
+        self.0.get(&0, 0)
+    }
+}
+
+impl<T: BorrowMut<MapData>> Generation<T> {
+    pub fn set(&mut self, generation: u32) -> Result<(), MapError> {
+        self.0.set(0, generation, 0)
+    }
+}
@@ -0,0 +1,17 @@
+use aya::maps::{MapData, PerCpuHashMap};
+
+pub struct GeoDrops<'a>(pub PerCpuHashMap<&'a mut MapData, u32, u64>);
+
+impl<'a> GeoDrops<'a> {
+    pub fn entries(&self) -> Vec<(u32, u64)> {
+        let mut entries = self
+            .0
+            .iter()
+            .flatten()
+            .map(|(country, drops)| (country, drops.iter().sum::<u64>()))
+            .collect::<Vec<_>>();
+
+        entries.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        entries
+    }
+}
@@ -0,0 +1,87 @@
+use aya::maps::{HashMap, MapData, MapError};
+use common::{GeoKey, GeoRule};
+use tracing::{error, info};
+
+use crate::{
+    audit::{Audit, Origin},
+    geo::Geo,
+};
+
+pub struct GeoRules<'a> {
+    generation: u32,
+    inner: HashMap<&'a mut MapData, GeoKey, u32>,
+}
+
+impl<'a> GeoRules<'a> {
+    pub fn clear(&mut self) -> Result<(), MapError> {
+        for (country, _) in self.get() {
+            self.inner.remove(&self.key(country))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn copy_to(&mut self, generation: u32) -> Result<(), MapError> {
+        for (country, rule) in self.get() {
+            self.inner.insert(
+                GeoKey {
+                    generation,
+                    country,
+                },
+                rule as u32,
+                0,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self) -> Vec<(u32, GeoRule)> {
+        let mut rules = self
+            .inner
+            .iter()
+            .flatten()
+            .filter(|(key, _)| key.generation == self.generation)
+            .map(|(key, rule)| {
+                let rule = if rule == GeoRule::Block as u32 {
+                    GeoRule::Block
+                } else {
+                    GeoRule::Allow
+                };
+
+                (key.country, rule)
+            })
+            .collect::<Vec<_>>();
+
+        rules.sort_unstable_by_key(|&(country, _)| country);
+        rules
+    }
+
+    pub fn insert(&mut self, country: u32, rule: GeoRule, origin: Origin) -> Result<(), MapError> {
+        let name = Geo::name(country);
+        let result = self.inner.insert(self.key(country), rule as u32, 0);
+
+        match result {
+            Ok(()) => info!("{name} added to geo {}", rule.name()),
+            Err(ref e) => error!("{name} could not be added to geo {}: {e}", rule.name()),
+        }
+
+        Audit::record(origin, &format!("geo.{}", rule.name()), &[name], &result);
+
+        result
+    }
+
+    fn key(&self, country: u32) -> GeoKey {
+        GeoKey {
+            generation: self.generation,
+            country,
+        }
+    }
+
+    pub fn new(map: HashMap<&'a mut MapData, GeoKey, u32>, generation: u32) -> Self {
+        Self {
+            generation,
+            inner: map,
+        }
+    }
+}
@@ -3,62 +3,237 @@ use std::{
     net::Ipv4Addr,
 };
 
-use aya::maps::{HashMap, MapData};
+use aya::maps::{HashMap, MapData, MapError};
+use common::ListKey;
+use humantime::format_rfc3339_seconds;
 use tracing::{error, info, warn};
 
-use crate::{ipv4::Addr, policy::Ipv4ListPolicy};
+use crate::{
+    audit::{Audit, Origin},
+    ipv4::Addr,
+    maps::capacity,
+    metadata::{Annotation, Metadata, ktime_to_system_time},
+    policy::Ipv4ListPolicy,
+    state::{Record, State},
+};
 
 pub struct Ipv4List<'a> {
-    inner: HashMap<&'a mut MapData, u32, u32>,
+    generation: u32,
+    inner: HashMap<&'a mut MapData, ListKey, u64>,
     label: String,
 }
 
 impl<'a> Ipv4List<'a> {
-    pub fn add(&mut self, args: &[&str]) {
-        for &addr in Addr::parse(args).0.as_slice().iter() {
-            if let Err(e) = self.inner.insert(addr.to_bits(), 0, 0) {
-                error!("{addr} could not be added to {}: {e}", self.label);
-            } else {
-                info!("{addr} added to {}", self.label);
-            }
+    pub fn add(&mut self, args: &[&str], origin: Origin) {
+        let (args, annotation) = match Annotation::parse(args) {
+            Ok(parsed) => parsed,
+            Err(e) => return warn!("{e}"),
+        };
+
+        for &addr in Addr::parse(&args).0.as_slice().iter() {
+            self.insert(addr, annotation.clone(), origin).ok();
         }
     }
 
-    pub fn apply(&mut self, policy: Option<Ipv4ListPolicy>) {
+    pub fn apply(
+        &mut self,
+        policy: Option<Ipv4ListPolicy>,
+        origin: Origin,
+    ) -> Result<(), MapError> {
         if let Some(Ipv4ListPolicy { ipv4 }) = policy {
-            if let Some(string_vec) = ipv4 {
-                let arg_vec = string_vec.iter().map(String::as_str).collect::<Vec<_>>();
-                let args = arg_vec.as_slice();
-
-                self.add(args);
+            if let Some(entries) = ipv4 {
+                for entry in entries {
+                    for &addr in Addr::parse(&[entry.addr()]).0.as_slice().iter() {
+                        self.insert(addr, entry.annotation(), origin)?;
+                    }
+                }
             } else {
                 warn!("`ipv4` array not found in {} policy", self.label);
             }
         } else {
             warn!("`{}` table not found in policy", self.label);
         }
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<(), MapError> {
+        for addr in self.keys() {
+            self.inner.remove(&self.key(addr))?;
+            Metadata::remove(&self.label, self.generation, Ipv4Addr::from_bits(addr));
+        }
+
+        Ok(())
+    }
+
+    fn copy_metadata(&self, addr: Ipv4Addr, generation: u32) {
+        if let Some(metadata) = Metadata::get(&self.label, self.generation, addr) {
+            Metadata::insert(&self.label, generation, addr, metadata);
+        }
+    }
+
+    pub fn copy_to(&mut self, generation: u32) -> Result<(), MapError> {
+        for addr in self.keys() {
+            let value = self.inner.get(&self.key(addr), 0)?;
+
+            self.inner.insert(ListKey { generation, addr }, value, 0)?;
+            self.copy_metadata(Ipv4Addr::from_bits(addr), generation);
+        }
+
+        Ok(())
     }
 
-    pub fn del(&mut self, args: &[&str]) {
+    pub fn copy_runtime_to(&mut self, generation: u32) -> Result<(), MapError> {
+        for (addr, metadata) in self.entries() {
+            if metadata.is_some_and(|metadata| metadata.origin != Origin::Policy) {
+                let value = self.inner.get(&self.key(addr.to_bits()), 0)?;
+
+                self.inner.insert(
+                    ListKey {
+                        generation,
+                        addr: addr.to_bits(),
+                    },
+                    value,
+                    0,
+                )?;
+                self.copy_metadata(addr, generation);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn del(&mut self, args: &[&str], origin: Origin) {
         for &addr in Addr::parse(args).0.as_slice().iter() {
-            if let Err(e) = self.inner.remove(&addr.to_bits()) {
+            let result = self.inner.remove(&self.key(addr.to_bits()));
+
+            if let Err(ref e) = result {
                 error!("{addr} could not be removed from {}: {e}", self.label);
             } else {
+                Metadata::remove(&self.label, self.generation, addr);
                 info!("{addr} removed from {}", self.label);
+
+                if origin != Origin::Policy {
+                    State::record(Record::Del {
+                        list: self.label.clone(),
+                        addr,
+                    });
+                }
+            }
+
+            Audit::record(origin, &format!("{}.del", self.label), &[addr], &result);
+        }
+    }
+
+    pub fn describe(&self, addr: Ipv4Addr) -> Option<String> {
+        let last_hit = self.inner.get(&self.key(addr.to_bits()), 0).ok()?;
+        let metadata = Metadata::get(&self.label, self.generation, addr)
+            .map_or_else(|| "origin=unknown".to_string(), |m| m.to_string());
+        let last_hit = ktime_to_system_time(last_hit).map_or_else(
+            || "-".to_string(),
+            |t| format_rfc3339_seconds(t).to_string(),
+        );
+
+        Some(format!("{addr} {metadata} last_hit={last_hit}"))
+    }
+
+    pub fn entries(&self) -> Vec<(Ipv4Addr, Option<Metadata>)> {
+        self.keys()
+            .into_iter()
+            .map(Ipv4Addr::from_bits)
+            .map(|addr| (addr, Metadata::get(&self.label, self.generation, addr)))
+            .collect()
+    }
+
+    pub fn expire(&mut self) {
+        let expired = self
+            .entries()
+            .into_iter()
+            .filter(|(_, metadata)| metadata.as_ref().is_some_and(Metadata::expired))
+            .map(|(addr, _)| addr.to_string())
+            .collect::<Vec<_>>();
+
+        if !expired.is_empty() {
+            let expired = expired.iter().map(String::as_str).collect::<Vec<_>>();
+
+            self.del(&expired, Origin::Timeout);
+        }
+    }
+
+    fn insert(
+        &mut self,
+        addr: Ipv4Addr,
+        annotation: Annotation,
+        origin: Origin,
+    ) -> Result<(), MapError> {
+        let result = self.inner.insert(self.key(addr.to_bits()), 0, 0);
+
+        if let Err(ref e) = result {
+            error!("{addr} could not be added to {}: {e}", self.label);
+        } else {
+            let metadata = Metadata::new(annotation, origin);
+
+            Metadata::insert(&self.label, self.generation, addr, metadata.clone());
+            info!("{addr} added to {}", self.label);
+
+            if origin != Origin::Policy {
+                State::record(Record::Add {
+                    list: self.label.clone(),
+                    addr,
+                    metadata,
+                });
             }
         }
+
+        Audit::record(origin, &format!("{}.add", self.label), &[addr], &result);
+
+        result
+    }
+
+    fn key(&self, addr: u32) -> ListKey {
+        ListKey {
+            generation: self.generation,
+            addr,
+        }
     }
 
     fn keys(&self) -> Vec<u32> {
-        self.inner.keys().flatten().collect()
+        self.inner
+            .keys()
+            .flatten()
+            .filter(|key| key.generation == self.generation)
+            .map(|key| key.addr)
+            .collect()
     }
 
-    pub fn new<T: Into<String>>(label: T, map: HashMap<&'a mut MapData, u32, u32>) -> Self {
+    pub fn new<T: Into<String>>(
+        label: T,
+        map: HashMap<&'a mut MapData, ListKey, u64>,
+        generation: u32,
+    ) -> Self {
         Self {
+            generation,
             label: label.into(),
             inner: map,
         }
     }
+
+    pub fn occupancy(&self) -> Result<(usize, u32), MapError> {
+        Ok((self.inner.keys().count(), capacity(&self.inner)?))
+    }
+
+    pub fn restore(&mut self, addr: Ipv4Addr, metadata: Metadata) -> Result<(), MapError> {
+        let result = self.inner.insert(self.key(addr.to_bits()), 0, 0);
+
+        if let Err(ref e) = result {
+            error!("{addr} could not be restored to {}: {e}", self.label);
+        } else {
+            Metadata::insert(&self.label, self.generation, addr, metadata);
+            info!("{addr} restored to {}", self.label);
+        }
+
+        result
+    }
 }
 
 impl<'a> Display for Ipv4List<'a> {
@@ -66,7 +241,15 @@ impl<'a> Display for Ipv4List<'a> {
         let ipv4_list = self
             .keys()
             .iter()
-            .map(|&key| Ipv4Addr::from_bits(key).to_string())
+            .map(|&key| {
+                let addr = Ipv4Addr::from_bits(key);
+
+                if f.alternate() {
+                    self.describe(addr).unwrap_or_else(|| addr.to_string())
+                } else {
+                    addr.to_string()
+                }
+            })
             .collect::<Vec<_>>()
             .join("\n");
 
@@ -76,169 +259,232 @@ impl<'a> Display for Ipv4List<'a> {
 
 #[cfg(test)]
 mod tests {
-    use std::net::Ipv4Addr;
+    use std::{net::Ipv4Addr, time::SystemTime};
 
     use aya::Ebpf;
     use serial_test::serial;
     use toml::from_str;
 
-    use crate::{Policy, ebpf::Init};
+    use crate::{
+        Policy,
+        audit::Origin,
+        ebpf::Init,
+        metadata::{Annotation, Metadata},
+    };
 
     #[serial]
     #[tokio::test]
     async fn add_addr_to_blacklist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut blacklist = ebpf.blacklist().unwrap();
         let expected = vec![u32::from(Ipv4Addr::new(127, 0, 0, 1))];
 
-        blacklist.add(&["127.0.0.1"]);
+        blacklist.add(&["127.0.0.1"], Origin::Repl);
         assert_eq!(blacklist.keys(), expected);
     }
 
     #[serial]
     #[tokio::test]
     async fn add_addr_to_whitelist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut whitelist = ebpf.whitelist().unwrap();
         let expected = vec![u32::from(Ipv4Addr::new(127, 0, 0, 1))];
 
-        whitelist.add(&["127.0.0.1"]);
+        whitelist.add(&["127.0.0.1"], Origin::Repl);
         assert_eq!(whitelist.keys(), expected);
     }
 
     #[serial]
     #[tokio::test]
     async fn add_invalid_addr_to_blacklist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut blacklist = ebpf.blacklist().unwrap();
 
-        blacklist.add(&["invalid"]);
+        blacklist.add(&["invalid"], Origin::Repl);
         assert_eq!(blacklist.keys(), Vec::<u32>::new());
     }
 
     #[serial]
     #[tokio::test]
     async fn add_invalid_addr_to_whitelist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut whitelist = ebpf.whitelist().unwrap();
 
-        whitelist.add(&["invalid"]);
+        whitelist.add(&["invalid"], Origin::Repl);
         assert_eq!(whitelist.keys(), Vec::<u32>::new());
     }
 
     #[serial]
     #[tokio::test]
     async fn apply_policy_to_blacklist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut blacklist = ebpf.blacklist().unwrap();
         let expected = vec![u32::from(Ipv4Addr::new(127, 0, 0, 1))];
         let policy = "[blacklist]\nipv4 = [\"127.0.0.1\"]";
         let blacklist_policy = from_str::<Policy>(policy).unwrap().blacklist;
 
-        blacklist.apply(blacklist_policy);
+        blacklist.apply(blacklist_policy, Origin::Policy).unwrap();
         assert_eq!(blacklist.keys(), expected);
     }
 
     #[serial]
     #[tokio::test]
     async fn apply_policy_to_whitelist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut whitelist = ebpf.whitelist().unwrap();
         let expected = vec![u32::from(Ipv4Addr::new(127, 0, 0, 1))];
         let policy = "[whitelist]\nipv4 = [\"127.0.0.1\"]";
         let whitelist_policy = from_str::<Policy>(policy).unwrap().whitelist;
 
-        whitelist.apply(whitelist_policy);
+        whitelist.apply(whitelist_policy, Origin::Policy).unwrap();
         assert_eq!(whitelist.keys(), expected);
     }
 
     #[serial]
     #[tokio::test]
     async fn apply_empty_policy_to_blacklist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut blacklist = ebpf.blacklist().unwrap();
         let policy = "";
         let blacklist_policy = from_str::<Policy>(policy).unwrap().blacklist;
 
-        blacklist.apply(blacklist_policy);
+        blacklist.apply(blacklist_policy, Origin::Policy).unwrap();
         assert_eq!(blacklist.keys(), Vec::<u32>::new());
     }
 
     #[serial]
     #[tokio::test]
     async fn apply_empty_policy_to_whitelist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut whitelist = ebpf.whitelist().unwrap();
         let policy = "";
         let whitelist_policy = from_str::<Policy>(policy).unwrap().blacklist;
 
-        whitelist.apply(whitelist_policy);
+        whitelist.apply(whitelist_policy, Origin::Policy).unwrap();
         assert_eq!(whitelist.keys(), Vec::<u32>::new());
     }
 
     #[serial]
     #[tokio::test]
     async fn delete_addr_from_blacklist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut blacklist = ebpf.blacklist().unwrap();
 
-        blacklist.add(&["127.0.0.1"]);
-        blacklist.del(&["127.0.0.1"]);
+        blacklist.add(&["127.0.0.1"], Origin::Repl);
+        blacklist.del(&["127.0.0.1"], Origin::Repl);
         assert_eq!(blacklist.keys(), Vec::<u32>::new());
     }
 
     #[serial]
     #[tokio::test]
     async fn delete_addr_from_whitelist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut whitelist = ebpf.whitelist().unwrap();
 
-        whitelist.add(&["127.0.0.1"]);
-        whitelist.del(&["127.0.0.1"]);
+        whitelist.add(&["127.0.0.1"], Origin::Repl);
+        whitelist.del(&["127.0.0.1"], Origin::Repl);
         assert_eq!(whitelist.keys(), Vec::<u32>::new());
     }
 
     #[serial]
     #[tokio::test]
     async fn delete_invalid_addr_from_blacklist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut blacklist = ebpf.blacklist().unwrap();
         let expected = vec![u32::from(Ipv4Addr::new(127, 0, 0, 1))];
 
-        blacklist.add(&["127.0.0.1"]);
-        blacklist.del(&["invalid"]);
+        blacklist.add(&["127.0.0.1"], Origin::Repl);
+        blacklist.del(&["invalid"], Origin::Repl);
         assert_eq!(blacklist.keys(), expected);
     }
 
     #[serial]
     #[tokio::test]
     async fn delete_invalid_addr_from_whitelist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut whitelist = ebpf.whitelist().unwrap();
         let expected = vec![u32::from(Ipv4Addr::new(127, 0, 0, 1))];
 
-        whitelist.add(&["127.0.0.1"]);
-        whitelist.del(&["invalid"]);
+        whitelist.add(&["127.0.0.1"], Origin::Repl);
+        whitelist.del(&["invalid"], Origin::Repl);
         assert_eq!(whitelist.keys(), expected);
     }
 
+    #[serial]
+    #[tokio::test]
+    async fn add_annotated_addr_to_blacklist() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut blacklist = ebpf.blacklist().unwrap();
+
+        blacklist.add(&["127.0.0.1", "--reason", "scanner"], Origin::Repl);
+        assert_eq!(
+            Metadata::get("blacklist", 0, Ipv4Addr::new(127, 0, 0, 1))
+                .unwrap()
+                .annotation
+                .reason
+                .as_deref(),
+            Some("scanner")
+        );
+        assert!(format!("{blacklist:#}").starts_with("127.0.0.1 origin=repl"));
+        assert!(format!("{blacklist:#}").ends_with("reason=\"scanner\" last_hit=-"));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn apply_annotated_policy_to_blacklist() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut blacklist = ebpf.blacklist().unwrap();
+        let expected = vec![u32::from(Ipv4Addr::new(127, 0, 0, 1))];
+        let policy = "[blacklist]\nipv4 = [{ addr = \"127.0.0.1\", reason = \"abuse\" }]";
+        let blacklist_policy = from_str::<Policy>(policy).unwrap().blacklist;
+
+        blacklist.apply(blacklist_policy, Origin::Policy).unwrap();
+        assert_eq!(blacklist.keys(), expected);
+        assert_eq!(
+            Metadata::get("blacklist", 0, Ipv4Addr::new(127, 0, 0, 1))
+                .unwrap()
+                .origin,
+            Origin::Policy
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn expire_banned_addr_from_blacklist() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut blacklist = ebpf.blacklist().unwrap();
+        let mut metadata = Metadata::new(Annotation::default(), Origin::Dataplane);
+
+        metadata.expires_at = Some(SystemTime::now());
+        blacklist
+            .restore(Ipv4Addr::new(127, 0, 0, 1), metadata)
+            .unwrap();
+        blacklist.add(&["127.0.0.2"], Origin::Repl);
+        blacklist.expire();
+
+        assert_eq!(
+            blacklist.keys(),
+            vec![u32::from(Ipv4Addr::new(127, 0, 0, 2))]
+        );
+    }
+
     #[serial]
     #[tokio::test]
     async fn format_blacklist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut blacklist = ebpf.blacklist().unwrap();
 
-        blacklist.add(&["0.0.0.0", "1.1.1.1"]);
+        blacklist.add(&["0.0.0.0", "1.1.1.1"], Origin::Repl);
         assert!(["0.0.0.0\n1.1.1.1", "1.1.1.1\n0.0.0.0"].contains(&blacklist.to_string().as_str()));
     }
 
     #[serial]
     #[tokio::test]
     async fn format_whitelist() {
-        let mut ebpf = Ebpf::init().unwrap();
+        let mut ebpf = Ebpf::detached().unwrap();
         let mut whitelist = ebpf.whitelist().unwrap();
 
-        whitelist.add(&["0.0.0.0", "1.1.1.1"]);
+        whitelist.add(&["0.0.0.0", "1.1.1.1"], Origin::Repl);
         assert!(["0.0.0.0\n1.1.1.1", "1.1.1.1\n0.0.0.0"].contains(&whitelist.to_string().as_str()));
     }
 }
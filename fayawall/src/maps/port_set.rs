@@ -0,0 +1,92 @@
+use std::fmt::{self, Display, Formatter};
+
+use aya::maps::{HashMap, MapData, MapError};
+use common::PortKey;
+use tracing::{error, info};
+
+use crate::audit::{Audit, Origin};
+
+pub struct PortSet<'a> {
+    generation: u32,
+    inner: HashMap<&'a mut MapData, PortKey, u8>,
+    label: String,
+}
+
+impl<'a> PortSet<'a> {
+    pub fn clear(&mut self) -> Result<(), MapError> {
+        for port in self.get() {
+            self.inner.remove(&self.key(port))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn copy_to(&mut self, generation: u32) -> Result<(), MapError> {
+        for port in self.get() {
+            self.inner.insert(
+                PortKey {
+                    generation,
+                    port: port.into(),
+                },
+                1,
+                0,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self) -> Vec<u16> {
+        let mut ports = self
+            .inner
+            .keys()
+            .flatten()
+            .filter(|key| key.generation == self.generation)
+            .filter_map(|key| u16::try_from(key.port).ok())
+            .collect::<Vec<_>>();
+
+        ports.sort_unstable();
+        ports
+    }
+
+    pub fn insert(&mut self, port: u16, origin: Origin) -> Result<(), MapError> {
+        let result = self.inner.insert(self.key(port), 1, 0);
+
+        match result {
+            Ok(()) => info!("Port {port} added to {}", self.label),
+            Err(ref e) => error!("Port {port} could not be added to {}: {e}", self.label),
+        }
+
+        Audit::record(origin, &format!("{}.add", self.label), &[port], &result);
+
+        result
+    }
+
+    fn key(&self, port: u16) -> PortKey {
+        PortKey {
+            generation: self.generation,
+            port: port.into(),
+        }
+    }
+
+    pub fn new(label: &str, map: HashMap<&'a mut MapData, PortKey, u8>, generation: u32) -> Self {
+        Self {
+            generation,
+            inner: map,
+            label: label.to_string(),
+        }
+    }
+}
+
+impl<'a> Display for PortSet<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let ports = self
+            .get()
+            .iter()
+            .map(u16::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        write!(f, "{ports}")
+    }
+}
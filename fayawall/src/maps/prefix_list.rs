@@ -0,0 +1,162 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    net::Ipv4Addr,
+};
+
+use aya::maps::{LpmTrie, MapData, MapError, lpm_trie::Key};
+use ipnet::Ipv4Net;
+use tracing::{error, info};
+
+pub struct PrefixList<'a> {
+    inner: LpmTrie<&'a mut MapData, u32, u32>,
+    label: String,
+}
+
+impl<'a> PrefixList<'a> {
+    pub fn entries(&self) -> Vec<(Ipv4Net, u32)> {
+        self.inner
+            .iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((Self::net(&key)?, value)))
+            .collect()
+    }
+
+    pub fn get(&self, addr: Ipv4Addr) -> Option<u32> {
+        self.inner
+            .get(&Key::new(32, addr.to_bits().to_be()), 0)
+            .ok()
+    }
+
+    pub fn insert(&mut self, net: Ipv4Net, value: u32) -> Result<(), MapError> {
+        let result = self.inner.insert(&Self::key(net), value, 0);
+
+        if let Err(ref e) = result {
+            error!("{net} could not be added to {}: {e}", self.label);
+        } else {
+            info!("{net} added to {}", self.label);
+        }
+
+        result
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.keys().next().is_none()
+    }
+
+    fn key(net: Ipv4Net) -> Key<u32> {
+        Key::new(net.prefix_len().into(), net.network().to_bits().to_be())
+    }
+
+    fn net(key: &Key<u32>) -> Option<Ipv4Net> {
+        Ipv4Net::new(
+            Ipv4Addr::from_bits(u32::from_be(key.data())),
+            key.prefix_len() as u8,
+        )
+        .ok()
+    }
+
+    fn nets(&self) -> Vec<Ipv4Net> {
+        self.inner
+            .keys()
+            .flatten()
+            .filter_map(|key| Self::net(&key))
+            .collect()
+    }
+
+    pub fn new<T: Into<String>>(label: T, map: LpmTrie<&'a mut MapData, u32, u32>) -> Self {
+        Self {
+            inner: map,
+            label: label.into(),
+        }
+    }
+
+    pub fn remove(&mut self, net: Ipv4Net) -> Result<(), MapError> {
+        let result = self.inner.remove(&Self::key(net));
+
+        if let Err(ref e) = result {
+            error!("{net} could not be removed from {}: {e}", self.label);
+        } else {
+            info!("{net} removed from {}", self.label);
+        }
+
+        result
+    }
+
+    pub fn replace(&mut self, nets: &[(Ipv4Net, u32)]) -> Result<(), MapError> {
+        for &(net, value) in nets {
+            self.insert(net, value)?;
+        }
+
+        for net in self.nets() {
+            if !nets.iter().any(|&(keep, _)| keep == net) {
+                self.remove(net)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Display for PrefixList<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let nets = self
+            .nets()
+            .iter()
+            .map(Ipv4Net::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        write!(f, "{nets}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use aya::Ebpf;
+    use serial_test::serial;
+
+    use crate::ebpf::Init;
+
+    #[serial]
+    #[tokio::test]
+    async fn get_longest_prefix() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut management = ebpf.management().unwrap();
+
+        management.insert("10.0.0.0/8".parse().unwrap(), 8).unwrap();
+        management
+            .insert("10.1.0.0/16".parse().unwrap(), 16)
+            .unwrap();
+
+        assert_eq!(management.get(Ipv4Addr::new(10, 1, 2, 3)), Some(16));
+        assert_eq!(management.get(Ipv4Addr::new(10, 2, 2, 3)), Some(8));
+        assert_eq!(management.get(Ipv4Addr::new(11, 0, 0, 1)), None);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn replace_prefix_list() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut management = ebpf.management().unwrap();
+
+        management.insert("10.0.0.0/8".parse().unwrap(), 0).unwrap();
+        management
+            .replace(&[("192.168.0.0/16".parse().unwrap(), 0)])
+            .unwrap();
+
+        assert_eq!(management.to_string(), "192.168.0.0/16");
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn format_prefix_list() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut management = ebpf.management().unwrap();
+
+        management.insert("10.0.0.0/8".parse().unwrap(), 0).unwrap();
+
+        assert_eq!(management.to_string(), "10.0.0.0/8");
+    }
+}
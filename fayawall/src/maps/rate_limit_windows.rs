@@ -0,0 +1,12 @@
+use std::net::Ipv4Addr;
+
+use aya::maps::{HashMap, MapData};
+use common::RateLimitWindow;
+
+pub struct RateLimitWindows<'a>(pub HashMap<&'a mut MapData, u32, RateLimitWindow>);
+
+impl<'a> RateLimitWindows<'a> {
+    pub fn get(&self, addr: Ipv4Addr) -> Option<RateLimitWindow> {
+        self.0.get(&addr.to_bits(), 0).ok()
+    }
+}
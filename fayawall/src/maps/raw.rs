@@ -0,0 +1,136 @@
+use std::{
+    io, mem,
+    os::fd::{AsFd, AsRawFd},
+    ptr,
+};
+
+use aya::{
+    maps::{Map, MapData, MapError, MapType},
+    sys::SyscallError,
+    util::nr_cpus,
+};
+
+const BPF_MAP_LOOKUP_ELEM: libc::c_long = 1;
+const BPF_MAP_UPDATE_ELEM: libc::c_long = 2;
+const BPF_MAP_GET_NEXT_KEY: libc::c_long = 4;
+
+pub type Entry = (Vec<u8>, Vec<u8>);
+pub type Layout = (Option<MapType>, u32, u32, u32, u32);
+
+#[repr(C)]
+#[derive(Default)]
+struct ElemAttr {
+    map_fd: u32,
+    pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+pub struct RawMap<'a>(pub &'a MapData);
+
+impl<'a> RawMap<'a> {
+    fn call(&self, cmd: libc::c_long, key: *const u8, value: *mut u8) -> io::Result<()> {
+        let mut attr = ElemAttr {
+            map_fd: self.0.fd().as_fd().as_raw_fd() as u32,
+            key: key as u64,
+            value: value as u64,
+            ..Default::default()
+        };
+
+        if unsafe {
+            libc::syscall(
+                libc::SYS_bpf,
+                cmd,
+                &mut attr as *mut ElemAttr,
+                mem::size_of::<ElemAttr>(),
+            )
+        } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn copy_to(&self, to: &RawMap) -> Result<usize, MapError> {
+        let entries = self.entries()?;
+
+        for (key, value) in &entries {
+            to.insert(key, value)?;
+        }
+
+        Ok(entries.len())
+    }
+
+    pub fn entries(&self) -> Result<Vec<Entry>, MapError> {
+        let (map_type, key_size, value_size, ..) = self.layout()?;
+        let value_size = match map_type {
+            Some(MapType::PerCpuArray | MapType::PerCpuHash | MapType::LruPerCpuHash) => {
+                let cpus = nr_cpus().map_err(|(_, e)| syscall_error("nr_cpus", e))?;
+
+                value_size.next_multiple_of(8) as usize * cpus
+            }
+            _ => value_size as usize,
+        };
+        let mut entries = Vec::new();
+        let mut key: Option<Vec<u8>> = None;
+
+        loop {
+            let mut next = vec![0; key_size as usize];
+            let current = key.as_ref().map_or(ptr::null(), |key| key.as_ptr());
+
+            match self.call(BPF_MAP_GET_NEXT_KEY, current, next.as_mut_ptr()) {
+                Ok(()) => {}
+                Err(e) if e.raw_os_error() == Some(libc::ENOENT) => break,
+                Err(e) => return Err(syscall_error("bpf_map_get_next_key", e)),
+            }
+
+            let mut value = vec![0; value_size];
+
+            match self.call(BPF_MAP_LOOKUP_ELEM, next.as_ptr(), value.as_mut_ptr()) {
+                Ok(()) => entries.push((next.clone(), value)),
+                Err(e) if e.raw_os_error() == Some(libc::ENOENT) => {}
+                Err(e) => return Err(syscall_error("bpf_map_lookup_elem", e)),
+            }
+
+            key = Some(next);
+        }
+
+        Ok(entries)
+    }
+
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), MapError> {
+        self.call(BPF_MAP_UPDATE_ELEM, key.as_ptr(), value.as_ptr().cast_mut())
+            .map_err(|e| syscall_error("bpf_map_update_elem", e))
+    }
+
+    pub fn layout(&self) -> Result<Layout, MapError> {
+        let info = self.0.info()?;
+
+        Ok((
+            info.map_type().ok(),
+            info.key_size(),
+            info.value_size(),
+            info.max_entries(),
+            info.map_flags(),
+        ))
+    }
+
+    pub fn of(map: &'a Map) -> Option<Self> {
+        match map {
+            Map::Array(data)
+            | Map::HashMap(data)
+            | Map::LpmTrie(data)
+            | Map::LruHashMap(data)
+            | Map::PerCpuArray(data)
+            | Map::PerCpuHashMap(data)
+            | Map::PerCpuLruHashMap(data) => Some(Self(data)),
+            _ => None,
+        }
+    }
+}
+
+fn syscall_error(call: &'static str, io_error: io::Error) -> MapError {
+    MapError::SyscallError(SyscallError { call, io_error })
+}
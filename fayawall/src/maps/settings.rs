@@ -0,0 +1,164 @@
+use aya::maps::{HashMap, MapData, MapError};
+use common::{DefaultAction, Setting, SettingKey};
+use tracing::info;
+
+use crate::audit::{Audit, Origin};
+
+pub struct Settings<'a> {
+    generation: u32,
+    inner: HashMap<&'a mut MapData, SettingKey, u64>,
+}
+
+impl<'a> Settings<'a> {
+    pub fn clear(&mut self) -> Result<(), MapError> {
+        let keys = self
+            .inner
+            .keys()
+            .flatten()
+            .filter(|key| key.generation == self.generation)
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            self.inner.remove(&key)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn copy_to(&mut self, generation: u32) -> Result<(), MapError> {
+        let entries = self
+            .inner
+            .iter()
+            .flatten()
+            .filter(|(key, _)| key.generation == self.generation)
+            .collect::<Vec<_>>();
+
+        for (key, value) in entries {
+            self.inner.insert(
+                SettingKey {
+                    generation,
+                    setting: key.setting,
+                },
+                value,
+                0,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn default_action(&self) -> DefaultAction {
+        match self.get(Setting::DefaultAction) {
+            action if action == DefaultAction::Drop as u64 => DefaultAction::Drop,
+            _ => DefaultAction::Pass,
+        }
+    }
+
+    /// Returns `None` for a setting that was never set, unlike `get`.
+    pub fn find(&self, setting: Setting) -> Option<u64> {
+        self.inner.get(&self.key(setting), 0).ok()
+    }
+
+    pub fn get(&self, setting: Setting) -> u64 {
+        self.find(setting).unwrap_or(0)
+    }
+
+    fn key(&self, setting: Setting) -> SettingKey {
+        SettingKey {
+            generation: self.generation,
+            setting: setting as u32,
+        }
+    }
+
+    pub fn new(map: HashMap<&'a mut MapData, SettingKey, u64>, generation: u32) -> Self {
+        Self {
+            generation,
+            inner: map,
+        }
+    }
+
+    pub fn set(&mut self, setting: Setting, value: u64, origin: Origin) -> Result<(), MapError> {
+        let result = self.inner.insert(self.key(setting), value, 0);
+
+        if result.is_ok() {
+            info!("{} set to {value}", setting.name());
+        }
+
+        Audit::record(
+            origin,
+            &format!("{}.set", setting.name()),
+            &[value],
+            &result,
+        );
+
+        result
+    }
+
+    pub fn set_default_action(
+        &mut self,
+        action: DefaultAction,
+        origin: Origin,
+    ) -> Result<(), MapError> {
+        let result = self
+            .inner
+            .insert(self.key(Setting::DefaultAction), action as u64, 0);
+
+        if result.is_ok() {
+            info!("default_action set to {}", action.name());
+        }
+
+        Audit::record(origin, "default_action.set", &[action.name()], &result);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aya::Ebpf;
+    use common::{DefaultAction, Setting};
+    use serial_test::serial;
+
+    use crate::{audit::Origin, ebpf::Init, policy::Policy};
+
+    #[serial]
+    #[tokio::test]
+    async fn default_action_follows_generation() {
+        let mut ebpf = Ebpf::detached().unwrap();
+
+        assert_eq!(
+            ebpf.settings().unwrap().default_action(),
+            DefaultAction::Pass
+        );
+
+        ebpf.settings()
+            .unwrap()
+            .set_default_action(DefaultAction::Drop, Origin::Repl)
+            .unwrap();
+        Policy::copy(&mut ebpf, 0, 1).unwrap();
+        Policy::clear(&mut ebpf, 0).unwrap();
+
+        assert_eq!(
+            ebpf.settings().unwrap().default_action(),
+            DefaultAction::Pass
+        );
+        assert_eq!(
+            ebpf.settings_at(1).unwrap().default_action(),
+            DefaultAction::Drop
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn find_unset_setting() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut settings = ebpf.settings().unwrap();
+
+        assert_eq!(settings.find(Setting::PacketLimit), None);
+
+        settings.set(Setting::PacketLimit, 0, Origin::Repl).unwrap();
+
+        assert_eq!(settings.find(Setting::PacketLimit), Some(0));
+        assert_eq!(settings.find(Setting::WindowSize), None);
+    }
+}
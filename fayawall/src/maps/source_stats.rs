@@ -0,0 +1,130 @@
+use std::{cmp::Reverse, net::Ipv4Addr, str::FromStr};
+
+use aya::maps::{HashMap, MapData, MapError};
+use common::SourceStat;
+
+use crate::maps::capacity;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TopBy {
+    Bytes,
+    #[default]
+    Drops,
+    Packets,
+}
+
+impl FromStr for TopBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bytes" => Ok(Self::Bytes),
+            "drops" => Ok(Self::Drops),
+            "packets" => Ok(Self::Packets),
+            _ => Err(format!("expected drops, packets or bytes, found `{s}`")),
+        }
+    }
+}
+
+pub struct SourceStats<'a>(pub HashMap<&'a mut MapData, u32, SourceStat>);
+
+impl<'a> SourceStats<'a> {
+    pub fn get(&self, addr: Ipv4Addr) -> Option<SourceStat> {
+        self.0.get(&addr.to_bits(), 0).ok()
+    }
+
+    pub fn occupancy(&self) -> Result<(usize, u32), MapError> {
+        Ok((self.0.keys().count(), capacity(&self.0)?))
+    }
+
+    pub fn top(&self, n: usize, by: TopBy) -> Vec<(Ipv4Addr, SourceStat)> {
+        let mut stats = self
+            .0
+            .iter()
+            .flatten()
+            .map(|(key, stat)| (Ipv4Addr::from_bits(key), stat))
+            .collect::<Vec<_>>();
+
+        stats.sort_by_key(|(_, stat)| {
+            Reverse(match by {
+                TopBy::Bytes => stat.bytes,
+                TopBy::Drops => stat.drops,
+                TopBy::Packets => stat.packets,
+            })
+        });
+        stats.truncate(n);
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use aya::Ebpf;
+    use common::SourceStat;
+    use serial_test::serial;
+
+    use super::TopBy;
+    use crate::ebpf::Init;
+
+    fn stat(packets: u64, bytes: u64, drops: u64) -> SourceStat {
+        SourceStat {
+            packets,
+            bytes,
+            drops,
+        }
+    }
+
+    #[test]
+    fn parse_top_by() {
+        assert_eq!("bytes".parse(), Ok(TopBy::Bytes));
+        assert_eq!("drops".parse(), Ok(TopBy::Drops));
+        assert_eq!("packets".parse(), Ok(TopBy::Packets));
+        assert!("invalid".parse::<TopBy>().is_err());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn top_sources_by_drops() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut source_stats = ebpf.source_stats().unwrap();
+
+        source_stats.0.insert(1, stat(10, 100, 1), 0).unwrap();
+        source_stats.0.insert(2, stat(5, 50, 5), 0).unwrap();
+        source_stats.0.insert(3, stat(1, 10, 0), 0).unwrap();
+
+        let top = source_stats
+            .top(2, TopBy::Drops)
+            .iter()
+            .map(|(addr, _)| *addr)
+            .collect::<Vec<_>>();
+
+        assert_eq!(top, vec![Ipv4Addr::from_bits(2), Ipv4Addr::from_bits(1)]);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn top_sources_by_packets() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut source_stats = ebpf.source_stats().unwrap();
+
+        source_stats.0.insert(1, stat(10, 100, 1), 0).unwrap();
+        source_stats.0.insert(2, stat(5, 500, 5), 0).unwrap();
+
+        let top = source_stats.top(1, TopBy::Packets);
+
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].0, Ipv4Addr::from_bits(1));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn top_sources_of_empty_map() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let source_stats = ebpf.source_stats().unwrap();
+
+        assert!(source_stats.top(10, TopBy::Bytes).is_empty());
+    }
+}
@@ -0,0 +1,10 @@
+use aya::maps::{MapData, MapError, PerCpuArray};
+use common::TcpAnomaly;
+
+pub struct TcpAnomalyLogs<'a>(pub PerCpuArray<&'a mut MapData, u64>);
+
+impl<'a> TcpAnomalyLogs<'a> {
+    pub fn get(&self, anomaly: TcpAnomaly) -> Result<u64, MapError> {
+        Ok(self.0.get(&(anomaly as u32), 0)?.iter().sum())
+    }
+}
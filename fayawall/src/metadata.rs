@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    mem::MaybeUninit,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use humantime::format_rfc3339_seconds;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, Origin},
+    sink::Sink,
+};
+
+pub type Store = Arc<Mutex<HashMap<Key, Metadata>>>;
+type Key = (String, u32, Ipv4Addr);
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Annotation {
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl Annotation {
+    pub fn parse<'a>(args: &[&'a str]) -> Result<(Vec<&'a str>, Self), String> {
+        let mut annotation = Self::default();
+        let mut addrs = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--label" => annotation
+                    .labels
+                    .push(args.next().ok_or("`--label` requires a value")?.to_string()),
+                "--reason" => {
+                    annotation.reason = Some(
+                        args.next()
+                            .ok_or("`--reason` requires a value")?
+                            .to_string(),
+                    );
+                }
+                addr => addrs.push(addr),
+            }
+        }
+
+        Ok((addrs, annotation))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Metadata {
+    #[serde(flatten)]
+    pub annotation: Annotation,
+    pub origin: Origin,
+    pub user: Option<String>,
+    pub added_at: SystemTime,
+    #[serde(default)]
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn get(list: &str, generation: u32, addr: Ipv4Addr) -> Option<Self> {
+        Sink::current()
+            .metadata
+            .lock()
+            .unwrap()
+            .get(&(list.to_string(), generation, addr))
+            .cloned()
+    }
+
+    pub fn insert(list: &str, generation: u32, addr: Ipv4Addr, metadata: Self) {
+        Sink::current()
+            .metadata
+            .lock()
+            .unwrap()
+            .insert((list.to_string(), generation, addr), metadata);
+    }
+
+    pub fn new(annotation: Annotation, origin: Origin) -> Self {
+        Self {
+            annotation,
+            origin,
+            user: audit::user(),
+            added_at: SystemTime::now(),
+            expires_at: None,
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= SystemTime::now())
+    }
+
+    pub fn remove(list: &str, generation: u32, addr: Ipv4Addr) {
+        Sink::current()
+            .metadata
+            .lock()
+            .unwrap()
+            .remove(&(list.to_string(), generation, addr));
+    }
+}
+
+impl Display for Metadata {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "origin={} user={} added_at={}",
+            self.origin,
+            self.user.as_deref().unwrap_or("-"),
+            format_rfc3339_seconds(self.added_at)
+        )?;
+
+        if let Some(expires_at) = self.expires_at {
+            write!(f, " expires_at={}", format_rfc3339_seconds(expires_at))?;
+        }
+
+        if let Some(ref reason) = self.annotation.reason {
+            write!(f, " reason={reason:?}")?;
+        }
+
+        if !self.annotation.labels.is_empty() {
+            write!(f, " labels={}", self.annotation.labels.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn ktime_now() -> Option<Duration> {
+    let mut now = MaybeUninit::<libc::timespec>::uninit();
+
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, now.as_mut_ptr()) } != 0 {
+        return None;
+    }
+
+    let now = unsafe { now.assume_init() };
+
+    Some(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
+}
+
+pub fn ktime_to_system_time(ktime: u64) -> Option<SystemTime> {
+    if ktime == 0 {
+        return None;
+    }
+
+    SystemTime::now().checked_sub(ktime_now()?.saturating_sub(Duration::from_nanos(ktime)))
+}
+
+pub fn system_time_to_ktime(time: SystemTime) -> Option<u64> {
+    let now = ktime_now()?;
+    let ktime = match time.duration_since(SystemTime::now()) {
+        Ok(ahead) => now.checked_add(ahead)?,
+        Err(e) => now.saturating_sub(e.duration()),
+    };
+
+    u64::try_from(ktime.as_nanos()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_annotation() {
+        let (addrs, annotation) = Annotation::parse(&[
+            "1.1.1.1", "--reason", "scanner", "2.2.2.2", "--label", "a", "--label", "b",
+        ])
+        .unwrap();
+        let expected = Annotation {
+            reason: Some("scanner".to_string()),
+            labels: vec!["a".to_string(), "b".to_string()],
+        };
+
+        assert_eq!(addrs, vec!["1.1.1.1", "2.2.2.2"]);
+        assert_eq!(annotation, expected);
+    }
+
+    #[test]
+    fn parse_invalid_annotation() {
+        assert!(Annotation::parse(&["1.1.1.1", "--reason"]).is_err());
+        assert!(Annotation::parse(&["--label"]).is_err());
+    }
+
+    #[test]
+    fn insert_and_remove_metadata() {
+        let addr = Ipv4Addr::new(192, 0, 2, 1);
+        let metadata = Metadata::new(Annotation::default(), Origin::Repl);
+
+        Metadata::insert("test", 0, addr, metadata.clone());
+        assert_eq!(Metadata::get("test", 0, addr), Some(metadata));
+        assert_eq!(Metadata::get("test", 1, addr), None);
+
+        Metadata::remove("test", 0, addr);
+        assert_eq!(Metadata::get("test", 0, addr), None);
+    }
+
+    #[test]
+    fn convert_ktime() {
+        assert_eq!(ktime_to_system_time(0), None);
+        assert!(ktime_to_system_time(1).unwrap() < SystemTime::now());
+
+        let ahead = system_time_to_ktime(SystemTime::now() + Duration::from_secs(60)).unwrap();
+
+        assert!(ahead > ktime_now().unwrap().as_nanos() as u64);
+        assert_eq!(system_time_to_ktime(SystemTime::UNIX_EPOCH), Some(0));
+    }
+}
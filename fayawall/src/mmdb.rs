@@ -0,0 +1,322 @@
+use std::{collections::HashMap, net::Ipv4Addr};
+
+use anyhow::{anyhow, bail};
+use ipnet::Ipv4Net;
+
+// Maps and arrays in real databases nest a handful of levels deep.
+const MAX_DEPTH: usize = 32;
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+pub enum Value {
+    Map(Vec<(String, Value)>),
+    String(String),
+    Uint(u64),
+    Other,
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn uint(&self) -> Option<u64> {
+        match self {
+            Self::Uint(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+pub struct Mmdb<'a> {
+    data: &'a [u8],
+    ip_version: Option<u64>,
+    node_count: u64,
+    record_size: u64,
+    tree_size: usize,
+}
+
+impl<'a> Mmdb<'a> {
+    fn decode(
+        data: &[u8],
+        base: usize,
+        offset: usize,
+        depth: usize,
+    ) -> anyhow::Result<(Value, usize)> {
+        if depth > MAX_DEPTH {
+            bail!("MMDB data nested deeper than {MAX_DEPTH} levels");
+        }
+
+        let byte = |at: usize| data.get(at).copied().ok_or(anyhow!("MMDB data truncated"));
+        let uint = |at: usize, len: usize| -> anyhow::Result<u64> {
+            (at..at + len).try_fold(0, |n, at| Ok((n << 8) | u64::from(byte(at)?)))
+        };
+        let control = byte(offset)?;
+        let mut offset = offset + 1;
+        let mut kind = control >> 5;
+
+        if kind == 1 {
+            let size = (control >> 3) & 0x3;
+            let high = u64::from(control & 0x7);
+            let pointer = match size {
+                0 => (high << 8) | uint(offset, 1)?,
+                1 => ((high << 16) | uint(offset, 2)?) + 2048,
+                2 => ((high << 24) | uint(offset, 3)?) + 526_336,
+                _ => uint(offset, 4)?,
+            };
+            let target = base + pointer as usize;
+
+            // The format never chains pointers, so one would only be a loop.
+            if byte(target)? >> 5 == 1 {
+                bail!("MMDB pointer at {} points to another pointer", offset - 1);
+            }
+
+            let (value, _) = Self::decode(data, base, target, depth + 1)?;
+
+            return Ok((value, offset + usize::from(size) + 1));
+        }
+
+        if kind == 0 {
+            kind = byte(offset)? + 7;
+            offset += 1;
+        }
+
+        let size = match control & 0x1f {
+            29 => 29 + uint(offset, 1)? as usize,
+            30 => 285 + uint(offset, 2)? as usize,
+            31 => 65_821 + uint(offset, 3)? as usize,
+            size => size.into(),
+        };
+
+        offset += match control & 0x1f {
+            29 => 1,
+            30 => 2,
+            31 => 3,
+            _ => 0,
+        };
+
+        match kind {
+            2 => {
+                let bytes = data
+                    .get(offset..offset + size)
+                    .ok_or(anyhow!("MMDB string truncated"))?;
+
+                Ok((
+                    Value::String(String::from_utf8_lossy(bytes).into_owned()),
+                    offset + size,
+                ))
+            }
+            5 | 6 | 9 => Ok((Value::Uint(uint(offset, size.min(8))?), offset + size)),
+            7 => {
+                // Every entry takes at least two bytes, whatever size the file claims.
+                let mut entries =
+                    Vec::with_capacity(size.min(data.len().saturating_sub(offset) / 2));
+
+                for _ in 0..size {
+                    let (key, next) = Self::decode(data, base, offset, depth + 1)?;
+                    let (value, next) = Self::decode(data, base, next, depth + 1)?;
+                    let Value::String(key) = key else {
+                        bail!("MMDB map key is not a string");
+                    };
+
+                    entries.push((key, value));
+                    offset = next;
+                }
+
+                Ok((Value::Map(entries), offset))
+            }
+            11 => {
+                for _ in 0..size {
+                    offset = Self::decode(data, base, offset, depth + 1)?.1;
+                }
+
+                Ok((Value::Other, offset))
+            }
+            14 => Ok((Value::Other, offset)),
+            _ => Ok((Value::Other, offset + size)),
+        }
+    }
+
+    fn leaf<F: Fn(&Value) -> Option<u32>>(
+        &self,
+        record: u64,
+        cache: &mut HashMap<u64, Option<u32>>,
+        field: &F,
+    ) -> Option<u32> {
+        *cache.entry(record).or_insert_with(|| {
+            let offset = self.tree_size + (record - self.node_count) as usize;
+            let (value, _) = Self::decode(self.data, self.tree_size + 16, offset, 0).ok()?;
+
+            field(&value)
+        })
+    }
+
+    pub fn new(data: &'a [u8]) -> anyhow::Result<Self> {
+        let marker = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or(anyhow!("MMDB metadata not found"))?;
+        let start = marker + METADATA_MARKER.len();
+        let (metadata, _) = Self::decode(data, start, start, 0)?;
+        let field = |key| {
+            metadata
+                .get(key)
+                .and_then(Value::uint)
+                .ok_or(anyhow!("MMDB metadata `{key}` not found"))
+        };
+        let node_count = field("node_count")?;
+        let record_size = field("record_size")?;
+
+        if ![24, 28, 32].contains(&record_size) {
+            bail!("MMDB record size {record_size} not supported");
+        }
+
+        Ok(Self {
+            data,
+            ip_version: metadata.get("ip_version").and_then(Value::uint),
+            node_count,
+            record_size,
+            tree_size: (node_count * record_size / 4) as usize,
+        })
+    }
+
+    pub fn nets<F: Fn(&Value) -> Option<u32>>(
+        &self,
+        field: F,
+    ) -> anyhow::Result<Vec<(Ipv4Net, u32)>> {
+        let mut root = 0;
+
+        if self.ip_version == Some(6) {
+            for _ in 0..96 {
+                if root >= self.node_count {
+                    return Ok(Vec::new());
+                }
+                root = self.record(root, 0)?;
+            }
+        }
+
+        let mut cache = HashMap::new();
+        let mut nets = Vec::new();
+        let mut stack = vec![(root, 0u32, 0u8)];
+
+        while let Some((node, bits, depth)) = stack.pop() {
+            if node > self.node_count {
+                if let Some(value) = self.leaf(node, &mut cache, &field) {
+                    nets.push((Ipv4Net::new(Ipv4Addr::from_bits(bits), depth)?, value));
+                }
+            } else if node < self.node_count && depth < 32 {
+                stack.push((self.record(node, 1)?, bits | (1 << (31 - depth)), depth + 1));
+                stack.push((self.record(node, 0)?, bits, depth + 1));
+            }
+        }
+
+        Ok(nets)
+    }
+
+    fn record(&self, node: u64, side: u64) -> anyhow::Result<u64> {
+        let size = (self.record_size / 4) as usize;
+        let start = node as usize * size;
+        let bytes = self
+            .data
+            .get(start..start + size)
+            .ok_or(anyhow!("MMDB node {node} out of bounds"))?;
+        let be = |bytes: &[u8]| bytes.iter().fold(0, |n, &b| (n << 8) | u64::from(b));
+
+        Ok(match (self.record_size, side) {
+            (24, 0) => be(&bytes[0..3]),
+            (24, _) => be(&bytes[3..6]),
+            (28, 0) => (u64::from(bytes[3] & 0xf0) << 20) | be(&bytes[0..3]),
+            (28, _) => (u64::from(bytes[3] & 0x0f) << 24) | be(&bytes[4..7]),
+            (_, 0) => be(&bytes[0..4]),
+            (_, _) => be(&bytes[4..8]),
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn database(leaf: &[u8]) -> Vec<u8> {
+        let node_count = 8u32;
+        let mut data = Vec::new();
+
+        for depth in 0..8 {
+            let bit = (1 >> (7 - depth)) & 1;
+            let next = if depth == 7 {
+                node_count + 16
+            } else {
+                depth + 1
+            };
+            let (left, right) = if bit == 0 {
+                (next, node_count)
+            } else {
+                (node_count, next)
+            };
+
+            data.extend_from_slice(&left.to_be_bytes()[1..]);
+            data.extend_from_slice(&right.to_be_bytes()[1..]);
+        }
+
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(leaf);
+        data.extend_from_slice(METADATA_MARKER);
+        data.push(0xe3);
+        data.extend(string("node_count"));
+        data.extend_from_slice(&[0xc1, node_count as u8]);
+        data.extend(string("record_size"));
+        data.extend_from_slice(&[0xa1, 24]);
+        data.extend(string("ip_version"));
+        data.extend_from_slice(&[0xa1, 4]);
+        data
+    }
+
+    pub fn string(s: &str) -> Vec<u8> {
+        let mut bytes = vec![0x40 | s.len() as u8];
+
+        bytes.extend_from_slice(s.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn walk_ipv4_tree() {
+        let mut leaf = vec![0xe1];
+
+        leaf.extend(string("id"));
+        leaf.extend_from_slice(&[0xc2, 0x01, 0x00]);
+
+        let data = database(&leaf);
+        let nets = Mmdb::new(&data)
+            .unwrap()
+            .nets(|value| value.get("id")?.uint()?.try_into().ok())
+            .unwrap();
+
+        assert_eq!(nets, [("1.0.0.0/8".parse().unwrap(), 256)]);
+        assert!(Mmdb::new(&data[..data.len() - 40]).is_err());
+    }
+
+    #[test]
+    fn reject_pointer_loop() {
+        let pointer = [0x20, 0x00];
+        let nested = [0xe1, 0x40, 0x20, 0x00];
+
+        assert!(Mmdb::decode(&pointer, 0, 0, 0).is_err());
+        assert!(Mmdb::decode(&nested, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn reject_oversized_map() {
+        let map = [0xff, 0xff, 0xff, 0xff];
+
+        assert!(Mmdb::decode(&map, 0, 0, 0).is_err());
+    }
+}
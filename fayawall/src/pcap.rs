@@ -0,0 +1,363 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b3c4d;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_SECTION: u32 = 0x0a0d0d0a;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAP_MICROS: u32 = 0xa1b2c3d4;
+const PCAP_NANOS: u32 = 0xa1b23c4d;
+const SNAPLEN: u32 = 65535;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub data: Vec<u8>,
+    pub orig_len: u32,
+    pub timestamp: Duration,
+}
+
+struct Cursor<'a> {
+    big_endian: bool,
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| invalid("truncated capture"))?;
+
+        self.offset += len;
+
+        Ok(bytes)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.bytes(2)?.try_into().unwrap();
+
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?.try_into().unwrap();
+
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+pub struct Writer(BufWriter<File>);
+
+impl Writer {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        file.write_all(&PCAP_NANOS.to_le_bytes())?;
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&4u16.to_le_bytes())?;
+        file.write_all(&[0; 8])?;
+        file.write_all(&SNAPLEN.to_le_bytes())?;
+        file.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+
+        Ok(Self(file))
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+
+    pub fn write(&mut self, packet: &Packet) -> io::Result<()> {
+        self.0
+            .write_all(&(packet.timestamp.as_secs() as u32).to_le_bytes())?;
+        self.0
+            .write_all(&packet.timestamp.subsec_nanos().to_le_bytes())?;
+        self.0
+            .write_all(&(packet.data.len() as u32).to_le_bytes())?;
+        self.0.write_all(&packet.orig_len.to_le_bytes())?;
+        self.0.write_all(&packet.data)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn parse(bytes: &[u8]) -> io::Result<Vec<Packet>> {
+    let magic = bytes.get(..4).ok_or_else(|| invalid("truncated capture"))?;
+
+    match u32::from_le_bytes(magic.try_into().unwrap()) {
+        PCAPNG_SECTION => parse_pcapng(bytes),
+        _ => parse_pcap(bytes),
+    }
+}
+
+fn parse_pcap(bytes: &[u8]) -> io::Result<Vec<Packet>> {
+    let magic = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    let (big_endian, nanos) = match (magic, magic.swap_bytes()) {
+        (PCAP_MICROS, _) => (false, false),
+        (PCAP_NANOS, _) => (false, true),
+        (_, PCAP_MICROS) => (true, false),
+        (_, PCAP_NANOS) => (true, true),
+        _ => return Err(invalid("not a pcap or pcapng capture")),
+    };
+    let mut cursor = Cursor {
+        big_endian,
+        bytes,
+        offset: 20,
+    };
+
+    if cursor.u32()? != LINKTYPE_ETHERNET {
+        return Err(invalid("only Ethernet captures are supported"));
+    }
+
+    let mut packets = Vec::new();
+
+    while !cursor.is_empty() {
+        let secs = cursor.u32()?;
+        let frac = cursor.u32()?;
+        let incl_len = cursor.u32()?;
+        let orig_len = cursor.u32()?;
+        let frac = if nanos {
+            Duration::from_nanos(frac.into())
+        } else {
+            Duration::from_micros(frac.into())
+        };
+
+        packets.push(Packet {
+            data: cursor.bytes(incl_len as usize)?.to_vec(),
+            orig_len,
+            timestamp: Duration::from_secs(secs.into()) + frac,
+        });
+    }
+
+    Ok(packets)
+}
+
+fn parse_pcapng(bytes: &[u8]) -> io::Result<Vec<Packet>> {
+    let mut packets = Vec::new();
+    let mut interfaces = Vec::<(u16, u64)>::new();
+    let mut cursor = Cursor {
+        big_endian: false,
+        bytes,
+        offset: 0,
+    };
+
+    while !cursor.is_empty() {
+        let start = cursor.offset;
+        let block_type = u32::from_le_bytes(cursor.bytes(4)?.try_into().unwrap());
+
+        if block_type == PCAPNG_SECTION {
+            let order = cursor.bytes(8)?[4..].try_into().unwrap();
+
+            cursor.big_endian = u32::from_be_bytes(order) == PCAPNG_BYTE_ORDER;
+            cursor.offset = start + 4;
+            interfaces.clear();
+        }
+
+        let block_type = if cursor.big_endian {
+            block_type.swap_bytes()
+        } else {
+            block_type
+        };
+        let block_len = cursor.u32()? as usize;
+
+        if block_len < 12 {
+            return Err(invalid("invalid pcapng block length"));
+        }
+
+        let mut body = Cursor {
+            big_endian: cursor.big_endian,
+            bytes: cursor.bytes(block_len - 12)?,
+            offset: 0,
+        };
+
+        cursor.u32()?;
+
+        match block_type {
+            PCAPNG_INTERFACE => {
+                let link_type = body.u16()?;
+
+                body.bytes(6)?;
+                interfaces.push((link_type, tsresol(&mut body)?));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let interface = body.u32()? as usize;
+                let (link_type, units) = *interfaces
+                    .get(interface)
+                    .ok_or_else(|| invalid("packet references unknown interface"))?;
+                let ts = (u64::from(body.u32()?) << 32) | u64::from(body.u32()?);
+                let captured = body.u32()?;
+                let orig_len = body.u32()?;
+
+                if u32::from(link_type) == LINKTYPE_ETHERNET {
+                    packets.push(Packet {
+                        data: body.bytes(captured as usize)?.to_vec(),
+                        orig_len,
+                        timestamp: Duration::from_secs(ts / units)
+                            + Duration::from_nanos(
+                                (u128::from(ts % units) * 1_000_000_000 / u128::from(units)) as u64,
+                            ),
+                    });
+                }
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                let orig_len = body.u32()?;
+                let captured = orig_len.min((block_len - 16) as u32);
+
+                if interfaces
+                    .first()
+                    .is_some_and(|&(link_type, _)| u32::from(link_type) == LINKTYPE_ETHERNET)
+                {
+                    packets.push(Packet {
+                        data: body.bytes(captured as usize)?.to_vec(),
+                        orig_len,
+                        timestamp: Duration::ZERO,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(packets)
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<Packet>> {
+    parse(&fs::read(path)?)
+}
+
+fn tsresol(options: &mut Cursor) -> io::Result<u64> {
+    while !options.is_empty() {
+        let code = options.u16()?;
+        let len = options.u16()? as usize;
+        let value = options.bytes(len.next_multiple_of(4))?;
+
+        match code {
+            0 => break,
+            9 if len == 1 && value[0] & 0x80 == 0 => {
+                return 10u64
+                    .checked_pow(value[0].into())
+                    .ok_or_else(|| invalid("invalid pcapng timestamp resolution"));
+            }
+            9 if len == 1 => {
+                return 1u64
+                    .checked_shl((value[0] & 0x7f).into())
+                    .ok_or_else(|| invalid("invalid pcapng timestamp resolution"));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_file};
+
+    use super::*;
+
+    fn packet(secs: u64, data: &[u8]) -> Packet {
+        Packet {
+            data: data.to_vec(),
+            orig_len: data.len() as u32,
+            timestamp: Duration::new(secs, 500),
+        }
+    }
+
+    #[test]
+    fn write_and_read_pcap() {
+        let path = temp_dir().join("fayawall-pcap-test.pcap");
+        let packets = vec![packet(1, &[0xaa; 60]), packet(2, &[0xbb; 14])];
+        let mut writer = Writer::create(&path).unwrap();
+
+        packets
+            .iter()
+            .for_each(|packet| writer.write(packet).unwrap());
+        writer.finish().unwrap();
+
+        let read_back = read(&path).unwrap();
+
+        remove_file(&path).ok();
+        assert_eq!(read_back, packets);
+    }
+
+    #[test]
+    fn parse_pcapng() {
+        let mut bytes = Vec::new();
+        let block = |bytes: &mut Vec<u8>, block_type: u32, body: &[u8]| {
+            let len = (body.len() + 12) as u32;
+
+            bytes.extend_from_slice(&block_type.to_le_bytes());
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(body);
+            bytes.extend_from_slice(&len.to_le_bytes());
+        };
+        let mut section = PCAPNG_BYTE_ORDER.to_le_bytes().to_vec();
+        let mut interface = vec![1, 0, 0, 0, 0xff, 0xff, 0, 0];
+        let mut enhanced = vec![0; 4];
+
+        section.extend_from_slice(&[1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        interface.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0]);
+        enhanced.extend_from_slice(&0u32.to_le_bytes());
+        enhanced.extend_from_slice(&2_000_000_001u32.to_le_bytes());
+        enhanced.extend_from_slice(&4u32.to_le_bytes());
+        enhanced.extend_from_slice(&64u32.to_le_bytes());
+        enhanced.extend_from_slice(&[1, 2, 3, 4]);
+
+        block(&mut bytes, PCAPNG_SECTION, &section);
+        block(&mut bytes, PCAPNG_INTERFACE, &interface);
+        block(&mut bytes, PCAPNG_ENHANCED_PACKET, &enhanced);
+
+        assert_eq!(
+            parse(&bytes).unwrap(),
+            vec![Packet {
+                data: vec![1, 2, 3, 4],
+                orig_len: 64,
+                timestamp: Duration::new(2, 1),
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_out_of_range_timestamps() {
+        let mut bytes = PCAP_MICROS.to_le_bytes().to_vec();
+
+        bytes.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&SNAPLEN.to_le_bytes());
+        bytes.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+
+        assert_eq!(
+            parse(&bytes).unwrap()[0].timestamp,
+            Duration::from_secs(1) + Duration::from_micros(u32::MAX.into())
+        );
+    }
+
+    #[test]
+    fn parse_invalid_capture() {
+        assert!(parse(&[0; 3]).is_err());
+        assert!(parse(&[0; 24]).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs::{create_dir_all, remove_dir_all, remove_file},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use aya::{
+    Ebpf, EbpfLoader,
+    maps::{MapData, MapType},
+    programs::links::PinnedLink,
+};
+use aya_obj::{Object, generated::bpf_map_type};
+use tracing::{info, warn};
+
+use crate::maps::raw::{Layout, RawMap};
+
+const LINK: &str = "link";
+const ROOT: &str = "/sys/fs/bpf/fayawall";
+
+static SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Pin {
+    dir: PathBuf,
+}
+
+impl Pin {
+    pub fn cleanup(iface: &str) -> io::Result<()> {
+        let pin = Self::new(iface);
+
+        if let Ok(link) = PinnedLink::from_pin(pin.link()) {
+            link.unpin()?;
+        }
+
+        match remove_dir_all(&pin.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => info!("Pins removed from `{}`", pin.dir.display()),
+        }
+
+        Ok(())
+    }
+
+    fn layout(map: &aya_obj::Map) -> Layout {
+        (
+            bpf_map_type::try_from(map.map_type())
+                .ok()
+                .and_then(|map_type| MapType::try_from(map_type).ok()),
+            map.key_size(),
+            map.value_size(),
+            map.max_entries(),
+            map.map_flags(),
+        )
+    }
+
+    pub fn link(&self) -> PathBuf {
+        self.dir.join(LINK)
+    }
+
+    /// Maps are pinned by name, so maps already pinned here are reused as-is
+    /// and the datapath keeps its state across restarts.
+    pub fn load(&self, object: &[u8]) -> anyhow::Result<Ebpf> {
+        create_dir_all(&self.dir)?;
+
+        Ok(EbpfLoader::new().map_pin_path(&self.dir).load(object)?)
+    }
+
+    pub fn maps(&self, ebpf: &Ebpf) -> anyhow::Result<()> {
+        create_dir_all(&self.dir)?;
+
+        for (name, map) in ebpf.maps() {
+            let path = self.dir.join(name);
+
+            if let Err(e) = remove_file(&path)
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                return Err(e.into());
+            }
+
+            map.pin(&path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn new(iface: &str) -> Self {
+        Self {
+            dir: Path::new(ROOT).join(iface),
+        }
+    }
+
+    /// Unpins maps whose layout no longer matches `object`, so they start empty
+    /// instead of being reused by a datapath that reads them differently.
+    pub fn prune(&self, object: &[u8]) -> anyhow::Result<()> {
+        for (name, map) in &Object::parse(object)?.maps {
+            let path = self.dir.join(name);
+            let Ok(pinned) = MapData::from_pin(&path) else {
+                continue;
+            };
+
+            if RawMap(&pinned).layout()? != Self::layout(map) {
+                remove_file(&path)?;
+                warn!("{name} layout changed and starts empty");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&self) -> io::Result<()> {
+        remove_dir_all(&self.dir)
+    }
+
+    /// A private pin directory for maps that are not kept once loaded.
+    pub fn scratch() -> Self {
+        Self {
+            dir: Path::new(ROOT).join(format!(
+                ".load-{}-{}",
+                process::id(),
+                SCRATCH.fetch_add(1, Ordering::Relaxed)
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{audit::Origin, ebpf::Init};
+
+    #[serial]
+    #[tokio::test]
+    async fn reuse_pinned_maps() {
+        let object = aya::include_bytes_aligned!(concat!(env!("OUT_DIR"), "/fayawall"));
+        let pins = Pin::new("fayawall-pin-test");
+
+        pins.load(object)
+            .unwrap()
+            .blacklist()
+            .unwrap()
+            .add(&["192.0.2.1"], Origin::Repl);
+        pins.prune(object).unwrap();
+
+        let blacklist = pins.load(object).unwrap().blacklist().unwrap().to_string();
+
+        pins.remove().unwrap();
+        assert_eq!(blacklist, "192.0.2.1");
+    }
+
+    #[test]
+    fn pin_dir_per_iface() {
+        assert_eq!(Pin::new("eth0").dir, Path::new("/sys/fs/bpf/fayawall/eth0"));
+        assert_eq!(
+            Pin::new("eth0").link(),
+            Path::new("/sys/fs/bpf/fayawall/eth0/link")
+        );
+    }
+}
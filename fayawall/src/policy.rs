@@ -1,16 +1,133 @@
-use std::fs::read_to_string;
+use std::{fs::read_to_string, time::Duration};
 
+use anyhow::bail;
 use aya::Ebpf;
 use clap::Parser;
+use common::{DefaultAction, FragmentMode, GeoRule, Sanity, ScanTrack, Setting, TcpAnomaly};
+use humantime::parse_duration;
 use serde::Deserialize;
 use toml::from_str;
 use tracing::{error, info, warn};
 
-use crate::{arg::Arg, ebpf::Init};
+use crate::{
+    arg::Arg,
+    asn::Asn,
+    audit::{Audit, Origin},
+    bogon::Bogon,
+    ebpf::Init,
+    geo::Geo,
+    lockout::Lockout,
+    metadata::Annotation,
+};
+
+const DEFAULT_SCAN_THRESHOLD: u64 = 20;
+const DEFAULT_SCAN_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_TRAP_BAN: Duration = Duration::from_secs(3600);
+const SCAN_BITMAP_BITS: u64 = 256;
+const SYNCOOKIES_ALWAYS: u8 = 2;
+const TCP_SYNCOOKIES: &str = "/proc/sys/net/ipv4/tcp_syncookies";
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnomalyActionPolicy {
+    Drop,
+    Log,
+    Pass,
+}
+
+#[derive(Deserialize)]
+pub struct AsnPolicy {
+    pub block: Option<Vec<u32>>,
+    pub database: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BogonPolicy {
+    pub enabled: Option<bool>,
+    pub list: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ConntrackPolicy {
+    pub allow_established: Option<bool>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultActionPolicy {
+    Drop,
+    Pass,
+}
+
+impl From<DefaultActionPolicy> for DefaultAction {
+    fn from(policy: DefaultActionPolicy) -> Self {
+        match policy {
+            DefaultActionPolicy::Drop => Self::Drop,
+            DefaultActionPolicy::Pass => Self::Pass,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FragmentModePolicy {
+    Drop,
+    Pass,
+    Track,
+}
+
+impl From<FragmentModePolicy> for FragmentMode {
+    fn from(policy: FragmentModePolicy) -> Self {
+        match policy {
+            FragmentModePolicy::Drop => Self::Drop,
+            FragmentModePolicy::Pass => Self::Pass,
+            FragmentModePolicy::Track => Self::Track,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FragmentPolicy {
+    pub drop_tiny: Option<bool>,
+    pub mode: Option<FragmentModePolicy>,
+}
+
+#[derive(Deserialize)]
+pub struct GeoPolicy {
+    pub allow: Option<Vec<String>>,
+    pub block: Option<Vec<String>>,
+    pub database: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Ipv4Entry {
+    Addr(String),
+    Annotated {
+        addr: String,
+        #[serde(flatten)]
+        annotation: Annotation,
+    },
+}
+
+impl Ipv4Entry {
+    pub fn addr(&self) -> &str {
+        match self {
+            Self::Addr(addr) | Self::Annotated { addr, .. } => addr,
+        }
+    }
+
+    pub fn annotation(&self) -> Annotation {
+        match self {
+            Self::Addr(_) => Annotation::default(),
+            Self::Annotated { annotation, .. } => annotation.clone(),
+        }
+    }
+}
 
 #[derive(Deserialize)]
 pub struct Ipv4ListPolicy {
-    pub ipv4: Option<Vec<String>>,
+    pub ipv4: Option<Vec<Ipv4Entry>>,
 }
 
 #[derive(Deserialize)]
@@ -19,10 +136,126 @@ pub struct RateLimitPolicy {
     pub window_size: Option<u64>,
 }
 
+#[derive(Deserialize)]
+pub struct SanityPolicy {
+    pub bad_fragment: Option<bool>,
+    pub bad_ihl: Option<bool>,
+    pub bad_version: Option<bool>,
+    pub ip_options: Option<bool>,
+    pub land: Option<bool>,
+    pub source_route: Option<bool>,
+    pub truncated: Option<bool>,
+    pub zero_ttl: Option<bool>,
+}
+
+impl SanityPolicy {
+    fn checks(&self) -> u64 {
+        [
+            (self.bad_fragment, Sanity::BadFragment),
+            (self.bad_ihl, Sanity::BadIhl),
+            (self.bad_version, Sanity::BadVersion),
+            (self.ip_options, Sanity::IpOptions),
+            (self.land, Sanity::Land),
+            (self.source_route, Sanity::SourceRoute),
+            (self.truncated, Sanity::Truncated),
+            (self.zero_ttl, Sanity::ZeroTtl),
+        ]
+        .into_iter()
+        .filter(|&(enabled, _)| enabled == Some(true))
+        .fold(0, |checks, (_, sanity)| checks | sanity.bit())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ScanDetectionPolicy {
+    pub ban: Option<String>,
+    pub threshold: Option<u64>,
+    pub track: Option<ScanTrackPolicy>,
+    pub window: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanTrackPolicy {
+    Hosts,
+    Ports,
+}
+
+impl From<ScanTrackPolicy> for ScanTrack {
+    fn from(policy: ScanTrackPolicy) -> Self {
+        match policy {
+            ScanTrackPolicy::Hosts => Self::Hosts,
+            ScanTrackPolicy::Ports => Self::Ports,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SynProtectionPolicy {
+    pub ports: Option<Vec<u16>>,
+    pub threshold: Option<u64>,
+}
+
+impl SynProtectionPolicy {
+    /// The kernel only accepts cookies issued from XDP when
+    /// `net.ipv4.tcp_syncookies` is 2; any other value resets the handshake.
+    pub fn syncookies() -> Option<u8> {
+        read_to_string(TCP_SYNCOOKIES).ok()?.trim().parse().ok()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TcpAnomalyPolicy {
+    pub bad_data_offset: Option<AnomalyActionPolicy>,
+    pub fin_scan: Option<AnomalyActionPolicy>,
+    pub null_scan: Option<AnomalyActionPolicy>,
+    pub port_zero: Option<AnomalyActionPolicy>,
+    pub syn_fin: Option<AnomalyActionPolicy>,
+    pub syn_payload: Option<AnomalyActionPolicy>,
+    pub syn_rst: Option<AnomalyActionPolicy>,
+    pub xmas_scan: Option<AnomalyActionPolicy>,
+}
+
+impl TcpAnomalyPolicy {
+    fn checks(&self, action: AnomalyActionPolicy) -> u64 {
+        [
+            (self.bad_data_offset, TcpAnomaly::BadDataOffset),
+            (self.fin_scan, TcpAnomaly::FinScan),
+            (self.null_scan, TcpAnomaly::NullScan),
+            (self.port_zero, TcpAnomaly::PortZero),
+            (self.syn_fin, TcpAnomaly::SynFin),
+            (self.syn_payload, TcpAnomaly::SynPayload),
+            (self.syn_rst, TcpAnomaly::SynRst),
+            (self.xmas_scan, TcpAnomaly::XmasScan),
+        ]
+        .into_iter()
+        .filter(|&(check, _)| check == Some(action))
+        .fold(0, |checks, (_, anomaly)| checks | anomaly.bit())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TrapPolicy {
+    pub ban: Option<String>,
+    pub ports: Option<Vec<u16>>,
+}
+
 #[derive(Deserialize)]
 pub struct Policy {
+    pub asn: Option<AsnPolicy>,
     pub blacklist: Option<Ipv4ListPolicy>,
+    pub bogon: Option<BogonPolicy>,
+    pub conntrack: Option<ConntrackPolicy>,
+    pub default_action: Option<DefaultActionPolicy>,
+    pub fragments: Option<FragmentPolicy>,
+    pub geo: Option<GeoPolicy>,
+    pub management: Option<Ipv4ListPolicy>,
     pub rate_limit: Option<RateLimitPolicy>,
+    pub sanity: Option<SanityPolicy>,
+    pub scan_detection: Option<ScanDetectionPolicy>,
+    pub syn_protection: Option<SynProtectionPolicy>,
+    pub tcp_anomalies: Option<TcpAnomalyPolicy>,
+    pub trap: Option<TrapPolicy>,
     pub whitelist: Option<Ipv4ListPolicy>,
 }
 
@@ -31,19 +264,25 @@ impl Policy {
         let policy_file = &Arg::parse().policy;
 
         match read_to_string(policy_file) {
-            Ok(ref policy) => match from_str(policy) {
-                Ok(Policy {
-                    blacklist,
-                    rate_limit,
-                    whitelist,
-                }) if blacklist.is_some() || rate_limit.is_some() || whitelist.is_some() => {
+            Ok(ref policy) => match from_str::<Policy>(policy) {
+                Ok(mut policy) if !policy.is_empty() => {
                     info!("Applying policy");
 
-                    ebpf.blacklist()?.apply(blacklist);
-                    ebpf.rate_limit_settings()?.apply(rate_limit);
-                    ebpf.whitelist()?.apply(whitelist);
+                    // Without a [management] table only the SSH peer is kept.
+                    policy
+                        .management
+                        .get_or_insert(Ipv4ListPolicy { ipv4: None });
 
-                    info!("Policy applied");
+                    let result = policy.commit(ebpf, Origin::Policy);
+
+                    Audit::record(
+                        Origin::Policy,
+                        "policy.apply",
+                        &[policy_file],
+                        &result.as_ref().map(|_| ()),
+                    );
+
+                    info!("Policy applied as generation {}", result?);
                 }
 
                 Err(e) => error!("`{policy_file}` not parsed: {e}"),
@@ -56,4 +295,710 @@ impl Policy {
 
         Ok(())
     }
+
+    pub fn clear(ebpf: &mut Ebpf, generation: u32) -> anyhow::Result<()> {
+        Asn::clear(ebpf, generation)?;
+        ebpf.blacklist_at(generation)?.clear()?;
+        ebpf.bogons_at(generation)?.clear()?;
+        ebpf.geo_db_at(generation)?.clear()?;
+        ebpf.geo_rules_at(generation)?.clear()?;
+        ebpf.settings_at(generation)?.clear()?;
+        ebpf.syn_ports_at(generation)?.clear()?;
+        ebpf.trap_ports_at(generation)?.clear()?;
+        ebpf.whitelist_at(generation)?.clear()?;
+
+        Ok(())
+    }
+
+    pub fn commit(mut self, ebpf: &mut Ebpf, origin: Origin) -> anyhow::Result<u32> {
+        let active = ebpf.generation()?.get()?;
+        let staged = active ^ 1;
+        let management = self
+            .management
+            .take()
+            .map(|management| Lockout::nets(Some(management)));
+
+        Self::clear(ebpf, staged)?;
+
+        let (result, entries) = Audit::defer(|| {
+            // Prefix databases are only replaced when the policy names one.
+            ebpf.bogons_at(active)?.copy_to(staged)?;
+            ebpf.geo_db_at(active)?.copy_to(staged)?;
+            self.stage(ebpf, staged, origin)?;
+            ebpf.blacklist_at(active)?.copy_runtime_to(staged)?;
+            ebpf.whitelist_at(active)?.copy_runtime_to(staged)?;
+
+            // Management prefixes are not generation-keyed, so they are only
+            // replaced once the rest of the policy has staged cleanly.
+            let default_action = ebpf.settings_at(staged)?.default_action();
+
+            match management {
+                Some(ref nets) => {
+                    Lockout::check(nets, default_action)?;
+                    ebpf.management()?.replace(nets)?;
+                }
+                None if default_action == DefaultAction::Drop => Lockout::default_drop(ebpf)?,
+                None => {}
+            }
+
+            anyhow::Ok(())
+        });
+
+        if let Err(e) = result {
+            Self::clear(ebpf, staged)?;
+            return Err(e);
+        }
+
+        ebpf.generation()?.set(staged)?;
+        Audit::flush(entries);
+        Self::clear(ebpf, active)?;
+
+        Ok(staged)
+    }
+
+    pub fn copy(ebpf: &mut Ebpf, from: u32, to: u32) -> anyhow::Result<()> {
+        Asn::copy(ebpf, from, to)?;
+        ebpf.blacklist_at(from)?.copy_to(to)?;
+        ebpf.bogons_at(from)?.copy_to(to)?;
+        ebpf.geo_db_at(from)?.copy_to(to)?;
+        ebpf.geo_rules_at(from)?.copy_to(to)?;
+        ebpf.settings_at(from)?.copy_to(to)?;
+        ebpf.syn_ports_at(from)?.copy_to(to)?;
+        ebpf.trap_ports_at(from)?.copy_to(to)?;
+        ebpf.whitelist_at(from)?.copy_to(to)?;
+
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.asn.is_none()
+            && self.blacklist.is_none()
+            && self.bogon.is_none()
+            && self.conntrack.is_none()
+            && self.default_action.is_none()
+            && self.fragments.is_none()
+            && self.geo.is_none()
+            && self.management.is_none()
+            && self.rate_limit.is_none()
+            && self.sanity.is_none()
+            && self.scan_detection.is_none()
+            && self.syn_protection.is_none()
+            && self.tcp_anomalies.is_none()
+            && self.trap.is_none()
+            && self.whitelist.is_none()
+    }
+
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        Ok(from_str(&read_to_string(path)?)?)
+    }
+
+    fn stage(self, ebpf: &mut Ebpf, generation: u32, origin: Origin) -> anyhow::Result<()> {
+        ebpf.blacklist_at(generation)?
+            .apply(self.blacklist, origin)?;
+
+        if let Some(action) = self.default_action {
+            ebpf.settings_at(generation)?
+                .set_default_action(action.into(), origin)?;
+        }
+
+        if let Some(RateLimitPolicy {
+            packet_limit,
+            window_size,
+        }) = self.rate_limit
+        {
+            let mut settings = ebpf.settings_at(generation)?;
+
+            if let Some(limit) = packet_limit {
+                settings.set(Setting::PacketLimit, limit, origin)?;
+            }
+
+            if let Some(size) = window_size {
+                settings.set(Setting::WindowSize, size, origin)?;
+            }
+        }
+
+        if let Some(AsnPolicy { block, database }) = self.asn {
+            let Some(database) = database else {
+                bail!("`asn.database` is required to block ASNs");
+            };
+
+            Asn::stage(
+                ebpf,
+                generation,
+                block.unwrap_or_default(),
+                database,
+                origin,
+            )?;
+        }
+
+        if let Some(BogonPolicy { enabled, list }) = self.bogon {
+            if let Some(list) = list {
+                Bogon::update(ebpf, Some(&list), generation, origin)?;
+            }
+
+            if enabled.unwrap_or(true) {
+                Bogon::enable(ebpf, generation, origin)?;
+            }
+        }
+
+        if let Some(ConntrackPolicy {
+            allow_established: Some(allow),
+        }) = self.conntrack
+        {
+            ebpf.settings_at(generation)?
+                .set(Setting::AllowEstablished, allow.into(), origin)?;
+        }
+
+        if let Some(FragmentPolicy { drop_tiny, mode }) = self.fragments {
+            let mut settings = ebpf.settings_at(generation)?;
+
+            if let Some(mode) = mode {
+                settings.set(
+                    Setting::FragmentMode,
+                    FragmentMode::from(mode) as u64,
+                    origin,
+                )?;
+            }
+
+            if let Some(drop_tiny) = drop_tiny {
+                settings.set(Setting::FragmentDropTiny, drop_tiny.into(), origin)?;
+            }
+        }
+
+        if let Some(GeoPolicy {
+            allow,
+            block,
+            database,
+        }) = self.geo
+        {
+            if let Some(database) = database {
+                Geo::update(ebpf, &database, generation, origin)?;
+            }
+
+            let allow = allow.unwrap_or_default();
+            let mut rules = ebpf.geo_rules_at(generation)?;
+
+            for (codes, rule) in [
+                (&allow, GeoRule::Allow),
+                (&block.unwrap_or_default(), GeoRule::Block),
+            ] {
+                for code in codes {
+                    rules.insert(Geo::code(code).map_err(anyhow::Error::msg)?, rule, origin)?;
+                }
+            }
+
+            if !allow.is_empty() {
+                ebpf.settings_at(generation)?
+                    .set(Setting::GeoAllowOnly, 1, origin)?;
+            }
+        }
+
+        if let Some(sanity) = self.sanity {
+            ebpf.settings_at(generation)?
+                .set(Setting::Sanity, sanity.checks(), origin)?;
+        }
+
+        if let Some(ScanDetectionPolicy {
+            ban,
+            threshold,
+            track,
+            window,
+        }) = self.scan_detection
+        {
+            let threshold = threshold.unwrap_or(DEFAULT_SCAN_THRESHOLD);
+
+            if !(2..=SCAN_BITMAP_BITS).contains(&threshold) {
+                bail!("`scan_detection.threshold` must be between 2 and {SCAN_BITMAP_BITS}");
+            }
+
+            let window = window
+                .as_deref()
+                .map_or(Ok(DEFAULT_SCAN_WINDOW), parse_duration)?;
+            let ban = ban.as_deref().map_or(Ok(Duration::ZERO), parse_duration)?;
+            let track = ScanTrack::from(track.unwrap_or(ScanTrackPolicy::Ports));
+            let mut settings = ebpf.settings_at(generation)?;
+
+            settings.set(Setting::ScanThreshold, threshold, origin)?;
+            settings.set(Setting::ScanWindow, window.as_nanos() as u64, origin)?;
+            settings.set(Setting::ScanTrack, track as u64, origin)?;
+            settings.set(Setting::ScanBanTtl, ban.as_secs(), origin)?;
+        }
+
+        if let Some(SynProtectionPolicy { ports, threshold }) = self.syn_protection {
+            if let Some(threshold) = threshold {
+                ebpf.settings_at(generation)?
+                    .set(Setting::SynThreshold, threshold, origin)?;
+
+                if threshold != 0 && SynProtectionPolicy::syncookies() != Some(SYNCOOKIES_ALWAYS) {
+                    warn!(
+                        "SYN cookies are only honoured with `sysctl -w net.ipv4.tcp_syncookies={SYNCOOKIES_ALWAYS}`"
+                    );
+                }
+            }
+
+            let mut syn_ports = ebpf.syn_ports_at(generation)?;
+
+            for port in ports.unwrap_or_default() {
+                syn_ports.insert(port, origin)?;
+            }
+        }
+
+        if let Some(tcp_anomalies) = self.tcp_anomalies {
+            let mut settings = ebpf.settings_at(generation)?;
+
+            for (setting, action) in [
+                (Setting::TcpAnomalyDrop, AnomalyActionPolicy::Drop),
+                (Setting::TcpAnomalyLog, AnomalyActionPolicy::Log),
+            ] {
+                settings.set(setting, tcp_anomalies.checks(action), origin)?;
+            }
+        }
+
+        if let Some(TrapPolicy { ban, ports }) = self.trap {
+            let ban = ban
+                .as_deref()
+                .map_or(Ok(DEFAULT_TRAP_BAN), parse_duration)?;
+
+            ebpf.settings_at(generation)?
+                .set(Setting::TrapBanTtl, ban.as_secs(), origin)?;
+
+            let mut trap_ports = ebpf.trap_ports_at(generation)?;
+
+            for port in ports.unwrap_or_default() {
+                trap_ports.insert(port, origin)?;
+            }
+        }
+
+        ebpf.whitelist_at(generation)?
+            .apply(self.whitelist, origin)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, remove_file, write},
+        net::Ipv4Addr,
+    };
+
+    use aya::Ebpf;
+    use common::{DefaultAction, FragmentMode, GeoRule, Sanity, ScanTrack, Setting, TcpAnomaly};
+    use serial_test::serial;
+    use toml::from_str;
+
+    use super::Policy;
+    use crate::{audit::Origin, ebpf::Init, geo::Geo};
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_switches_generation() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[blacklist]\nipv4 = [\"127.0.0.1\"]\n[rate_limit]\npacket_limit = 1";
+
+        ebpf.whitelist().unwrap().add(&["10.0.0.1"], Origin::Repl);
+
+        let generation = from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert_eq!(ebpf.generation().unwrap().get().unwrap(), generation);
+        assert_eq!(ebpf.blacklist().unwrap().to_string(), "127.0.0.1");
+        assert_eq!(ebpf.settings().unwrap().find(Setting::PacketLimit), Some(1));
+        assert_eq!(ebpf.whitelist().unwrap().to_string(), "10.0.0.1");
+        assert_eq!(ebpf.whitelist().unwrap().occupancy().unwrap().0, 1);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_drops_previous_policy_entries() {
+        let mut ebpf = Ebpf::detached().unwrap();
+
+        for policy in [
+            "[blacklist]\nipv4 = [\"127.0.0.1\"]",
+            "[blacklist]\nipv4 = [\"127.0.0.2\"]",
+        ] {
+            from_str::<Policy>(policy)
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .unwrap();
+        }
+
+        assert_eq!(ebpf.blacklist().unwrap().to_string(), "127.0.0.2");
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_allow_established() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[conntrack]\nallow_established = true";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert_eq!(ebpf.settings().unwrap().get(Setting::AllowEstablished), 1);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_asn_prefixes() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let path = temp_dir().join("fayawall-policy-asn.tsv");
+        let policy = format!("[asn]\nblock = [14061]\ndatabase = {:?}", path.display());
+
+        write(
+            &path,
+            "192.0.2.0\t192.0.2.255\t14061\tUS\tDIGITALOCEAN-ASN\n\
+             198.51.100.0\t198.51.100.255\t13335\tUS\tCLOUDFLARENET\n",
+        )
+        .unwrap();
+        from_str::<Policy>(&policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+        remove_file(&path).ok();
+
+        assert_eq!(
+            ebpf.asn_prefixes().unwrap().get(),
+            [("192.0.2.0/24".parse().unwrap(), 14061)]
+        );
+        assert!(
+            from_str::<Policy>("[asn]\nblock = [14061]")
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_enables_bogon_filter() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let path = temp_dir().join("fayawall-policy-bogons.txt");
+        let policy = format!("[bogon]\nlist = {:?}", path.display());
+
+        write(&path, "0.0.0.0/8\n10.0.0.0/8\n").unwrap();
+        from_str::<Policy>(&policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+        remove_file(&path).ok();
+
+        assert_eq!(ebpf.settings().unwrap().get(Setting::Bogon), 1);
+        assert_eq!(ebpf.bogons().unwrap().len(), 2);
+        assert!(ebpf.bogons().unwrap().contains(Ipv4Addr::new(10, 1, 2, 3)));
+
+        from_str::<Policy>("[bogon]\nenabled = false")
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert_eq!(ebpf.settings().unwrap().get(Setting::Bogon), 0);
+        assert_eq!(ebpf.bogons().unwrap().len(), 2);
+
+        let active = ebpf.generation().unwrap().get().unwrap();
+        let missing = temp_dir().join("fayawall-policy-bogons-missing.txt");
+
+        assert!(
+            from_str::<Policy>(&format!("[bogon]\nlist = {:?}", missing.display()))
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+        assert_eq!(ebpf.bogons().unwrap().len(), 2);
+        assert_eq!(ebpf.bogons_at(active ^ 1).unwrap().len(), 0);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_fragment_mode() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[fragments]\nmode = \"track\"\ndrop_tiny = true";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        let settings = ebpf.settings().unwrap();
+
+        assert_eq!(
+            settings.get(Setting::FragmentMode),
+            FragmentMode::Track as u64
+        );
+        assert_eq!(settings.get(Setting::FragmentDropTiny), 1);
+        assert!(from_str::<Policy>("[fragments]\nmode = \"reassemble\"").is_err());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_geo_rules() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[geo]\nblock = [\"cn\"]\nallow = [\"AU\", \"NZ\"]";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        let rules = ebpf.geo_rules().unwrap().get();
+
+        assert_eq!(
+            rules
+                .iter()
+                .map(|&(country, rule)| (Geo::name(country), rule))
+                .collect::<Vec<_>>(),
+            [
+                ("AU".to_string(), GeoRule::Allow),
+                ("CN".to_string(), GeoRule::Block),
+                ("NZ".to_string(), GeoRule::Allow),
+            ]
+        );
+        assert_eq!(ebpf.settings().unwrap().get(Setting::GeoAllowOnly), 1);
+        assert!(
+            from_str::<Policy>("[geo]\nblock = [\"CHN\"]")
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_sanity_checks() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[sanity]\nland = true\nzero_ttl = true\nip_options = false";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert_eq!(
+            ebpf.settings().unwrap().get(Setting::Sanity),
+            Sanity::Land.bit() | Sanity::ZeroTtl.bit()
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_scan_detection() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy =
+            "[scan_detection]\nthreshold = 50\nwindow = \"30s\"\ntrack = \"hosts\"\nban = \"1h\"";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        let settings = ebpf.settings().unwrap();
+
+        assert_eq!(settings.get(Setting::ScanThreshold), 50);
+        assert_eq!(settings.get(Setting::ScanWindow), 30_000_000_000);
+        assert_eq!(settings.get(Setting::ScanTrack), ScanTrack::Hosts as u64);
+        assert_eq!(settings.get(Setting::ScanBanTtl), 3600);
+        assert!(
+            from_str::<Policy>("[scan_detection]\nthreshold = 1000")
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_syn_protection() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[syn_protection]\nthreshold = 1000\nports = [443, 22]";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert_eq!(ebpf.settings().unwrap().get(Setting::SynThreshold), 1000);
+        assert_eq!(ebpf.syn_ports().unwrap().get(), [22, 443]);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_tcp_anomaly_actions() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[tcp_anomalies]\nnull_scan = \"drop\"\nxmas_scan = \"drop\"\n\
+                      syn_payload = \"log\"\nport_zero = \"pass\"";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        let settings = ebpf.settings().unwrap();
+
+        assert_eq!(
+            settings.get(Setting::TcpAnomalyDrop),
+            TcpAnomaly::NullScan.bit() | TcpAnomaly::XmasScan.bit()
+        );
+        assert_eq!(
+            settings.get(Setting::TcpAnomalyLog),
+            TcpAnomaly::SynPayload.bit()
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_sets_trap_ports() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "[trap]\nports = [3389, 23, 5900]";
+
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert_eq!(ebpf.trap_ports().unwrap().get(), [23, 3389, 5900]);
+        assert_eq!(ebpf.settings().unwrap().get(Setting::TrapBanTtl), 3600);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn default_drop_requires_management() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "default_action = \"drop\"\n[blacklist]\nipv4 = [\"127.0.0.1\"]";
+
+        assert!(
+            from_str::<Policy>(policy)
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+        assert_eq!(
+            ebpf.settings().unwrap().default_action(),
+            DefaultAction::Pass
+        );
+
+        ebpf.management()
+            .unwrap()
+            .insert("10.0.0.0/8".parse().unwrap(), 0)
+            .unwrap();
+        from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert_eq!(
+            ebpf.settings().unwrap().default_action(),
+            DefaultAction::Drop
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn reject_policy_removing_last_management_address() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let policy = "default_action = \"drop\"\n[management]\nipv4 = [\"10.0.0.0/8\"]";
+        let generation = from_str::<Policy>(policy)
+            .unwrap()
+            .commit(&mut ebpf, Origin::Policy)
+            .unwrap();
+
+        assert!(
+            from_str::<Policy>("default_action = \"drop\"\n[management]\nipv4 = []")
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+        assert_eq!(ebpf.generation().unwrap().get().unwrap(), generation);
+        assert_eq!(
+            ebpf.settings().unwrap().default_action(),
+            DefaultAction::Drop
+        );
+        assert!(
+            ebpf.management()
+                .unwrap()
+                .get(Ipv4Addr::new(10, 0, 0, 1))
+                .is_some()
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_keeps_geo_database_on_rollback() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let dir = temp_dir().join("fayawall-policy-geo");
+        let blocks = dir.join("GeoLite2-Country-Blocks-IPv4.csv");
+        let loaded = [("192.0.2.0/24".parse().unwrap(), Geo::code("AU").unwrap())];
+
+        create_dir_all(&dir).unwrap();
+        write(
+            dir.join("GeoLite2-Country-Locations-en.csv"),
+            "geoname_id,locale_code,continent_code,continent_name,country_iso_code
+             1814991,en,AS,Asia,CN
+",
+        )
+        .unwrap();
+        write(
+            &blocks,
+            "network,geoname_id
+198.51.100.0/24,1814991
+",
+        )
+        .unwrap();
+        ebpf.geo_db().unwrap().load(&loaded).unwrap();
+
+        let policy = format!(
+            "[geo]
+database = {:?}
+[scan_detection]
+threshold = 1",
+            blocks.display()
+        );
+
+        assert!(
+            from_str::<Policy>(&policy)
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+        remove_dir_all(&dir).ok();
+        assert_eq!(ebpf.geo_db().unwrap().get(), loaded);
+        assert_eq!(ebpf.geo_db_at(1).unwrap().len(), 0);
+
+        from_str::<Policy>(
+            "[geo]
+block = [\"AU\"]",
+        )
+        .unwrap()
+        .commit(&mut ebpf, Origin::Policy)
+        .unwrap();
+
+        assert_eq!(ebpf.geo_db().unwrap().get(), loaded);
+        assert_eq!(ebpf.geo_db_at(0).unwrap().len(), 0);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn commit_policy_rolls_back_on_failure() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let addrs = (0..=2048u32)
+            .map(|i| format!("\"{}\"", Ipv4Addr::from_bits(i)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let policy = format!("[whitelist]\nipv4 = [\"10.0.0.1\"]\n[blacklist]\nipv4 = [{addrs}]");
+
+        assert!(
+            from_str::<Policy>(&policy)
+                .unwrap()
+                .commit(&mut ebpf, Origin::Policy)
+                .is_err()
+        );
+        assert_eq!(ebpf.generation().unwrap().get().unwrap(), 0);
+        assert_eq!(ebpf.blacklist_at(1).unwrap().occupancy().unwrap().0, 0);
+        assert_eq!(ebpf.whitelist_at(1).unwrap().occupancy().unwrap().0, 0);
+    }
 }
@@ -0,0 +1,246 @@
+use std::{collections::BTreeMap, fmt::Write as _, net::Ipv4Addr};
+
+use aya::Ebpf;
+use common::Counter;
+use tracing::{info, warn};
+
+use crate::{
+    audit::Origin,
+    ebpf::Init,
+    maps::source_stats::TopBy,
+    pcap::{self, Writer},
+    policy::Policy,
+    sink::Sink,
+    test_run::{self, XDP_DROP},
+};
+
+const DEFAULT_N: usize = 10;
+const FAILED: &str = "FAILED";
+
+#[derive(Debug, PartialEq)]
+pub struct Replay {
+    capture: String,
+    dropped: Option<String>,
+    n: usize,
+    policy: Option<String>,
+}
+
+impl Replay {
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut capture = None;
+        let mut replay = Self {
+            capture: String::new(),
+            dropped: None,
+            n: DEFAULT_N,
+            policy: None,
+        };
+        let mut args = args.iter();
+
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--dropped" => {
+                    replay.dropped = Some(
+                        args.next()
+                            .ok_or("`--dropped` requires a value")?
+                            .to_string(),
+                    );
+                }
+                "--policy" => {
+                    replay.policy = Some(
+                        args.next()
+                            .ok_or("`--policy` requires a value")?
+                            .to_string(),
+                    );
+                }
+                "--top" => {
+                    let n = args.next().ok_or("`--top` requires a value")?;
+
+                    replay.n = n
+                        .parse()
+                        .map_err(|e| format!("Invalid number of sources `{n}`: {e}"))?;
+                }
+                path if capture.is_none() => capture = Some(path.to_string()),
+                invalid => return Err(format!("Unexpected argument `{invalid}`")),
+            }
+        }
+
+        replay.capture = capture.ok_or("A capture file is required")?;
+
+        Ok(replay)
+    }
+
+    fn report(
+        &self,
+        ebpf: &mut Ebpf,
+        verdicts: &BTreeMap<&str, u64>,
+        sources: Vec<(Ipv4Addr, u64)>,
+    ) -> anyhow::Result<String> {
+        let mut report = format!("{:<15} {:>12}\n", "VERDICT", "PACKETS");
+
+        for (verdict, packets) in verdicts {
+            writeln!(report, "{verdict:<15} {packets:>12}")?;
+        }
+
+        writeln!(report, "\n{:<15} {:>12}", "REASON", "PACKETS")?;
+
+        let counters = ebpf.counters()?;
+
+        for counter in Counter::ALL {
+            writeln!(
+                report,
+                "{:<15} {:>12}",
+                counter.name(),
+                counters.get(counter)?
+            )?;
+        }
+
+        writeln!(report, "\n{:<15} {:>12}", "SOURCE", "DROPS")?;
+
+        for (addr, drops) in sources {
+            writeln!(report, "{:<15} {drops:>12}", addr.to_string())?;
+        }
+
+        Ok(report)
+    }
+
+    pub fn run(&self) -> anyhow::Result<()> {
+        Sink::simulation().scope(|| self.simulate())
+    }
+
+    fn simulate(&self) -> anyhow::Result<()> {
+        let packets = pcap::read(&self.capture)?;
+        let mut ebpf = Ebpf::detached()?;
+        let mut dropped = self.dropped.as_ref().map(Writer::create).transpose()?;
+        let mut verdicts = BTreeMap::new();
+
+        if let Some(ref path) = self.policy {
+            Policy::load(path)?.commit(&mut ebpf, Origin::Replay)?;
+        }
+
+        for (index, packet) in packets.iter().enumerate() {
+            let action = match test_run::test_run(&ebpf, &packet.data) {
+                Ok(action) => action,
+                Err(e) => {
+                    warn!("Packet {index} could not be replayed: {e}");
+                    *verdicts.entry(FAILED).or_default() += 1;
+                    continue;
+                }
+            };
+
+            *verdicts.entry(test_run::verdict(action)).or_default() += 1;
+
+            if let Some(writer) = dropped.as_mut()
+                && action == XDP_DROP
+            {
+                writer.write(packet)?;
+            }
+        }
+
+        if let Some(writer) = dropped {
+            writer.finish()?;
+        }
+
+        let sources = ebpf
+            .source_stats()?
+            .top(self.n, TopBy::Drops)
+            .into_iter()
+            .filter(|(_, stat)| stat.drops > 0)
+            .map(|(addr, stat)| (addr, stat.drops))
+            .collect();
+
+        info!("{} packets replayed from `{}`", packets.len(), self.capture);
+        print!("{}", self.report(&mut ebpf, &verdicts, sources)?);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{remove_file, write},
+        time::Duration,
+    };
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{packet::tcp_v4, pcap::Packet};
+
+    fn packet(src: Ipv4Addr) -> Packet {
+        let data = tcp_v4(src, 22);
+
+        Packet {
+            orig_len: data.len() as u32,
+            data,
+            timestamp: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn parse_replay() {
+        let expected = Replay {
+            capture: "capture.pcap".to_string(),
+            dropped: Some("dropped.pcap".to_string()),
+            n: 5,
+            policy: Some("p.toml".to_string()),
+        };
+
+        assert_eq!(
+            Replay::parse(&[
+                "capture.pcap",
+                "--policy",
+                "p.toml",
+                "--dropped",
+                "dropped.pcap",
+                "--top",
+                "5"
+            ]),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn parse_invalid_replay() {
+        assert!(Replay::parse(&[]).is_err());
+        assert!(Replay::parse(&["a.pcap", "b.pcap"]).is_err());
+        assert!(Replay::parse(&["a.pcap", "--policy"]).is_err());
+        assert!(Replay::parse(&["a.pcap", "--top", "x"]).is_err());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn replay_writes_dropped_packets() {
+        let dir = temp_dir();
+        let capture = dir.join("fayawall-replay-test.pcap");
+        let dropped = dir.join("fayawall-replay-dropped-test.pcap");
+        let policy = dir.join("fayawall-replay-test.toml");
+        let blocked = Ipv4Addr::new(198, 51, 100, 7);
+        let mut writer = Writer::create(&capture).unwrap();
+
+        for src in [blocked, Ipv4Addr::new(198, 51, 100, 8), blocked] {
+            writer.write(&packet(src)).unwrap();
+        }
+        writer.finish().unwrap();
+        write(&policy, format!("[blacklist]\nipv4 = [\"{blocked}\"]")).unwrap();
+
+        let replay = Replay::parse(&[
+            capture.to_str().unwrap(),
+            "--policy",
+            policy.to_str().unwrap(),
+            "--dropped",
+            dropped.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        replay.run().unwrap();
+
+        let packets = pcap::read(&dropped).unwrap();
+
+        [capture, dropped, policy].iter().for_each(|path| {
+            remove_file(path).ok();
+        });
+        assert_eq!(packets, vec![packet(blocked), packet(blocked)]);
+    }
+}
@@ -0,0 +1,83 @@
+use std::{cell::RefCell, path::PathBuf, sync::LazyLock};
+
+use clap::Parser;
+
+use crate::{arg::Arg, metadata::Store};
+
+static PROCESS: LazyLock<Sink> = LazyLock::new(Sink::process);
+
+thread_local! {
+    static SCOPED: RefCell<Option<Sink>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Sink {
+    pub audit_log: Option<PathBuf>,
+    pub metadata: Store,
+    pub state_dir: Option<PathBuf>,
+}
+
+impl Sink {
+    pub fn current() -> Self {
+        SCOPED
+            .with_borrow(Clone::clone)
+            .unwrap_or_else(|| PROCESS.clone())
+    }
+
+    fn process() -> Self {
+        if cfg!(test) {
+            return Self::default();
+        }
+
+        let Arg {
+            audit_log,
+            state_dir,
+            ..
+        } = Arg::parse();
+
+        Self {
+            audit_log: Some(audit_log.into()),
+            metadata: Store::default(),
+            state_dir: Some(state_dir.into()),
+        }
+    }
+
+    pub fn scope<T>(self, f: impl FnOnce() -> T) -> T {
+        let previous = SCOPED.replace(Some(self));
+        let result = f();
+
+        SCOPED.set(previous);
+        result
+    }
+
+    pub fn simulation() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{
+        audit::Origin,
+        metadata::{Annotation, Metadata},
+    };
+
+    #[test]
+    fn simulation_metadata_is_discarded() {
+        let addr = Ipv4Addr::new(192, 0, 2, 99);
+
+        Sink::simulation().scope(|| {
+            Metadata::insert(
+                "blacklist",
+                0,
+                addr,
+                Metadata::new(Annotation::default(), Origin::Replay),
+            );
+            assert!(Metadata::get("blacklist", 0, addr).is_some());
+        });
+        assert!(Metadata::get("blacklist", 0, addr).is_none());
+    }
+}
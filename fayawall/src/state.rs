@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions, read_to_string},
+    io::{self, Write},
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+use aya::Ebpf;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    audit::{Audit, Origin},
+    ebpf::Init,
+    lockout::Lockout,
+    maps::bans,
+    metadata::Metadata,
+    sink::Sink,
+};
+
+const JOURNAL: &str = "journal.jsonl";
+const LISTS: [&str; 3] = [bans::LABEL, "blacklist", "whitelist"];
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "op")]
+pub enum Record {
+    Add {
+        list: String,
+        addr: Ipv4Addr,
+        metadata: Metadata,
+    },
+    Del {
+        list: String,
+        addr: Ipv4Addr,
+    },
+}
+
+pub struct State;
+
+impl State {
+    fn append<P: AsRef<Path>>(path: P, record: &Record) -> io::Result<()> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        writeln!(file, "{}", serde_json::to_string(record)?)
+    }
+
+    pub fn clear() {
+        let Some(journal) = Self::journal() else {
+            return;
+        };
+        let result = match fs::remove_file(&journal) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+
+        if let Err(ref e) = result {
+            error!("`{}` could not be removed: {e}", journal.display());
+        } else {
+            info!("State cleared");
+        }
+
+        Audit::record(Origin::Repl, "state.clear", &[journal.display()], &result);
+    }
+
+    pub fn compact(ebpf: &mut Ebpf) -> anyhow::Result<()> {
+        let Some(journal) = Self::journal() else {
+            return Ok(());
+        };
+        let mut records = Vec::new();
+
+        for list in LISTS {
+            let entries = match list {
+                bans::LABEL => ebpf.bans()?.entries(),
+                "blacklist" => ebpf.blacklist()?.entries(),
+                _ => ebpf.whitelist()?.entries(),
+            };
+
+            records.extend(entries.into_iter().filter_map(|(addr, metadata)| {
+                metadata
+                    .filter(|metadata| metadata.origin != Origin::Policy && !metadata.expired())
+                    .map(|metadata| Record::Add {
+                        list: list.to_string(),
+                        addr,
+                        metadata,
+                    })
+            }));
+        }
+
+        if let Err(e) = Self::write(&journal, &records) {
+            error!("`{}` could not be compacted: {e}", journal.display());
+        }
+
+        Ok(())
+    }
+
+    fn journal() -> Option<PathBuf> {
+        Sink::current().state_dir.map(|dir| dir.join(JOURNAL))
+    }
+
+    fn load<P: AsRef<Path>>(path: P) -> io::Result<HashMap<(String, Ipv4Addr), Metadata>> {
+        let journal = match read_to_string(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            result => result?,
+        };
+        let mut entries = HashMap::new();
+
+        for line in journal.lines() {
+            match serde_json::from_str::<Record>(line) {
+                Ok(Record::Add {
+                    list,
+                    addr,
+                    metadata,
+                }) => {
+                    entries.insert((list, addr), metadata);
+                }
+                Ok(Record::Del { list, addr }) => {
+                    entries.remove(&(list, addr));
+                }
+                Err(e) => warn!("Skipping journal record: {e}"),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn record(record: Record) {
+        let Some(journal) = Self::journal() else {
+            return;
+        };
+
+        if let Err(e) = Self::append(&journal, &record) {
+            error!("State could not be written to `{}`: {e}", journal.display());
+        }
+    }
+
+    pub fn replay(ebpf: &mut Ebpf) -> anyhow::Result<()> {
+        let Some(journal) = Self::journal() else {
+            return Ok(());
+        };
+        let entries = match Self::load(&journal) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("`{}` could not be read: {e}", journal.display());
+                return Ok(());
+            }
+        };
+        let mut restored = 0;
+
+        for ((list, addr), metadata) in entries {
+            if metadata.expired() {
+                continue;
+            }
+
+            let addr_str = addr.to_string();
+            let result = match list.as_str() {
+                bans::LABEL => ebpf.bans()?.restore(addr, metadata),
+                "blacklist" if Lockout::guard(ebpf, &[&addr_str])?.is_empty() => continue,
+                "blacklist" => ebpf.blacklist()?.restore(addr, metadata),
+                "whitelist" => ebpf.whitelist()?.restore(addr, metadata),
+                _ => {
+                    warn!("Skipping journal record for unknown list `{list}`");
+                    continue;
+                }
+            };
+
+            if result.is_ok() {
+                restored += 1;
+            }
+        }
+
+        Audit::record(
+            Origin::State,
+            "state.replay",
+            &[restored],
+            &Ok::<(), &str>(()),
+        );
+        info!("{restored} entries restored from state");
+
+        Self::compact(ebpf)
+    }
+
+    fn write<P: AsRef<Path>>(path: P, records: &[Record]) -> io::Result<()> {
+        let path = path.as_ref();
+        let staged = path.with_extension("tmp");
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = fs::File::create(&staged)?;
+
+        for record in records {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+
+        file.sync_all()?;
+        fs::rename(staged, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_dir_all};
+
+    use super::*;
+    use crate::metadata::Annotation;
+
+    fn add(addr: Ipv4Addr) -> Record {
+        Record::Add {
+            list: "blacklist".to_string(),
+            addr,
+            metadata: Metadata::new(Annotation::default(), Origin::Repl),
+        }
+    }
+
+    #[test]
+    fn load_journal() {
+        let dir = temp_dir().join("fayawall-state-test");
+        let path = dir.join(JOURNAL);
+        let kept = Ipv4Addr::new(192, 0, 2, 1);
+        let deleted = Ipv4Addr::new(192, 0, 2, 2);
+
+        remove_dir_all(&dir).ok();
+        State::append(&path, &add(kept)).unwrap();
+        State::append(&path, &add(deleted)).unwrap();
+        State::append(
+            &path,
+            &Record::Del {
+                list: "blacklist".to_string(),
+                addr: deleted,
+            },
+        )
+        .unwrap();
+
+        let entries = State::load(&path).unwrap();
+
+        remove_dir_all(&dir).ok();
+        assert_eq!(entries.len(), 1);
+        assert!(entries.contains_key(&("blacklist".to_string(), kept)));
+    }
+
+    #[test]
+    fn load_missing_journal() {
+        let path = temp_dir().join("fayawall-state-missing").join(JOURNAL);
+
+        assert!(State::load(path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn write_compacted_journal() {
+        let dir = temp_dir().join("fayawall-state-compact-test");
+        let path = dir.join(JOURNAL);
+        let addr = Ipv4Addr::new(192, 0, 2, 1);
+
+        remove_dir_all(&dir).ok();
+        State::append(&path, &add(Ipv4Addr::new(192, 0, 2, 2))).unwrap();
+        State::write(&path, &[add(addr)]).unwrap();
+
+        let entries = State::load(&path).unwrap();
+
+        remove_dir_all(&dir).ok();
+        assert_eq!(entries.len(), 1);
+        assert!(entries.contains_key(&("blacklist".to_string(), addr)));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    io, mem,
+    os::fd::{AsFd, AsRawFd},
+};
+
+use aya::{Ebpf, programs::Xdp};
+
+const BPF_PROG_TEST_RUN: libc::c_long = 10;
+
+pub const XDP_ABORTED: u32 = 0;
+pub const XDP_DROP: u32 = 1;
+pub const XDP_PASS: u32 = 2;
+pub const XDP_TX: u32 = 3;
+pub const XDP_REDIRECT: u32 = 4;
+
+#[repr(C)]
+#[derive(Default)]
+struct TestRunAttr {
+    prog_fd: u32,
+    retval: u32,
+    data_size_in: u32,
+    data_size_out: u32,
+    data_in: u64,
+    data_out: u64,
+    repeat: u32,
+    duration: u32,
+    ctx_size_in: u32,
+    ctx_size_out: u32,
+    ctx_in: u64,
+    ctx_out: u64,
+    flags: u32,
+    cpu: u32,
+    batch_size: u32,
+}
+
+pub fn bench(ebpf: &Ebpf, data: &[u8], repeat: u32) -> io::Result<(u32, u32)> {
+    let attr = run(ebpf, data, repeat)?;
+
+    Ok((attr.retval, attr.duration))
+}
+
+fn run(ebpf: &Ebpf, data: &[u8], repeat: u32) -> io::Result<TestRunAttr> {
+    run_into(ebpf, data, repeat, &mut [])
+}
+
+fn run_into(ebpf: &Ebpf, data: &[u8], repeat: u32, out: &mut [u8]) -> io::Result<TestRunAttr> {
+    let prog: &Xdp = ebpf
+        .program("xdp_firewall")
+        .expect("BPF program xdp_firewall not found")
+        .try_into()
+        .map_err(io::Error::other)?;
+    let prog_fd = prog.fd().map_err(io::Error::other)?;
+    let mut attr = TestRunAttr {
+        prog_fd: prog_fd.as_fd().as_raw_fd() as u32,
+        data_size_in: data.len() as u32,
+        data_in: data.as_ptr() as u64,
+        data_size_out: out.len() as u32,
+        data_out: if out.is_empty() {
+            0
+        } else {
+            out.as_mut_ptr() as u64
+        },
+        repeat,
+        ..Default::default()
+    };
+
+    if unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_PROG_TEST_RUN,
+            &mut attr as *mut TestRunAttr,
+            mem::size_of::<TestRunAttr>(),
+        )
+    } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(attr)
+}
+
+pub fn test_run(ebpf: &Ebpf, data: &[u8]) -> io::Result<u32> {
+    Ok(run(ebpf, data, 1)?.retval)
+}
+
+#[cfg(test)]
+pub fn test_run_output(ebpf: &Ebpf, data: &[u8]) -> io::Result<(u32, Vec<u8>)> {
+    let mut out = vec![0; 1500];
+    let attr = run_into(ebpf, data, 1, &mut out)?;
+
+    out.truncate(attr.data_size_out as usize);
+
+    Ok((attr.retval, out))
+}
+
+pub fn verdict(action: u32) -> &'static str {
+    match action {
+        XDP_ABORTED => "XDP_ABORTED",
+        XDP_DROP => "XDP_DROP",
+        XDP_PASS => "XDP_PASS",
+        XDP_TX => "XDP_TX",
+        XDP_REDIRECT => "XDP_REDIRECT",
+        _ => "UNKNOWN",
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fmt::Write as _,
+    io::{Write, stdin, stdout},
+    thread,
+    time::Duration,
+};
+
+use aya::Ebpf;
+use tokio::{sync::oneshot, time::interval};
+
+use crate::{ebpf::Init, maps::source_stats::TopBy};
+
+const DEFAULT_N: usize = 10;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq)]
+pub struct Top {
+    by: TopBy,
+    live: bool,
+    n: usize,
+}
+
+impl Top {
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut top = Self {
+            by: TopBy::default(),
+            live: false,
+            n: DEFAULT_N,
+        };
+        let mut args = args.iter();
+
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--by" => {
+                    top.by = args
+                        .next()
+                        .ok_or("`--by` requires a value")?
+                        .parse::<TopBy>()?;
+                }
+                "--live" => top.live = true,
+                n => {
+                    top.n = n
+                        .parse()
+                        .map_err(|e| format!("Invalid number of sources `{n}`: {e}"))?;
+                }
+            }
+        }
+
+        Ok(top)
+    }
+
+    fn render(&self, ebpf: &mut Ebpf) -> anyhow::Result<String> {
+        let mut table = format!(
+            "{:<15} {:>12} {:>14} {:>12}\n",
+            "SOURCE", "PACKETS", "BYTES", "DROPS"
+        );
+
+        for (addr, stat) in ebpf.source_stats()?.top(self.n, self.by) {
+            writeln!(
+                table,
+                "{:<15} {:>12} {:>14} {:>12}",
+                addr.to_string(),
+                stat.packets,
+                stat.bytes,
+                stat.drops
+            )?;
+        }
+
+        Ok(table)
+    }
+
+    pub async fn run<F>(&self, ebpf: &mut Ebpf, mut housekeep: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&mut Ebpf),
+    {
+        if !self.live {
+            print!("{}", self.render(ebpf)?);
+            return Ok(());
+        }
+
+        let (tx, mut rx) = oneshot::channel();
+        let mut ticks = interval(REFRESH_INTERVAL);
+
+        thread::spawn(move || {
+            stdin().read_line(&mut String::new()).ok();
+            tx.send(()).ok();
+        });
+
+        loop {
+            tokio::select! {
+                _ = &mut rx => break,
+                _ = ticks.tick() => {
+                    housekeep(ebpf);
+                    print!("\x1B[2J\x1B[H{}\nPress Enter to stop\n", self.render(ebpf)?);
+                    stdout().flush()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_no_args() {
+        let expected = Top {
+            by: TopBy::Drops,
+            live: false,
+            n: DEFAULT_N,
+        };
+
+        assert_eq!(Top::parse(&[]), Ok(expected));
+    }
+
+    #[test]
+    fn parse_all_args() {
+        let expected = Top {
+            by: TopBy::Bytes,
+            live: true,
+            n: 5,
+        };
+
+        assert_eq!(Top::parse(&["5", "--by", "bytes", "--live"]), Ok(expected));
+    }
+
+    #[test]
+    fn parse_invalid_args() {
+        assert!(Top::parse(&["--by"]).is_err());
+        assert!(Top::parse(&["--by", "invalid"]).is_err());
+        assert!(Top::parse(&["invalid"]).is_err());
+    }
+}
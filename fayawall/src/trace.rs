@@ -0,0 +1,348 @@
+use std::{
+    io, mem,
+    net::Ipv4Addr,
+    ptr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aya::{Ebpf, maps::RingBuf};
+use common::{Check, Counter, Trace as Record};
+use humantime::{format_rfc3339_seconds, parse_duration};
+use ipnet::Ipv4Net;
+use tokio::{
+    io::{Interest, unix::AsyncFd},
+    task::JoinHandle,
+};
+use tracing::{error, info};
+
+use crate::{
+    audit::{Audit, Origin},
+    ebpf::Init,
+    ipv4::Net,
+    test_run::verdict,
+};
+
+pub const TARGET: &str = "trace";
+
+const DEFAULT_DURATION: Duration = Duration::from_secs(5 * 60);
+const IPPROTO_ICMP: u32 = 1;
+const IPPROTO_TCP: u32 = 6;
+const IPPROTO_UDP: u32 = 17;
+
+pub struct Trace;
+
+impl Trace {
+    pub fn add(ebpf: &mut Ebpf, nets: &[Ipv4Net], duration: Duration) -> anyhow::Result<()> {
+        let expires_at = SystemTime::now() + duration;
+        let mut trace = ebpf.trace()?;
+
+        for &net in nets {
+            let result = trace.insert(net, Self::secs(expires_at));
+
+            Audit::record(Origin::Repl, "trace.add", &[net], &result);
+
+            if result.is_ok() {
+                info!("{net} traced until {}", format_rfc3339_seconds(expires_at));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn del(ebpf: &mut Ebpf, args: &[&str], origin: Origin) -> anyhow::Result<()> {
+        let mut trace = ebpf.trace()?;
+
+        for net in Net::parse(args).0 {
+            let result = trace.remove(net);
+
+            Audit::record(origin, "trace.del", &[net], &result);
+        }
+
+        Ok(())
+    }
+
+    fn decode(item: &[u8]) -> Result<Record, String> {
+        if item.len() < mem::size_of::<Record>() {
+            return Err(format!("Trace of {} bytes is truncated", item.len()));
+        }
+
+        Ok(unsafe { ptr::read_unaligned(item.as_ptr().cast::<Record>()) })
+    }
+
+    pub fn expire(ebpf: &mut Ebpf) -> anyhow::Result<()> {
+        let now = Self::secs(SystemTime::now());
+        let expired = ebpf
+            .trace()?
+            .entries()
+            .into_iter()
+            .filter(|&(_, expires_at)| expires_at <= now)
+            .map(|(net, _)| net.to_string())
+            .collect::<Vec<_>>();
+
+        if !expired.is_empty() {
+            let expired = expired.iter().map(String::as_str).collect::<Vec<_>>();
+
+            Self::del(ebpf, &expired, Origin::Timeout)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn format(record: &Record) -> String {
+        let protocol = match record.protocol {
+            IPPROTO_ICMP => "icmp".to_string(),
+            IPPROTO_TCP => "tcp".to_string(),
+            IPPROTO_UDP => "udp".to_string(),
+            protocol => format!("proto={protocol}"),
+        };
+        let hit = Check::try_from(record.check).ok();
+        let checks = Check::ALL
+            .into_iter()
+            .filter(|&check| check != Check::Default)
+            .map(|check| {
+                let result = if Some(check) == hit {
+                    "hit"
+                } else if record.checks & 1 << check as u32 != 0 {
+                    "miss"
+                } else {
+                    "skip"
+                };
+
+                format!("{}={result}", check.name())
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let reason = match Counter::try_from(record.counter) {
+            Ok(counter) => counter.name().to_string(),
+            Err(counter) => format!("counter={counter}"),
+        };
+
+        format!(
+            "{}:{} -> {}:{} {protocol} len={} {checks} action={} reason={}",
+            Ipv4Addr::from_bits(record.src),
+            record.sport,
+            Ipv4Addr::from_bits(record.dst),
+            record.dport,
+            record.len,
+            verdict(record.action),
+            reason
+        )
+    }
+
+    pub fn get(ebpf: &mut Ebpf) -> anyhow::Result<String> {
+        Ok(ebpf
+            .trace()?
+            .entries()
+            .into_iter()
+            .map(|(net, expires_at)| {
+                let expires_at = UNIX_EPOCH + Duration::from_secs(expires_at.into());
+
+                format!("{net} expires_at={}", format_rfc3339_seconds(expires_at))
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    pub fn listen(ebpf: &mut Ebpf) -> anyhow::Result<JoinHandle<()>> {
+        let map = ebpf.take_map("TRACES").expect("BPF map TRACES not found");
+        let ring_buf = RingBuf::try_from(map)?;
+        let mut ring_buf = unsafe { AsyncFd::register_with_interest(ring_buf, Interest::READABLE) }
+            .map_err(io::Error::from)?;
+
+        let listener = tokio::spawn(async move {
+            loop {
+                let mut guard = match ring_buf.readable_mut().await {
+                    Ok(guard) => guard,
+                    Err(e) => {
+                        error!("Trace ring buffer could not be polled: {e}");
+                        break;
+                    }
+                };
+
+                while let Some(item) = guard.get_inner_mut().next() {
+                    match Self::decode(&item) {
+                        Ok(record) => info!(target: TARGET, "{}", Self::format(&record)),
+                        Err(e) => error!("Skipping trace: {e}"),
+                    }
+                }
+
+                guard.clear_ready();
+            }
+        });
+
+        Ok(listener)
+    }
+
+    pub fn parse(args: &[&str]) -> Result<(Vec<Ipv4Net>, Duration), String> {
+        let mut duration = DEFAULT_DURATION;
+        let mut nets = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--duration" => {
+                    let value = args.next().ok_or("`--duration` requires a value")?;
+
+                    duration = parse_duration(value)
+                        .map_err(|e| format!("Invalid duration `{value}`: {e}"))?;
+                }
+                net => nets.push(net),
+            }
+        }
+
+        let nets = Net::parse(&nets).0;
+
+        if nets.is_empty() {
+            return Err("At least one address or CIDR is required".to_string());
+        }
+
+        Ok((nets, duration))
+    }
+
+    fn secs(time: SystemTime) -> u32 {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .try_into()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{
+        packet::tcp_v4,
+        test_run::{XDP_DROP, test_run},
+    };
+
+    #[test]
+    fn parse_trace() {
+        let (nets, duration) =
+            Trace::parse(&["192.0.2.1", "198.51.100.0/24", "--duration", "30s"]).unwrap();
+
+        assert_eq!(
+            nets,
+            [
+                "192.0.2.1/32".parse::<Ipv4Net>().unwrap(),
+                "198.51.100.0/24".parse().unwrap()
+            ]
+        );
+        assert_eq!(duration, Duration::from_secs(30));
+        assert_eq!(Trace::parse(&["192.0.2.1"]).unwrap().1, DEFAULT_DURATION);
+    }
+
+    #[test]
+    fn parse_invalid_trace() {
+        assert!(Trace::parse(&[]).is_err());
+        assert!(Trace::parse(&["invalid"]).is_err());
+        assert!(Trace::parse(&["192.0.2.1", "--duration"]).is_err());
+        assert!(Trace::parse(&["192.0.2.1", "--duration", "soon"]).is_err());
+    }
+
+    fn record(checks: &[Check], check: Check, counter: u32) -> Record {
+        Record {
+            src: Ipv4Addr::new(192, 0, 2, 1).to_bits(),
+            dst: Ipv4Addr::new(192, 0, 2, 254).to_bits(),
+            len: 54,
+            protocol: IPPROTO_TCP,
+            sport: 40000,
+            dport: 22,
+            checks: checks
+                .iter()
+                .fold(0, |checks, &check| checks | 1 << check as u32),
+            check: check as u32,
+            counter,
+            action: XDP_DROP,
+        }
+    }
+
+    #[test]
+    fn format_trace() {
+        let record = record(
+            &[Check::Management, Check::Whitelist, Check::Blacklist],
+            Check::Blacklist,
+            Counter::Blacklist as u32,
+        );
+        let checks = Check::ALL
+            .into_iter()
+            .filter(|&check| check != Check::Default)
+            .map(|check| {
+                let result = match check {
+                    Check::Blacklist => "hit",
+                    Check::Management | Check::Whitelist => "miss",
+                    _ => "skip",
+                };
+
+                format!("{}={result}", check.name())
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        assert_eq!(
+            Trace::format(&record),
+            format!(
+                "192.0.2.1:40000 -> 192.0.2.254:22 tcp len=54 {checks} action=XDP_DROP reason=blacklist"
+            )
+        );
+    }
+
+    #[test]
+    fn format_trace_with_unknown_counter() {
+        let record = record(&[Check::Default], Check::Default, u32::MAX);
+
+        assert!(Trace::format(&record).ends_with(&format!("reason=counter={}", u32::MAX)));
+        assert!(Trace::format(&record).contains("management=skip"));
+    }
+
+    #[test]
+    fn decode_trace() {
+        let record = record(&[Check::Management], Check::Management, 0);
+        let item = unsafe {
+            slice::from_raw_parts(
+                (&record as *const Record).cast::<u8>(),
+                mem::size_of::<Record>(),
+            )
+        };
+
+        assert_eq!(Trace::decode(item).unwrap().checks, record.checks);
+        assert!(Trace::decode(&item[..8]).is_err());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn expire_trace() {
+        let mut ebpf = Ebpf::detached().unwrap();
+        let mut traces = RingBuf::try_from(ebpf.take_map("TRACES").unwrap()).unwrap();
+        let src = Ipv4Addr::new(192, 0, 2, 1);
+
+        Trace::add(
+            &mut ebpf,
+            &["192.0.2.0/24".parse().unwrap()],
+            Duration::ZERO,
+        )
+        .unwrap();
+        Trace::add(
+            &mut ebpf,
+            &["198.51.100.1/32".parse().unwrap()],
+            DEFAULT_DURATION,
+        )
+        .unwrap();
+        test_run(&ebpf, &tcp_v4(src, 22)).unwrap();
+
+        let record = Trace::decode(&traces.next().unwrap()).unwrap();
+
+        assert_eq!(Ipv4Addr::from_bits(record.src), src);
+        assert_eq!(Check::try_from(record.check), Ok(Check::Default));
+
+        Trace::expire(&mut ebpf).unwrap();
+        test_run(&ebpf, &tcp_v4(src, 22)).unwrap();
+
+        assert!(traces.next().is_none());
+        assert_eq!(ebpf.trace().unwrap().to_string(), "198.51.100.1/32");
+    }
+}
@@ -0,0 +1,372 @@
+use std::fs::read;
+
+use aya::{Ebpf, maps::MapType};
+use clap::Parser;
+use common::Setting;
+use tracing::{error, info, warn};
+
+use crate::{
+    arg::Arg,
+    audit::{Audit, Origin},
+    ebpf::{self, Init},
+    maps::raw::{Entry, Layout, RawMap},
+    pin::Pin,
+};
+
+// Maps that only cache datapath state and may start empty when their layout changes.
+const EPHEMERAL: &[&str] = &[
+    "ASN_EVENTS",
+    "BAN_RATE",
+    "CONNTRACK",
+    "COUNTERS",
+    "FRAGMENTS",
+    "GEO_DROPS",
+    "RATE_LIMIT_WINDOWS",
+    "SCANS",
+    "SOURCE_STATS",
+    "SYN_WINDOW",
+    "TCP_ANOMALY_LOGS",
+];
+
+// Maps whose entries moved into SETTINGS, with the conversion of each entry.
+const FOLDED: &[(&str, Convert)] = &[
+    ("DEFAULT_ACTION", Upgrade::default_action_setting),
+    ("RATE_LIMIT_SETTINGS", Upgrade::rate_limit_setting),
+];
+
+// Every key/value layout a map has had, oldest first, with the conversion to the next one.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        map: "BLACKLIST",
+        from: (4, 4),
+        to: (8, 8),
+        convert: Upgrade::list_key,
+    },
+    Migration {
+        map: "BOGONS",
+        from: (8, 4),
+        to: (12, 4),
+        convert: Upgrade::lpm_key,
+    },
+    Migration {
+        map: "GEO",
+        from: (8, 4),
+        to: (12, 4),
+        convert: Upgrade::lpm_key,
+    },
+    Migration {
+        map: "WHITELIST",
+        from: (4, 4),
+        to: (8, 8),
+        convert: Upgrade::list_key,
+    },
+];
+
+type Convert = fn(&[u8], &[u8], u32) -> Entry;
+type Version = (Option<MapType>, u32, u32, u32);
+
+struct Migration {
+    map: &'static str,
+    from: (u32, u32),
+    to: (u32, u32),
+    convert: Convert,
+}
+
+pub struct Upgrade;
+
+impl Upgrade {
+    fn default_action_setting(key: &[u8], value: &[u8], _generation: u32) -> Entry {
+        let action = u32::from_ne_bytes(value[..4].try_into().unwrap());
+        let key = [
+            &key[..4],
+            &(Setting::DefaultAction as u32).to_ne_bytes()[..],
+        ]
+        .concat();
+
+        (key, u64::from(action).to_ne_bytes().to_vec())
+    }
+
+    fn fold(from: &Ebpf, to: &Ebpf, generation: u32) -> anyhow::Result<()> {
+        let Some(settings) = to.map("SETTINGS").and_then(RawMap::of) else {
+            return Ok(());
+        };
+
+        for &(name, convert) in FOLDED {
+            let Some(old) = from.map(name).and_then(RawMap::of) else {
+                continue;
+            };
+
+            if to.map(name).is_some() {
+                continue;
+            }
+
+            let entries = old.entries()?;
+
+            for (key, value) in &entries {
+                let (key, value) = convert(key, value, generation);
+
+                settings.insert(&key, &value)?;
+            }
+
+            info!("{} entries of {name} folded into SETTINGS", entries.len());
+        }
+
+        Ok(())
+    }
+
+    fn list_key(key: &[u8], _value: &[u8], generation: u32) -> Entry {
+        let key = [&generation.to_ne_bytes()[..], &key[..4]].concat();
+
+        (key, 0u64.to_ne_bytes().to_vec())
+    }
+
+    fn lpm_key(key: &[u8], value: &[u8], generation: u32) -> Entry {
+        let prefix_len = u32::from_ne_bytes(key[..4].try_into().unwrap()) + 32;
+        let key = [
+            &prefix_len.to_ne_bytes()[..],
+            &generation.to_be_bytes(),
+            &key[4..8],
+        ]
+        .concat();
+
+        (key, value.to_vec())
+    }
+
+    fn migrate(from: &mut Ebpf, to: &mut Ebpf) -> anyhow::Result<()> {
+        let generation = match from.map("GENERATION") {
+            Some(_) => from.generation()?.get()?,
+            None => 0,
+        };
+
+        for (name, map) in to.maps() {
+            let (Some(old), Some(new)) = (from.map(name).and_then(RawMap::of), RawMap::of(map))
+            else {
+                continue;
+            };
+            let (old_version, new_version) =
+                (Self::version(old.layout()?), Self::version(new.layout()?));
+
+            if old_version == new_version {
+                let copied = old.copy_to(&new)?;
+
+                info!("{copied} entries copied to {name}");
+                continue;
+            }
+
+            let Some(steps) = Self::steps(name, old_version, new_version) else {
+                if EPHEMERAL.contains(&name) {
+                    warn!("{name} layout changed and starts empty");
+                    continue;
+                }
+
+                anyhow::bail!(
+                    "{name} layout changed from {old_version:?} to {new_version:?} without a migration"
+                );
+            };
+            let entries = old.entries()?;
+
+            for (key, value) in &entries {
+                let (key, value) = steps
+                    .iter()
+                    .fold((key.clone(), value.clone()), |(key, value), convert| {
+                        convert(&key, &value, generation)
+                    });
+
+                new.insert(&key, &value)?;
+            }
+
+            info!("{} entries migrated to {name}", entries.len());
+        }
+
+        Self::fold(from, to, generation)
+    }
+
+    fn rate_limit_setting(key: &[u8], value: &[u8], generation: u32) -> Entry {
+        let (generation, setting) = match key {
+            [setting] => (generation, u32::from(*setting)),
+            _ => (
+                u32::from_ne_bytes(key[..4].try_into().unwrap()),
+                u32::from_ne_bytes(key[4..8].try_into().unwrap()),
+            ),
+        };
+        let setting = match setting {
+            0 => Setting::PacketLimit,
+            _ => Setting::WindowSize,
+        };
+        let key = [generation.to_ne_bytes(), (setting as u32).to_ne_bytes()].concat();
+
+        (key, value.to_vec())
+    }
+
+    pub fn run(ebpf: &mut Ebpf, path: &str) -> anyhow::Result<()> {
+        let result = Self::swap(ebpf, path);
+
+        Audit::record(
+            Origin::Repl,
+            "upgrade",
+            &[path],
+            &result.as_ref().map(|_| ()),
+        );
+
+        match result {
+            Ok(()) => info!("xdp_firewall upgraded from `{path}`"),
+            Err(ref e) => error!("xdp_firewall upgrade from `{path}` rolled back: {e}"),
+        }
+
+        result
+    }
+
+    fn steps(name: &str, from: Version, to: Version) -> Option<Vec<Convert>> {
+        let ((from_type, from_key, from_value, from_flags), (to_type, to_key, to_value, to_flags)) =
+            (from, to);
+
+        if from_type != to_type || from_flags != to_flags {
+            return None;
+        }
+
+        let mut at = (from_key, from_value);
+        let mut steps = Vec::new();
+
+        while at != (to_key, to_value) {
+            let migration = MIGRATIONS
+                .iter()
+                .find(|migration| migration.map == name && migration.from == at)?;
+
+            steps.push(migration.convert);
+            at = migration.to;
+        }
+
+        Some(steps)
+    }
+
+    fn swap(ebpf: &mut Ebpf, path: &str) -> anyhow::Result<()> {
+        let object = read(path)?;
+        let mut upgraded = ebpf::load(&object)?;
+
+        Self::migrate(ebpf, &mut upgraded)?;
+        ebpf::relink(ebpf, &mut upgraded)?;
+        *ebpf = upgraded;
+
+        let Arg { iface, pin, .. } = Arg::parse();
+
+        if pin && let Err(e) = Pin::new(&iface).maps(ebpf) {
+            error!("Upgraded maps could not be pinned: {e}");
+        }
+
+        Ok(())
+    }
+
+    fn version((map_type, key_size, value_size, _, flags): Layout) -> Version {
+        (map_type, key_size, value_size, flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{remove_file, write},
+    };
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::ebpf::Init;
+
+    #[serial]
+    #[tokio::test]
+    async fn upgrade_preserves_lists() {
+        let mut ebpf = Ebpf::init().unwrap();
+        let path = temp_dir().join("fayawall-upgrade-test.o");
+
+        write(
+            &path,
+            aya::include_bytes_aligned!(concat!(env!("OUT_DIR"), "/fayawall")),
+        )
+        .unwrap();
+        ebpf.blacklist().unwrap().add(&["127.0.0.1"], Origin::Repl);
+        Upgrade::run(&mut ebpf, path.to_str().unwrap()).unwrap();
+        remove_file(&path).ok();
+
+        assert_eq!(ebpf.blacklist().unwrap().to_string(), "127.0.0.1");
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn upgrade_rolls_back_on_invalid_object() {
+        let mut ebpf = Ebpf::init().unwrap();
+        let path = temp_dir().join("fayawall-upgrade-invalid-test.o");
+
+        write(&path, b"invalid").unwrap();
+        ebpf.blacklist().unwrap().add(&["127.0.0.1"], Origin::Repl);
+
+        assert!(Upgrade::run(&mut ebpf, path.to_str().unwrap()).is_err());
+        remove_file(&path).ok();
+        assert_eq!(ebpf.blacklist().unwrap().to_string(), "127.0.0.1");
+    }
+
+    #[test]
+    fn migrate_layout_versions() {
+        let hash = Some(MapType::Hash);
+        let steps = Upgrade::steps("BLACKLIST", (hash, 4, 4, 0), (hash, 8, 8, 0)).unwrap();
+        let (key, value) = steps[0](&[192, 0, 2, 1], &[1, 0, 0, 0], 1);
+
+        assert_eq!(steps.len(), 1);
+        assert_eq!(key, [1u32.to_ne_bytes(), [192, 0, 2, 1]].concat());
+        assert_eq!(value, 0u64.to_ne_bytes());
+        assert!(
+            Upgrade::steps("BLACKLIST", (hash, 8, 8, 0), (hash, 8, 8, 0))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(Upgrade::steps("BLACKLIST", (hash, 8, 16, 0), (hash, 8, 8, 0)).is_none());
+        assert!(Upgrade::steps("GEO_RULES", (hash, 4, 4, 0), (hash, 8, 8, 0)).is_none());
+
+        let lpm = Some(MapType::LpmTrie);
+        let steps = Upgrade::steps("GEO", (lpm, 8, 4, 1), (lpm, 12, 4, 1)).unwrap();
+        let (key, value) = steps[0](
+            &[&24u32.to_ne_bytes()[..], &[192, 0, 2, 0]].concat(),
+            &[1, 2, 3, 4],
+            1,
+        );
+
+        assert_eq!(
+            key,
+            [
+                &56u32.to_ne_bytes()[..],
+                &1u32.to_be_bytes(),
+                &[192, 0, 2, 0]
+            ]
+            .concat()
+        );
+        assert_eq!(value, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn fold_settings() {
+        let setting = |generation: u32, setting: Setting| {
+            [generation.to_ne_bytes(), (setting as u32).to_ne_bytes()].concat()
+        };
+
+        assert_eq!(
+            Upgrade::default_action_setting(&1u32.to_ne_bytes(), &1u32.to_ne_bytes(), 0),
+            (
+                setting(1, Setting::DefaultAction),
+                1u64.to_ne_bytes().to_vec()
+            )
+        );
+        assert_eq!(
+            Upgrade::rate_limit_setting(
+                &[1u32.to_ne_bytes(), 0u32.to_ne_bytes()].concat(),
+                &[7; 8],
+                0
+            )
+            .0,
+            setting(1, Setting::PacketLimit)
+        );
+        assert_eq!(
+            Upgrade::rate_limit_setting(&[1], &[7; 8], 1).0,
+            setting(1, Setting::WindowSize)
+        );
+    }
+}